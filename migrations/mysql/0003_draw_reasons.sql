@@ -0,0 +1,7 @@
+-- Breakdown of `draws` by reason (filled only when config `draw_reasons = true`).
+ALTER TABLE aggregates ADD COLUMN draws_agreement            BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN draws_repetition           BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN draws_stalemate            BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN draws_insufficient         BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN draws_fifty_moves          BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN draws_timeout_insufficient BIGINT NOT NULL DEFAULT 0;
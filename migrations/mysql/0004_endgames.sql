@@ -0,0 +1,13 @@
+-- Results by material signature at the first endgame position (config `endgames = true`).
+CREATE TABLE IF NOT EXISTS endgames (
+  month        VARCHAR(7)  NOT NULL, -- "YYYY-MM"
+  speed        VARCHAR(16) NOT NULL, -- bullet, blitz, rapid, ... (from TimeControl)
+  white_bucket INTEGER     NOT NULL, -- lower bound of bucket (e.g., 2200)
+  black_bucket INTEGER     NOT NULL,
+  signature    VARCHAR(40) NOT NULL, -- e.g., "KRPvKR"
+  games        BIGINT      NOT NULL,
+  white_wins   BIGINT      NOT NULL,
+  black_wins   BIGINT      NOT NULL,
+  draws        BIGINT      NOT NULL,
+  PRIMARY KEY (month, speed, white_bucket, black_bucket, signature)
+);
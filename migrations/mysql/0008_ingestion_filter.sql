@@ -0,0 +1,3 @@
+-- Header filter used for each ingestion (NULL = all games) and how many games it rejected.
+ALTER TABLE ingestions ADD COLUMN game_filter TEXT;
+ALTER TABLE ingestions ADD COLUMN filtered_out BIGINT DEFAULT 0;
@@ -0,0 +1,21 @@
+-- Most active players and most frequent `Opening` names per month and speed
+-- (bounded Space-Saving summaries; `games` is an upper bound, `games - error` a lower bound).
+CREATE TABLE IF NOT EXISTS top_players (
+  month   VARCHAR(7)  NOT NULL, -- "YYYY-MM"
+  speed   VARCHAR(16) NOT NULL, -- bullet, blitz, ... (from TimeControl)
+  rank_no INTEGER     NOT NULL, -- 1 = most games
+  player  VARCHAR(64) NOT NULL,
+  games   BIGINT      NOT NULL,
+  error   BIGINT      NOT NULL,
+  PRIMARY KEY (month, speed, rank_no)
+);
+
+CREATE TABLE IF NOT EXISTS top_openings (
+  month   VARCHAR(7)   NOT NULL,
+  speed   VARCHAR(16)  NOT NULL,
+  rank_no INTEGER      NOT NULL,
+  opening VARCHAR(255) NOT NULL, -- exact `Opening` header
+  games   BIGINT       NOT NULL,
+  error   BIGINT       NOT NULL,
+  PRIMARY KEY (month, speed, rank_no)
+);
@@ -0,0 +1,2 @@
+-- Games skipped by `dedup` because their Site ID was already seen.
+ALTER TABLE ingestions ADD COLUMN duplicates BIGINT DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Local (stdin) inputs saved so far, keyed by the SHA-256 of the PGN text.
+CREATE TABLE IF NOT EXISTS local_ingestions (
+  content_hash VARCHAR(64) NOT NULL PRIMARY KEY, -- hex SHA-256 of the decompressed PGN
+  file_name    TEXT,                              -- --source, if given
+  months       TEXT        NOT NULL,              -- comma-separated "YYYY-MM" the input covered
+  games        BIGINT      NOT NULL DEFAULT 0,
+  game_filter  TEXT,
+  started_at   TEXT        NOT NULL,
+  finished_at  TEXT        NOT NULL,
+  attempts     INTEGER     NOT NULL DEFAULT 1     -- times it was saved (> 1 after --force)
+);
@@ -0,0 +1,6 @@
+-- Add the game speed (from TimeControl) to the key of `aggregates`.
+-- Existing rows keep their counts under speed '*' (months ingested before the split).
+ALTER TABLE aggregates
+  ADD COLUMN speed VARCHAR(16) NOT NULL DEFAULT '*' AFTER month,
+  DROP PRIMARY KEY,
+  ADD PRIMARY KEY (month, speed, eco_group, white_bucket, black_bucket, white_title, black_title);
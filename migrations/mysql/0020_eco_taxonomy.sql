@@ -0,0 +1,10 @@
+-- Version of the ECO taxonomy (`eco_taxonomy`) each saved month / local input was built with.
+-- Rows saved before versioning used the built-in groups, version 'lta-1'
+-- (a month has rows as soon as one ingestion of it finished).
+ALTER TABLE ingestions ADD COLUMN eco_taxonomy VARCHAR(64);
+ALTER TABLE local_ingestions ADD COLUMN eco_taxonomy VARCHAR(64);
+ALTER TABLE eco_groups ADD COLUMN taxonomy VARCHAR(64);
+
+UPDATE ingestions SET eco_taxonomy = 'lta-1' WHERE finished_at IS NOT NULL;
+UPDATE local_ingestions SET eco_taxonomy = 'lta-1';
+UPDATE eco_groups SET taxonomy = 'lta-1';
@@ -0,0 +1,11 @@
+-- Rating histogram behind `rating_quantiles`: how many White/Black Elos of each
+-- value a month and speed saw. A save adds its counts here and recomputes the
+-- quantiles from the sum, so several inputs of one month combine exactly.
+-- Months saved before this table start from the next input saved for them.
+CREATE TABLE IF NOT EXISTS rating_histograms (
+  month   VARCHAR(7)  NOT NULL, -- "YYYY-MM"
+  speed   VARCHAR(16) NOT NULL, -- bullet, blitz, rapid, ... (from TimeControl)
+  elo     INTEGER     NOT NULL,
+  ratings BIGINT      NOT NULL DEFAULT 0, -- White and Black Elos equal to `elo`
+  PRIMARY KEY (month, speed, elo)
+);
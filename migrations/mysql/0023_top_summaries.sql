@@ -0,0 +1,13 @@
+-- Space-Saving summaries behind `top_players` / `top_openings`: up to
+-- `top_capacity` tracked items per month, speed and list. A save merges its
+-- summary into the stored one and re-ranks the list from the result.
+-- Months saved before this table start from the next input saved for them.
+CREATE TABLE IF NOT EXISTS top_summaries (
+  month VARCHAR(7)  NOT NULL, -- "YYYY-MM"
+  speed VARCHAR(16) NOT NULL,
+  list  VARCHAR(8)  NOT NULL, -- player | opening
+  item  VARCHAR(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL, -- exact `White`/`Black` or `Opening`
+  games BIGINT      NOT NULL, -- upper bound
+  error BIGINT      NOT NULL, -- games - error is a lower bound
+  PRIMARY KEY (month, speed, list, item)
+);
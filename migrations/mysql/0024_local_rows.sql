@@ -0,0 +1,96 @@
+-- Each local input's own rows, keyed by its `content_hash` (see `local_ingestions`).
+-- The month tables hold the sum of every input, so `--force` takes exactly
+-- these rows back out before re-adding the input, and a remote month that
+-- is re-ingested (its rows deleted) gets them added back.
+-- Inputs saved before these tables have no rows here and can't be replaced.
+CREATE TABLE IF NOT EXISTS local_aggregates (
+  content_hash VARCHAR(64) NOT NULL,
+  month        VARCHAR(7)  NOT NULL,
+  speed        VARCHAR(16) NOT NULL,
+  eco_group    VARCHAR(16) NOT NULL,
+  white_bucket INTEGER     NOT NULL,
+  black_bucket INTEGER     NOT NULL,
+  white_title  VARCHAR(8)  NOT NULL,
+  black_title  VARCHAR(8)  NOT NULL,
+  games        BIGINT NOT NULL DEFAULT 0,
+  white_wins   BIGINT NOT NULL DEFAULT 0,
+  black_wins   BIGINT NOT NULL DEFAULT 0,
+  draws        BIGINT NOT NULL DEFAULT 0,
+  unfinished   BIGINT NOT NULL DEFAULT 0,
+  draws_agreement            BIGINT NOT NULL DEFAULT 0,
+  draws_repetition           BIGINT NOT NULL DEFAULT 0,
+  draws_stalemate            BIGINT NOT NULL DEFAULT 0,
+  draws_insufficient         BIGINT NOT NULL DEFAULT 0,
+  draws_fifty_moves          BIGINT NOT NULL DEFAULT 0,
+  draws_timeout_insufficient BIGINT NOT NULL DEFAULT 0,
+  white_castle_kingside  BIGINT NOT NULL DEFAULT 0,
+  white_castle_queenside BIGINT NOT NULL DEFAULT 0,
+  white_castle_none      BIGINT NOT NULL DEFAULT 0,
+  white_castle_ply_sum   BIGINT NOT NULL DEFAULT 0,
+  white_early_queen      BIGINT NOT NULL DEFAULT 0,
+  black_castle_kingside  BIGINT NOT NULL DEFAULT 0,
+  black_castle_queenside BIGINT NOT NULL DEFAULT 0,
+  black_castle_none      BIGINT NOT NULL DEFAULT 0,
+  black_castle_ply_sum   BIGINT NOT NULL DEFAULT 0,
+  black_early_queen      BIGINT NOT NULL DEFAULT 0,
+  white_rating_gained     BIGINT NOT NULL DEFAULT 0,
+  white_rating_lost       BIGINT NOT NULL DEFAULT 0,
+  white_rating_diff_games BIGINT NOT NULL DEFAULT 0,
+  black_rating_gained     BIGINT NOT NULL DEFAULT 0,
+  black_rating_lost       BIGINT NOT NULL DEFAULT 0,
+  black_rating_diff_games BIGINT NOT NULL DEFAULT 0,
+  white_elo_games  BIGINT NOT NULL DEFAULT 0,
+  white_elo_sum    BIGINT NOT NULL DEFAULT 0,
+  white_elo_sq_sum BIGINT NOT NULL DEFAULT 0,
+  black_elo_games  BIGINT NOT NULL DEFAULT 0,
+  black_elo_sum    BIGINT NOT NULL DEFAULT 0,
+  black_elo_sq_sum BIGINT NOT NULL DEFAULT 0,
+  PRIMARY KEY (content_hash, month, speed, eco_group, white_bucket, black_bucket, white_title, black_title)
+);
+
+CREATE TABLE IF NOT EXISTS local_endgames (
+  content_hash VARCHAR(64) NOT NULL,
+  month        VARCHAR(7)  NOT NULL,
+  speed        VARCHAR(16) NOT NULL,
+  white_bucket INTEGER     NOT NULL,
+  black_bucket INTEGER     NOT NULL,
+  signature    VARCHAR(40) NOT NULL,
+  games        BIGINT      NOT NULL DEFAULT 0,
+  white_wins   BIGINT      NOT NULL DEFAULT 0,
+  black_wins   BIGINT      NOT NULL DEFAULT 0,
+  draws        BIGINT      NOT NULL DEFAULT 0,
+  unfinished   BIGINT      NOT NULL DEFAULT 0,
+  PRIMARY KEY (content_hash, month, speed, white_bucket, black_bucket, signature)
+);
+
+CREATE TABLE IF NOT EXISTS local_rating_histograms (
+  content_hash VARCHAR(64) NOT NULL,
+  month        VARCHAR(7)  NOT NULL,
+  speed        VARCHAR(16) NOT NULL,
+  elo          INTEGER     NOT NULL,
+  ratings      BIGINT      NOT NULL DEFAULT 0,
+  PRIMARY KEY (content_hash, month, speed, elo)
+);
+
+CREATE TABLE IF NOT EXISTS local_player_cohorts (
+  content_hash  VARCHAR(64) NOT NULL,
+  month         VARCHAR(7)  NOT NULL,
+  cohort        VARCHAR(9)  NOT NULL,
+  rating_bucket INTEGER     NOT NULL,
+  eco_group     VARCHAR(16) NOT NULL,
+  players       BIGINT      NOT NULL DEFAULT 0,
+  games         BIGINT      NOT NULL DEFAULT 0,
+  PRIMARY KEY (content_hash, month, cohort, rating_bucket, eco_group)
+);
+
+-- the input's own Space-Saving summaries (up to `top_capacity` items)
+CREATE TABLE IF NOT EXISTS local_top_summaries (
+  content_hash VARCHAR(64) NOT NULL,
+  month        VARCHAR(7)  NOT NULL,
+  speed        VARCHAR(16) NOT NULL,
+  list         VARCHAR(8)  NOT NULL,
+  item         VARCHAR(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL,
+  games        BIGINT      NOT NULL,
+  error        BIGINT      NOT NULL,
+  PRIMARY KEY (content_hash, month, speed, list, item)
+);
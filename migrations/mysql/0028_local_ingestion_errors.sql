@@ -0,0 +1,4 @@
+-- Why the last save of a local input failed (NULL once one succeeds), and
+-- whether it was ever saved: a 'failed' input has no rows in the month tables.
+ALTER TABLE local_ingestions ADD COLUMN error TEXT;
+ALTER TABLE local_ingestions ADD COLUMN status VARCHAR(16) NOT NULL DEFAULT 'success';
@@ -0,0 +1,11 @@
+-- Castling and early-queen counters (filled only when config `move_style = true`).
+ALTER TABLE aggregates ADD COLUMN white_castle_kingside  BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN white_castle_queenside BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN white_castle_none      BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN white_castle_ply_sum   BIGINT NOT NULL DEFAULT 0; -- sum of castling plies
+ALTER TABLE aggregates ADD COLUMN white_early_queen      BIGINT NOT NULL DEFAULT 0; -- queen moved before ply 8
+ALTER TABLE aggregates ADD COLUMN black_castle_kingside  BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_castle_queenside BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_castle_none      BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_castle_ply_sum   BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_early_queen      BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,18 @@
+-- New-vs-returning players. `player_first_seen` is the set of usernames seen
+-- so far (lowercased) with the month of their first ingested game.
+CREATE TABLE IF NOT EXISTS player_first_seen (
+  player      VARCHAR(64) NOT NULL PRIMARY KEY,
+  first_month VARCHAR(7)  NOT NULL  -- "YYYY-MM"
+);
+
+-- Players and their games per month, split by cohort ("new" = first month),
+-- mean-Elo bucket and ECO group ('*' = all groups).
+CREATE TABLE IF NOT EXISTS player_cohorts (
+  month         VARCHAR(7)  NOT NULL,
+  cohort        VARCHAR(9)  NOT NULL, -- new | returning
+  rating_bucket INTEGER     NOT NULL, -- bucket of the player's mean Elo that month (0 = unknown)
+  eco_group     VARCHAR(16) NOT NULL,
+  players       BIGINT      NOT NULL DEFAULT 0,
+  games         BIGINT      NOT NULL DEFAULT 0,
+  PRIMARY KEY (month, cohort, rating_bucket, eco_group)
+);
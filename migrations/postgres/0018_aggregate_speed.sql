@@ -0,0 +1,14 @@
+-- Add the game speed (from TimeControl) to the key of `aggregates`.
+-- Existing rows keep their counts under speed '*' (months ingested before the split).
+ALTER TABLE aggregates ADD COLUMN speed VARCHAR(16) NOT NULL DEFAULT '*';
+
+-- The key constraint is still named after the table 0007 renamed.
+DO $$
+DECLARE pk TEXT;
+BEGIN
+  SELECT conname INTO pk FROM pg_constraint WHERE conrelid = 'aggregates'::regclass AND contype = 'p';
+  EXECUTE format('ALTER TABLE aggregates DROP CONSTRAINT %I', pk);
+END $$;
+
+ALTER TABLE aggregates ADD CONSTRAINT aggregates_pkey
+  PRIMARY KEY (month, speed, eco_group, white_bucket, black_bucket, white_title, black_title);
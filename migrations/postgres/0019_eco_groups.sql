@@ -0,0 +1,78 @@
+-- ECO groups as stored in `eco_group`, with their names and ECO volume.
+-- Seeded here; every --save run then rewrites the table from `eco::ECO_RANGES`.
+CREATE TABLE IF NOT EXISTS eco_groups (
+  label     VARCHAR(16) NOT NULL PRIMARY KEY, -- e.g., B20-B99, A47, U00
+  eco_start VARCHAR(3),                       -- first ECO code (NULL for U00)
+  eco_end   VARCHAR(3),                       -- last ECO code, inclusive
+  name      VARCHAR(64) NOT NULL,             -- e.g., Sicilian defence
+  family    VARCHAR(64) NOT NULL              -- ECO volume, e.g., Semi-open games
+);
+
+INSERT INTO eco_groups (label, eco_start, eco_end, name, family) VALUES
+  ('A00', 'A00', 'A00', 'Polish (Sokolsky) opening', 'Flank openings'),
+  ('A01', 'A01', 'A01', 'Nimzovich-Larsen attack', 'Flank openings'),
+  ('A02-A03', 'A02', 'A03', 'Bird''s opening', 'Flank openings'),
+  ('A04-A09', 'A04', 'A09', 'Reti opening', 'Flank openings'),
+  ('A10-A39', 'A10', 'A39', 'English opening', 'Flank openings'),
+  ('A40-A41', 'A40', 'A41', 'Queen''s pawn', 'Flank openings'),
+  ('A42', 'A42', 'A42', 'Modern defence (Averbakh)', 'Flank openings'),
+  ('A43-A44', 'A43', 'A44', 'Old Benoni defence', 'Flank openings'),
+  ('A45-A46', 'A45', 'A46', 'Queen''s pawn game', 'Flank openings'),
+  ('A47', 'A47', 'A47', 'Queen''s Indian defence', 'Flank openings'),
+  ('A48-A49', 'A48', 'A49', 'East Indian / King''s Indian development', 'Flank openings'),
+  ('A50', 'A50', 'A50', 'Queen''s pawn game (1.d4 Nf6 2.c4)', 'Flank openings'),
+  ('A51-A52', 'A51', 'A52', 'Budapest defence', 'Flank openings'),
+  ('A53-A55', 'A53', 'A55', 'Old Indian defence', 'Flank openings'),
+  ('A56', 'A56', 'A56', 'Benoni defence', 'Flank openings'),
+  ('A57-A59', 'A57', 'A59', 'Benko gambit', 'Flank openings'),
+  ('A60-A79', 'A60', 'A79', 'Modern Benoni', 'Flank openings'),
+  ('A80-A99', 'A80', 'A99', 'Dutch defence', 'Flank openings'),
+  ('B00', 'B00', 'B00', 'King''s pawn opening', 'Semi-open games'),
+  ('B01', 'B01', 'B01', 'Scandinavian defence', 'Semi-open games'),
+  ('B02-B05', 'B02', 'B05', 'Alekhine''s defence', 'Semi-open games'),
+  ('B06', 'B06', 'B06', 'Modern defence (Robatsch)', 'Semi-open games'),
+  ('B07-B09', 'B07', 'B09', 'Pirc defence', 'Semi-open games'),
+  ('B10-B19', 'B10', 'B19', 'Caro-Kann defence', 'Semi-open games'),
+  ('B20-B99', 'B20', 'B99', 'Sicilian defence', 'Semi-open games'),
+  ('C00-C19', 'C00', 'C19', 'French defence', 'Open games and the French defence'),
+  ('C20', 'C20', 'C20', 'King''s pawn game', 'Open games and the French defence'),
+  ('C21-C22', 'C21', 'C22', 'Centre game', 'Open games and the French defence'),
+  ('C23-C24', 'C23', 'C24', 'Bishop''s opening', 'Open games and the French defence'),
+  ('C25-C29', 'C25', 'C29', 'Vienna game', 'Open games and the French defence'),
+  ('C30-C39', 'C30', 'C39', 'King''s gambit', 'Open games and the French defence'),
+  ('C40', 'C40', 'C40', 'King''s knight opening', 'Open games and the French defence'),
+  ('C41', 'C41', 'C41', 'Philidor defence', 'Open games and the French defence'),
+  ('C42-C43', 'C42', 'C43', 'Petrov''s defence', 'Open games and the French defence'),
+  ('C44', 'C44', 'C44', 'King''s pawn game (misc.)', 'Open games and the French defence'),
+  ('C45', 'C45', 'C45', 'Scotch game', 'Open games and the French defence'),
+  ('C46', 'C46', 'C46', 'Three knights game', 'Open games and the French defence'),
+  ('C47-C49', 'C47', 'C49', 'Four knights / Scotch variation', 'Open games and the French defence'),
+  ('C50', 'C50', 'C50', 'Italian game', 'Open games and the French defence'),
+  ('C51-C52', 'C51', 'C52', 'Evans gambit', 'Open games and the French defence'),
+  ('C53-C54', 'C53', 'C54', 'Giuoco Piano', 'Open games and the French defence'),
+  ('C55-C59', 'C55', 'C59', 'Two knights defence', 'Open games and the French defence'),
+  ('C60-C99', 'C60', 'C99', 'Ruy Lopez', 'Open games and the French defence'),
+  ('D00', 'D00', 'D00', 'Queen''s pawn game', 'Closed and semi-closed games'),
+  ('D01', 'D01', 'D01', 'Richter–Veresov', 'Closed and semi-closed games'),
+  ('D02', 'D02', 'D02', 'Queen''s pawn game', 'Closed and semi-closed games'),
+  ('D03', 'D03', 'D03', 'Torre attack', 'Closed and semi-closed games'),
+  ('D04-D05', 'D04', 'D05', 'Queen''s pawn game (e3)', 'Closed and semi-closed games'),
+  ('D06', 'D06', 'D06', 'Queen''s gambit', 'Closed and semi-closed games'),
+  ('D07-D09', 'D07', 'D09', 'Queen''s gambit declined, Chigorin', 'Closed and semi-closed games'),
+  ('D10-D15', 'D10', 'D15', 'Slav defence', 'Closed and semi-closed games'),
+  ('D16', 'D16', 'D16', 'Slav accepted (Alapin)', 'Closed and semi-closed games'),
+  ('D17-D19', 'D17', 'D19', 'Slav, Czech defence', 'Closed and semi-closed games'),
+  ('D20-D29', 'D20', 'D29', 'Queen''s gambit accepted', 'Closed and semi-closed games'),
+  ('D30-D42', 'D30', 'D42', 'Queen''s gambit declined', 'Closed and semi-closed games'),
+  ('D43-D49', 'D43', 'D49', 'Queen''s gambit declined, Semi-Slav', 'Closed and semi-closed games'),
+  ('D50-D69', 'D50', 'D69', 'Queen''s gambit declined, 4.Bg5', 'Closed and semi-closed games'),
+  ('D70-D79', 'D70', 'D79', 'Neo-Grünfeld defence', 'Closed and semi-closed games'),
+  ('D80-D99', 'D80', 'D99', 'Grünfeld defence', 'Closed and semi-closed games'),
+  ('E00', 'E00', 'E00', 'Queen''s pawn game', 'Indian defences'),
+  ('E01-E09', 'E01', 'E09', 'Catalan (closed)', 'Indian defences'),
+  ('E10', 'E10', 'E10', 'Queen''s pawn game', 'Indian defences'),
+  ('E11', 'E11', 'E11', 'Bogo-Indian defence', 'Indian defences'),
+  ('E12-E19', 'E12', 'E19', 'Queen''s Indian defence', 'Indian defences'),
+  ('E20-E59', 'E20', 'E59', 'Nimzo-Indian defence', 'Indian defences'),
+  ('E60-E99', 'E60', 'E99', 'King''s Indian defence', 'Indian defences'),
+  ('U00', NULL, NULL, 'Unknown', 'Unknown');
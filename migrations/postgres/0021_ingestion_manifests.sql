@@ -0,0 +1,5 @@
+-- Run manifest (TOML, see src/manifest.rs) of each saved month / local input:
+-- tool version, config hash, source hash and the settings that must match
+-- across the database. NULL for rows saved before manifests.
+ALTER TABLE ingestions ADD COLUMN manifest TEXT;
+ALTER TABLE local_ingestions ADD COLUMN manifest TEXT;
@@ -0,0 +1,4 @@
+-- Drawn games whose moves (or FEN) couldn't be replayed, so that with
+-- `draw_reasons` the draws_* columns add up to `draws`.
+ALTER TABLE aggregates       ADD COLUMN draws_unknown BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE local_aggregates ADD COLUMN draws_unknown BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Games without a BOT skipped by `bot_games = "isolate"`; `bot_excluded` only
+-- counts the BOT games skipped by `bot_games = "exclude"`.
+ALTER TABLE ingestions ADD COLUMN human_excluded BIGINT DEFAULT 0;
@@ -0,0 +1,7 @@
+-- Rating points gained/lost per side (from WhiteRatingDiff / BlackRatingDiff).
+ALTER TABLE aggregates ADD COLUMN white_rating_gained     BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN white_rating_lost       BIGINT NOT NULL DEFAULT 0; -- positive sum
+ALTER TABLE aggregates ADD COLUMN white_rating_diff_games BIGINT NOT NULL DEFAULT 0; -- games with the header
+ALTER TABLE aggregates ADD COLUMN black_rating_gained     BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_rating_lost       BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_rating_diff_games BIGINT NOT NULL DEFAULT 0;
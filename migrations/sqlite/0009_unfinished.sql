@@ -0,0 +1,4 @@
+-- Games without a final result ("*", ...), so games = white_wins + black_wins + draws + unfinished
+-- (or games = white_wins + black_wins + draws with config `exclude_unfinished = true`).
+ALTER TABLE aggregates ADD COLUMN unfinished BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE endgames   ADD COLUMN unfinished BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- Exact Elo mean/spread per cell: mean = sum / games, variance = sq_sum / games - mean^2.
+ALTER TABLE aggregates ADD COLUMN white_elo_games  BIGINT NOT NULL DEFAULT 0; -- games with a numeric WhiteElo
+ALTER TABLE aggregates ADD COLUMN white_elo_sum    BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN white_elo_sq_sum BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_elo_games  BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_elo_sum    BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE aggregates ADD COLUMN black_elo_sq_sum BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- Rating distribution per month and speed (every White and Black Elo counts once).
+CREATE TABLE IF NOT EXISTS rating_quantiles (
+  month   VARCHAR(7)  NOT NULL, -- "YYYY-MM"
+  speed   VARCHAR(16) NOT NULL, -- bullet, blitz, rapid, ... (from TimeControl)
+  ratings BIGINT      NOT NULL, -- number of ratings seen
+  p10     INTEGER     NOT NULL,
+  p25     INTEGER     NOT NULL,
+  p50     INTEGER     NOT NULL,
+  p75     INTEGER     NOT NULL,
+  p90     INTEGER     NOT NULL,
+  PRIMARY KEY (month, speed)
+);
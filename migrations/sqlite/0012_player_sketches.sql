@@ -0,0 +1,11 @@
+-- HyperLogLog sketches of distinct players (White/Black usernames).
+-- eco_group = '*' and speed = '*' hold the whole month; registers union by max.
+CREATE TABLE IF NOT EXISTS player_sketches (
+  month            VARCHAR(7)  NOT NULL, -- "YYYY-MM"
+  eco_group        VARCHAR(16) NOT NULL, -- e.g., B20-B99, or '*'
+  speed            VARCHAR(16) NOT NULL, -- bullet, blitz, ..., or '*'
+  hll_precision    INTEGER     NOT NULL, -- p: 2^p registers
+  registers        TEXT        NOT NULL, -- hex, one byte per register
+  players_estimate BIGINT      NOT NULL, -- estimated distinct players
+  PRIMARY KEY (month, eco_group, speed)
+);
@@ -0,0 +1,3 @@
+-- Why the last attempt failed (NULL once it succeeds) and how many attempts were made.
+ALTER TABLE ingestions ADD COLUMN error TEXT;
+ALTER TABLE ingestions ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
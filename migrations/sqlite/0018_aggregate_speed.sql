@@ -0,0 +1,72 @@
+-- Add the game speed (from TimeControl) to the key of `aggregates`.
+-- The primary key changes, so the table is rebuilt; existing rows keep their
+-- counts under speed '*' (months ingested before the split).
+CREATE TABLE aggregates_new (
+  month        VARCHAR(7)  NOT NULL,
+  speed        VARCHAR(16) NOT NULL DEFAULT '*', -- bullet, blitz, ...; '*' = ingested before this split
+  eco_group    VARCHAR(16) NOT NULL,
+  white_bucket INTEGER     NOT NULL,
+  black_bucket INTEGER     NOT NULL,
+  white_title  VARCHAR(8)  NOT NULL DEFAULT 'none', -- none | titled | bot
+  black_title  VARCHAR(8)  NOT NULL DEFAULT 'none',
+  games        BIGINT      NOT NULL,
+  white_wins   BIGINT      NOT NULL,
+  black_wins   BIGINT      NOT NULL,
+  draws        BIGINT      NOT NULL,
+  draws_agreement            BIGINT NOT NULL DEFAULT 0,
+  draws_repetition           BIGINT NOT NULL DEFAULT 0,
+  draws_stalemate            BIGINT NOT NULL DEFAULT 0,
+  draws_insufficient         BIGINT NOT NULL DEFAULT 0,
+  draws_fifty_moves          BIGINT NOT NULL DEFAULT 0,
+  draws_timeout_insufficient BIGINT NOT NULL DEFAULT 0,
+  white_castle_kingside  BIGINT NOT NULL DEFAULT 0,
+  white_castle_queenside BIGINT NOT NULL DEFAULT 0,
+  white_castle_none      BIGINT NOT NULL DEFAULT 0,
+  white_castle_ply_sum   BIGINT NOT NULL DEFAULT 0,
+  white_early_queen      BIGINT NOT NULL DEFAULT 0,
+  black_castle_kingside  BIGINT NOT NULL DEFAULT 0,
+  black_castle_queenside BIGINT NOT NULL DEFAULT 0,
+  black_castle_none      BIGINT NOT NULL DEFAULT 0,
+  black_castle_ply_sum   BIGINT NOT NULL DEFAULT 0,
+  black_early_queen      BIGINT NOT NULL DEFAULT 0,
+  white_rating_gained     BIGINT NOT NULL DEFAULT 0,
+  white_rating_lost       BIGINT NOT NULL DEFAULT 0,
+  white_rating_diff_games BIGINT NOT NULL DEFAULT 0,
+  black_rating_gained     BIGINT NOT NULL DEFAULT 0,
+  black_rating_lost       BIGINT NOT NULL DEFAULT 0,
+  black_rating_diff_games BIGINT NOT NULL DEFAULT 0,
+  unfinished       BIGINT NOT NULL DEFAULT 0,
+  white_elo_games  BIGINT NOT NULL DEFAULT 0,
+  white_elo_sum    BIGINT NOT NULL DEFAULT 0,
+  white_elo_sq_sum BIGINT NOT NULL DEFAULT 0,
+  black_elo_games  BIGINT NOT NULL DEFAULT 0,
+  black_elo_sum    BIGINT NOT NULL DEFAULT 0,
+  black_elo_sq_sum BIGINT NOT NULL DEFAULT 0,
+  PRIMARY KEY (month, speed, eco_group, white_bucket, black_bucket, white_title, black_title)
+);
+
+INSERT INTO aggregates_new (
+  month, eco_group, white_bucket, black_bucket, white_title, black_title,
+  games, white_wins, black_wins, draws,
+  draws_agreement, draws_repetition, draws_stalemate, draws_insufficient, draws_fifty_moves, draws_timeout_insufficient,
+  white_castle_kingside, white_castle_queenside, white_castle_none, white_castle_ply_sum, white_early_queen,
+  black_castle_kingside, black_castle_queenside, black_castle_none, black_castle_ply_sum, black_early_queen,
+  white_rating_gained, white_rating_lost, white_rating_diff_games,
+  black_rating_gained, black_rating_lost, black_rating_diff_games,
+  unfinished,
+  white_elo_games, white_elo_sum, white_elo_sq_sum, black_elo_games, black_elo_sum, black_elo_sq_sum
+)
+SELECT
+  month, eco_group, white_bucket, black_bucket, white_title, black_title,
+  games, white_wins, black_wins, draws,
+  draws_agreement, draws_repetition, draws_stalemate, draws_insufficient, draws_fifty_moves, draws_timeout_insufficient,
+  white_castle_kingside, white_castle_queenside, white_castle_none, white_castle_ply_sum, white_early_queen,
+  black_castle_kingside, black_castle_queenside, black_castle_none, black_castle_ply_sum, black_early_queen,
+  white_rating_gained, white_rating_lost, white_rating_diff_games,
+  black_rating_gained, black_rating_lost, black_rating_diff_games,
+  unfinished,
+  white_elo_games, white_elo_sum, white_elo_sq_sum, black_elo_games, black_elo_sum, black_elo_sq_sum
+FROM aggregates;
+
+DROP TABLE aggregates;
+ALTER TABLE aggregates_new RENAME TO aggregates;
@@ -0,0 +1,43 @@
+-- Each player's games per month (lowercased username), and per ECO group.
+-- Saves add to them, so a player spread over several inputs of a month is
+-- one player; `player_cohorts` is recomputed from them for every month a
+-- save touches.
+CREATE TABLE IF NOT EXISTS player_months (
+  month     VARCHAR(7)  NOT NULL,
+  player    VARCHAR(64) NOT NULL,
+  games     BIGINT      NOT NULL DEFAULT 0,
+  elo_sum   BIGINT      NOT NULL DEFAULT 0, -- of the player's numeric Elos that month
+  elo_games BIGINT      NOT NULL DEFAULT 0,
+  PRIMARY KEY (month, player)
+);
+
+CREATE TABLE IF NOT EXISTS player_month_ecos (
+  month     VARCHAR(7)  NOT NULL,
+  player    VARCHAR(64) NOT NULL,
+  eco_group VARCHAR(16) NOT NULL,
+  games     BIGINT      NOT NULL DEFAULT 0,
+  PRIMARY KEY (month, player, eco_group)
+);
+
+-- each local input's own player months, so `--force` can take them out again
+CREATE TABLE IF NOT EXISTS local_player_months (
+  content_hash VARCHAR(64) NOT NULL,
+  month        VARCHAR(7)  NOT NULL,
+  player       VARCHAR(64) NOT NULL,
+  games        BIGINT      NOT NULL DEFAULT 0,
+  elo_sum      BIGINT      NOT NULL DEFAULT 0,
+  elo_games    BIGINT      NOT NULL DEFAULT 0,
+  PRIMARY KEY (content_hash, month, player)
+);
+
+CREATE TABLE IF NOT EXISTS local_player_month_ecos (
+  content_hash VARCHAR(64) NOT NULL,
+  month        VARCHAR(7)  NOT NULL,
+  player       VARCHAR(64) NOT NULL,
+  eco_group    VARCHAR(16) NOT NULL,
+  games        BIGINT      NOT NULL DEFAULT 0,
+  PRIMARY KEY (content_hash, month, player, eco_group)
+);
+
+-- replaced by local_player_months (cohort rows are derived, not added up)
+DROP TABLE IF EXISTS local_player_cohorts;
@@ -1,47 +1,145 @@
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufRead, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use rayon::prelude::*;
 
-use crate::config::Config;
-use crate::model::{Counter, Key};
+use crate::config::{BotPolicy, Config};
+use crate::model::{
+    Counter, EndgameKey, Key, MonthSpeed, Outcomes, PlayerCell, COUNTER_COLUMNS, RATING_QUANTILES,
+};
 use crate::pgn::{
-    elo_bucket_with_size, is_game_start, month_from_headers, eco_group_from_headers, parse_elo,
-    parse_headers, result_from_headers,
+    is_game_start, month_from_headers, eco_group_from_headers, parse_elo,
+    move_style, movetext_sans, parse_headers, parse_rating_diff, result_from_headers, speed_from_headers, title_class,
 };
+use crate::cohort::{self, CohortPlayers};
+use crate::dedup::{game_key, site_of};
+use crate::eco;
+use crate::openings;
+use crate::replay;
+use crate::sketch::{stable_hash64, EloHistogram, Hll, TopK};
 
 pub type AggMap = HashMap<Key, Counter>;
+pub type EndgameMap = HashMap<EndgameKey, Outcomes>;
+pub type RatingMap = HashMap<MonthSpeed, EloHistogram>;
+pub type PlayerMap = HashMap<PlayerCell, Hll>;
+pub type TopMap = HashMap<MonthSpeed, TopK>;
+
+/// Everything one pass over a PGN stream produces.
+#[derive(Default)]
+pub struct Aggregation {
+    pub map: AggMap,
+    pub endgames: EndgameMap, // empty unless `endgames` is enabled
+    pub ratings: RatingMap,   // every White/Black Elo, per month and speed (`rating_quantiles`)
+    pub players: PlayerMap,   // empty unless `distinct_players` is enabled
+    pub top_players: TopMap,  // empty unless `heavy_hitters` is enabled
+    pub top_openings: TopMap,
+    pub cohort_players: CohortPlayers, // empty unless `cohorts` is enabled
+    pub bot_excluded: u64,    // BOT games skipped by `bot_games = "exclude"`
+    pub human_excluded: u64,  // non-BOT games skipped by `bot_games = "isolate"`
+    pub filtered_out: u64,    // games rejected by the header filter
+    pub duplicates: u64,      // games skipped by `dedup` (Site ID already seen)
+    pub sampled_out: u64,     // games left out by `--sample-rate`
+    pub truncated: bool,      // `--max-games` / `--max-bytes` stopped the pass early
+}
 
-/// Aggregate from any buffered reader of PGN text using config (batch size, bucket size).
-pub fn aggregate_from_reader<R: BufRead>(mut reader: R, cfg: &Config) -> io::Result<(AggMap, usize)> {
-    let mut global_map: AggMap = HashMap::new();
+impl Aggregation {
+    fn merge(&mut self, other: Aggregation) {
+        merge_maps(&mut self.map, other.map);
+        for (k, c) in other.endgames {
+            self.endgames.entry(k).or_default().merge(&c);
+        }
+        for (k, hist) in other.ratings {
+            self.ratings.entry(k).or_default().merge(&hist);
+        }
+        for (k, hll) in other.players {
+            match self.players.get_mut(&k) {
+                Some(mine) => mine.merge(&hll),
+                None => { self.players.insert(k, hll); }
+            }
+        }
+        for (map, other_map) in [(&mut self.top_players, other.top_players), (&mut self.top_openings, other.top_openings)] {
+            for (k, top) in other_map {
+                match map.get_mut(&k) {
+                    Some(mine) => mine.merge(&top),
+                    None => { map.insert(k, top); }
+                }
+            }
+        }
+        self.cohort_players.merge(other.cohort_players);
+        self.bot_excluded += other.bot_excluded;
+        self.human_excluded += other.human_excluded;
+        self.filtered_out += other.filtered_out;
+        self.duplicates += other.duplicates;
+        self.sampled_out += other.sampled_out;
+        self.truncated |= other.truncated;
+    }
+}
+
+/// Aggregate from any buffered reader of PGN text using config (batch size, buckets).
+pub fn aggregate_from_reader<R: BufRead>(mut reader: R, cfg: &Config) -> io::Result<(Aggregation, usize)> {
+    let mut global_map = Aggregation::default();
     let mut current_game: Vec<String> = Vec::with_capacity(512);
     let mut batch: Vec<Vec<String>> = Vec::with_capacity(cfg.batch_size);
     let mut total_games = 0usize;
+    let mut bytes = 0u64;
+    // sampling and dedup run here, in input order, so the first copy of a game is the one kept
+    let mut seen = cfg.seen_games.as_ref().map(|s| s.lock().unwrap());
+    let mut admit = |game: &[String], agg: &mut Aggregation| -> bool {
+        if let Some(rate) = cfg.preview.sample_rate {
+            if !in_sample(game, rate) {
+                agg.sampled_out += 1;
+                return false;
+            }
+        }
+        if let (Some(seen), Some(key)) = (seen.as_mut(), site_of(game).and_then(game_key)) {
+            if seen.check_and_insert(key) {
+                agg.duplicates += 1;
+                return false;
+            }
+        }
+        true
+    };
 
     let mut line = String::new();
     loop {
         line.clear();
         let n = reader.read_line(&mut line)?;
         if n == 0 { break; }
+        bytes += n as u64;
+        if cfg.preview.max_bytes.is_some_and(|max| bytes > max) {
+            global_map.truncated = true;
+            // partial game, unless the line over the limit starts the next one
+            if !is_game_start(&line) { current_game.clear(); }
+            break;
+        }
         if line.ends_with('\n') { line.pop(); if line.ends_with('\r') { line.pop(); } }
 
         if is_game_start(&line) && !current_game.is_empty() {
-            batch.push(std::mem::take(&mut current_game));
+            let game = std::mem::take(&mut current_game);
             total_games += 1;
+            if admit(&game, &mut global_map) {
+                batch.push(game);
+            }
             if batch.len() >= cfg.batch_size {
                 process_batch_parallel(&batch, &mut global_map, cfg);
                 batch.clear();
+                global_map.cohort_players.spill_if_large(cohort::SPILL_AT)?;
+            }
+            if cfg.preview.max_games.is_some_and(|max| total_games as u64 >= max) {
+                global_map.truncated = true;
+                break;
             }
         }
         current_game.push(line.clone());
     }
 
     if !current_game.is_empty() {
-        batch.push(current_game);
         total_games += 1;
+        if admit(&current_game, &mut global_map) {
+            batch.push(current_game);
+        }
     }
     if !batch.is_empty() {
         process_batch_parallel(&batch, &mut global_map, cfg);
@@ -50,75 +148,503 @@ pub fn aggregate_from_reader<R: BufRead>(mut reader: R, cfg: &Config) -> io::Res
     Ok((global_map, total_games))
 }
 
-fn process_batch_parallel(batch: &[Vec<String>], global: &mut AggMap, cfg: &Config) {
-    let batch_map: AggMap = batch
+/// Deterministic sample membership: hash of the game ID (of the header block
+/// when there is no `Site`), so reruns keep the same games.
+fn in_sample(game: &[String], rate: f64) -> bool {
+    let h = match site_of(game).and_then(game_key) {
+        Some(key) => stable_hash64(&key.to_le_bytes()),
+        None => {
+            let headers: Vec<u8> = game.iter().take_while(|l| l.starts_with('[')).flat_map(|l| l.bytes()).collect();
+            stable_hash64(&headers)
+        }
+    };
+    (h as f64) < rate * u64::MAX as f64
+}
+
+fn process_batch_parallel(batch: &[Vec<String>], global: &mut Aggregation, cfg: &Config) {
+    let batch_map: Aggregation = batch
         .par_iter()
         .fold(
-            || AggMap::new(),
+            Aggregation::default,
             |mut acc, game_lines| { process_game_into_map(game_lines, &mut acc, cfg); acc },
         )
         .reduce(
-            || AggMap::new(),
-            |mut a, b| { merge_maps(&mut a, b); a },
+            Aggregation::default,
+            |mut a, b| { a.merge(b); a },
         );
-    merge_maps(global, batch_map);
+    global.merge(batch_map);
 }
 
-fn process_game_into_map(game_lines: &[String], map: &mut AggMap, cfg: &Config) {
+fn process_game_into_map(game_lines: &[String], agg: &mut Aggregation, cfg: &Config) {
     if game_lines.is_empty() { return; }
     let h = parse_headers(game_lines);
 
+    if let Some(filter) = cfg.compiled_filter.as_ref() {
+        if !filter.matches(&h) {
+            agg.filtered_out += 1;
+            return;
+        }
+    }
+
+    let w_title = title_class(h.get("WhiteTitle"));
+    let b_title = title_class(h.get("BlackTitle"));
+    let has_bot = w_title == "bot" || b_title == "bot";
+    match cfg.bot_games {
+        BotPolicy::Include => {}
+        BotPolicy::Exclude if has_bot => {
+            agg.bot_excluded += 1;
+            return;
+        }
+        BotPolicy::Isolate if !has_bot => {
+            agg.human_excluded += 1;
+            return;
+        }
+        BotPolicy::Exclude | BotPolicy::Isolate => {}
+    }
+
     let month = month_from_headers(&h);
-    let eco_group = eco_group_from_headers(&h);
+    let eco_group = eco_group_for_game(&h, game_lines, cfg);
     let result = result_from_headers(&h);
 
     let w_elo = parse_elo(h.get("WhiteElo"));
     let b_elo = parse_elo(h.get("BlackElo"));
 
+    let speed = speed_from_headers(&h);
+    let w_bucket = cfg.elo_bucket(w_elo);
+    let b_bucket = cfg.elo_bucket(b_elo);
     let key = Key {
-        month,
-        eco_group,
-        w_bucket: elo_bucket_with_size(w_elo, cfg.bucket_size),
-        b_bucket: elo_bucket_with_size(b_elo, cfg.bucket_size),
+        month: month.clone(),
+        speed,
+        eco_group: eco_group.clone(),
+        w_bucket,
+        b_bucket,
+        w_title: w_title.to_string(),
+        b_title: b_title.to_string(),
     };
 
-    let counter = map.entry(key).or_default();
-    counter.add_result(&result);
+    // with `exclude_unfinished`, an unfinished game only shows in its row's
+    // `unfinished`; no other counter or analysis sees it
+    if cfg.exclude_unfinished && !matches!(result.as_str(), "1-0" | "0-1" | "1/2-1/2") {
+        agg.map.entry(key).or_default().add_result(&result, true);
+        return;
+    }
+
+    if cfg.rating_quantiles {
+        let hist = agg
+            .ratings
+            .entry(MonthSpeed { month: month.clone(), speed })
+            .or_default();
+        for elo in [w_elo, b_elo].into_iter().flatten() {
+            hist.add(elo);
+        }
+    }
+
+    // one replay serves both the endgame signature and the draw reason
+    let is_draw = result == "1/2-1/2";
+    let replayed = replay::analyze(
+        &h,
+        game_lines,
+        cfg.draw_reasons && is_draw,
+        cfg.endgames.then_some(cfg.endgame_max_material),
+    );
+    if cfg.endgames {
+        if let Some(signature) = replayed.endgame {
+            let ek = EndgameKey {
+                month: month.clone(),
+                speed,
+                w_bucket,
+                b_bucket,
+                signature,
+            };
+            agg.endgames.entry(ek).or_default().add_result(&result, cfg.exclude_unfinished);
+        }
+    }
+
+    if cfg.heavy_hitters {
+        let cell = MonthSpeed { month: month.clone(), speed };
+        let players = agg.top_players.entry(cell.clone()).or_insert_with(|| TopK::new(cfg.top_capacity));
+        for name in [h.get("White"), h.get("Black")].into_iter().flatten() {
+            if !name.is_empty() && name.as_str() != "?" {
+                players.add(name);
+            }
+        }
+        if let Some(opening) = h.get("Opening").filter(|o| !o.is_empty() && o.as_str() != "?") {
+            agg.top_openings.entry(cell).or_insert_with(|| TopK::new(cfg.top_capacity)).add(opening);
+        }
+    }
+
+    if cfg.distinct_players {
+        let hashes: Vec<u64> = [h.get("White"), h.get("Black")]
+            .into_iter()
+            .flatten()
+            .filter(|name| !name.is_empty() && name.as_str() != "?")
+            .map(|name| stable_hash64(name.to_ascii_lowercase().as_bytes()))
+            .collect();
+        let mut cells = vec![PlayerCell { month: month.clone(), eco_group: "*".to_string(), speed: "*" }];
+        if cfg.distinct_players_by_group {
+            cells.push(PlayerCell { month: month.clone(), eco_group: eco_group.clone(), speed });
+        }
+        for cell in cells {
+            let hll = agg.players.entry(cell).or_insert_with(|| Hll::new(cfg.hll_precision));
+            for &x in &hashes {
+                hll.add_hash(x);
+            }
+        }
+    }
+
+    if cfg.cohorts {
+        for (name, elo) in [(h.get("White"), w_elo), (h.get("Black"), b_elo)] {
+            let Some(name) = name.filter(|n| !n.is_empty() && n.as_str() != "?") else { continue };
+            agg.cohort_players.add_game(name.to_ascii_lowercase(), month.clone(), elo, &eco_group);
+        }
+    }
+
+    let counter = agg.map.entry(key).or_default();
+    counter.add_result(&result, cfg.exclude_unfinished);
+    counter.add_rating_diffs(
+        parse_rating_diff(h.get("WhiteRatingDiff")),
+        parse_rating_diff(h.get("BlackRatingDiff")),
+    );
+    counter.add_elos(w_elo, b_elo);
+
+    if let Some(reason) = replayed.draw_reason {
+        counter.add_draw_reason(reason);
+    }
+
+    if cfg.move_style {
+        let (white, black) = move_style(&h, &movetext_sans(game_lines));
+        counter.add_move_style(&white, &black);
+    }
+}
+
+/// ECO group from the header, or from the moves (opening book) when the header
+/// is missing/invalid and `eco_from_moves` is on, or always with `eco_prefer_computed`.
+fn eco_group_for_game(h: &HashMap<String, String>, game_lines: &[String], cfg: &Config) -> String {
+    let from_header = eco_group_from_headers(h);
+    // book lines start from the standard position; a set-up game keeps its header
+    let set_up = h.get("SetUp").map(String::as_str) == Some("1") || h.contains_key("FEN");
+    if !set_up && (cfg.eco_prefer_computed || (cfg.eco_from_moves && from_header == "U00")) {
+        if let Some(eco) = openings::book().classify(&movetext_sans(game_lines)) {
+            return crate::eco::label_for_code(eco).to_string();
+        }
+    }
+    from_header
 }
 
 fn merge_maps(dst: &mut AggMap, src: AggMap) {
     for (k, c) in src {
-        let e = dst.entry(k).or_default();
-        e.games += c.games;
-        e.white_wins += c.white_wins;
-        e.black_wins += c.black_wins;
-        e.draws += c.draws;
+        dst.entry(k).or_default().merge(&c);
     }
 }
 
-pub fn write_csv(map: &AggMap, out_path: &Path) -> io::Result<()> {
+/// `eco_names` adds an `eco_name` column (see `eco::name_for_label`) after `eco_group`.
+pub fn write_csv(map: &AggMap, out_path: &Path, scale: f64, eco_names: bool) -> io::Result<()> {
     let mut entries: Vec<_> = map.iter().collect();
-    entries.sort_by_key(|(_, c)| std::cmp::Reverse(c.games));
+    entries.sort_by_key(|(_, c)| std::cmp::Reverse(c.outcomes.games));
 
     let mut f = File::create(out_path)?;
     // counts only
     writeln!(
         f,
-        "month,eco_group,white_bucket,black_bucket,games,white_wins,black_wins,draws"
+        "month,speed,eco_group,{}white_bucket,black_bucket,white_title,black_title,{}",
+        if eco_names { "eco_name," } else { "" },
+        COUNTER_COLUMNS.join(",")
     )?;
     for (k, c) in entries {
+        let counts: Vec<String> = c.values().iter().map(|&v| scaled(v, scale).to_string()).collect();
+        let eco_name = if eco_names { format!("\"{}\",", eco::name_for_label(&k.eco_group)) } else { String::new() };
         writeln!(
             f,
-            "{},{},{},{},{},{},{},{}",
+            "{},{},{},{}{},{},{},{},{}",
             k.month,
+            k.speed,
             k.eco_group,
+            eco_name,
             k.w_bucket,
             k.b_bucket,
-            c.games,
-            c.white_wins,
-            c.black_wins,
-            c.draws
+            k.w_title,
+            k.b_title,
+            counts.join(",")
         )?;
     }
     Ok(())
 }
+
+/// Main CSV at `out_path`, plus one sibling CSV per non-empty side table
+/// (e.g., `agg.csv` → `agg-endgames.csv`).
+pub fn write_csvs(agg: &Aggregation, out_path: &Path, cfg: &Config) -> io::Result<()> {
+    let out_path = &output_path(out_path, cfg);
+    let (scale, top_k) = (cfg.preview.scale_factor(), cfg.top_k);
+    write_csv(&agg.map, out_path, scale, cfg.csv_eco_names)?;
+    if !agg.endgames.is_empty() {
+        write_endgames_csv(&agg.endgames, &sibling_path(out_path, "endgames"), scale)?;
+    }
+    if !agg.ratings.is_empty() {
+        write_quantiles_csv(&agg.ratings, &sibling_path(out_path, "quantiles"))?;
+    }
+    if !agg.players.is_empty() {
+        write_players_csv(&agg.players, &sibling_path(out_path, "players"))?;
+    }
+    if !agg.top_players.is_empty() {
+        write_top_csv(&agg.top_players, "player", top_k, &sibling_path(out_path, "top-players"))?;
+        write_top_csv(&agg.top_openings, "opening", top_k, &sibling_path(out_path, "top-openings"))?;
+    }
+    Ok(())
+}
+
+/// Where `--out` really goes: previews get a `-preview` suffix so they can't
+/// be mistaken for (or overwrite) full outputs.
+pub fn output_path(out_path: &Path, cfg: &Config) -> PathBuf {
+    if cfg.preview.is_active() { sibling_path(out_path, "preview") } else { out_path.to_path_buf() }
+}
+
+fn scaled(v: u64, scale: f64) -> u64 {
+    if scale == 1.0 { v } else { (v as f64 * scale).round() as u64 }
+}
+
+pub fn sibling_path(out_path: &Path, suffix: &str) -> PathBuf {
+    let stem = out_path.file_stem().and_then(|s| s.to_str()).unwrap_or("agg");
+    let ext = out_path.extension().and_then(|e| e.to_str()).unwrap_or("csv");
+    out_path.with_file_name(format!("{}-{}.{}", stem, suffix, ext))
+}
+
+pub fn write_endgames_csv(map: &EndgameMap, out_path: &Path, scale: f64) -> io::Result<()> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(_, c)| std::cmp::Reverse(c.games));
+
+    let mut f = File::create(out_path)?;
+    writeln!(f, "month,speed,white_bucket,black_bucket,signature,games,white_wins,black_wins,draws,unfinished")?;
+    for (k, c) in entries {
+        writeln!(
+            f,
+            "{},{},{},{},{},{},{},{},{},{}",
+            k.month, k.speed, k.w_bucket, k.b_bucket, k.signature,
+            scaled(c.games, scale), scaled(c.white_wins, scale), scaled(c.black_wins, scale),
+            scaled(c.draws, scale), scaled(c.unfinished, scale)
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_quantiles_csv(map: &RatingMap, out_path: &Path) -> io::Result<()> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.month.cmp(&b.month).then_with(|| a.speed.cmp(b.speed)));
+
+    let names: Vec<&str> = RATING_QUANTILES.iter().map(|(n, _)| *n).collect();
+    let qs: Vec<f64> = RATING_QUANTILES.iter().map(|(_, q)| *q).collect();
+
+    let mut f = File::create(out_path)?;
+    writeln!(f, "month,speed,ratings,{}", names.join(","))?;
+    for (k, hist) in entries {
+        let values: Vec<String> = hist.quantiles(&qs).iter().map(|v| v.to_string()).collect();
+        writeln!(f, "{},{},{},{}", k.month, k.speed, hist.count(), values.join(","))?;
+    }
+    Ok(())
+}
+
+pub fn write_players_csv(map: &PlayerMap, out_path: &Path) -> io::Result<()> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| {
+        a.month.cmp(&b.month).then_with(|| a.eco_group.cmp(&b.eco_group)).then_with(|| a.speed.cmp(b.speed))
+    });
+
+    let mut f = File::create(out_path)?;
+    writeln!(f, "month,eco_group,speed,players_estimate")?;
+    for (k, hll) in entries {
+        writeln!(f, "{},{},{},{}", k.month, k.eco_group, k.speed, hll.estimate().round() as u64)?;
+    }
+    Ok(())
+}
+
+pub fn write_top_csv(map: &TopMap, item_col: &str, top_k: usize, out_path: &Path) -> io::Result<()> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.month.cmp(&b.month).then_with(|| a.speed.cmp(b.speed)));
+
+    let mut f = File::create(out_path)?;
+    writeln!(f, "month,speed,rank,{},games,error", item_col)?;
+    for (k, top) in entries {
+        for (rank, (item, games, error)) in top.top(top_k).into_iter().enumerate() {
+            writeln!(f, "{},{},{},\"{}\",{},{}", k.month, k.speed, rank + 1, item.replace('"', "\"\""), games, error)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Preview;
+
+    fn game(headers: &[(&str, &str)], movetext: &str) -> (HashMap<String, String>, Vec<String>) {
+        let h = headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        (h, vec![movetext.to_string()])
+    }
+
+    const BOT_GAME: &str = "[Event \"Rated Blitz game\"]\n[Site \"https://lichess.org/b0t00001\"]\n\
+        [UTCDate \"2013.01.02\"]\n[WhiteTitle \"BOT\"]\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n";
+    const HUMAN_GAMES: &str = "[Event \"Rated Blitz game\"]\n[Site \"https://lichess.org/hum00001\"]\n\
+        [UTCDate \"2013.01.02\"]\n[WhiteTitle \"GM\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n\n\
+        [Event \"Rated Blitz game\"]\n[Site \"https://lichess.org/hum00002\"]\n\
+        [UTCDate \"2013.01.03\"]\n[Result \"1/2-1/2\"]\n\n1. c4 c5 1/2-1/2\n\n";
+
+    fn aggregate(pgn: &str, cfg: &Config) -> Aggregation {
+        aggregate_from_reader(pgn.as_bytes(), cfg).unwrap().0
+    }
+
+    fn games_by_title(agg: &Aggregation, w_title: &str) -> u64 {
+        agg.map.iter().filter(|(k, _)| k.w_title == w_title).map(|(_, c)| c.outcomes.games).sum()
+    }
+
+    #[test]
+    fn bot_policies() {
+        let pgn = format!("{}{}", BOT_GAME, HUMAN_GAMES);
+
+        let include = aggregate(&pgn, &Config { bot_games: BotPolicy::Include, ..Config::default() });
+        assert_eq!(games_by_title(&include, "bot"), 1);
+        assert_eq!(games_by_title(&include, "titled") + games_by_title(&include, "none"), 2);
+        assert_eq!((include.bot_excluded, include.human_excluded), (0, 0));
+
+        let exclude = aggregate(&pgn, &Config { bot_games: BotPolicy::Exclude, ..Config::default() });
+        assert_eq!(games_by_title(&exclude, "bot"), 0);
+        assert_eq!(games_by_title(&exclude, "titled") + games_by_title(&exclude, "none"), 2);
+        assert_eq!((exclude.bot_excluded, exclude.human_excluded), (1, 0));
+
+        let isolate = aggregate(&pgn, &Config { bot_games: BotPolicy::Isolate, ..Config::default() });
+        assert_eq!(games_by_title(&isolate, "bot"), 1);
+        assert_eq!(games_by_title(&isolate, "titled") + games_by_title(&isolate, "none"), 0);
+        assert_eq!((isolate.bot_excluded, isolate.human_excluded), (0, 2));
+    }
+
+    #[test]
+    fn excluded_unfinished_games_skip_other_analyses() {
+        let pgn = "[Event \"Rated Blitz game\"]\n[Site \"https://lichess.org/unf00001\"]\n[UTCDate \"2013.01.02\"]\n\
+            [WhiteElo \"1500\"]\n[BlackElo \"1600\"]\n[Result \"*\"]\n\n1. e4 e5 2. Qh5 *\n\n";
+        let on = Config { move_style: true, rating_quantiles: true, exclude_unfinished: true, ..Config::default() };
+        let agg = aggregate(pgn, &on);
+        let c = agg.map.values().next().unwrap();
+        assert_eq!((c.outcomes.games, c.outcomes.unfinished), (0, 1));
+        assert_eq!((c.white_elo_games, c.white_early_queen), (0, 0));
+        assert!(agg.ratings.is_empty());
+
+        let off = Config { move_style: true, rating_quantiles: true, ..Config::default() };
+        let agg = aggregate(pgn, &off);
+        let c = agg.map.values().next().unwrap();
+        assert_eq!((c.outcomes.games, c.outcomes.unfinished), (1, 1));
+        assert_eq!((c.white_elo_games, c.white_early_queen), (1, 1));
+        assert_eq!(agg.ratings.len(), 1);
+    }
+
+    #[test]
+    fn eco_from_moves_fills_only_missing_headers() {
+        let cfg = Config { eco_from_moves: true, ..Config::default() };
+        let (h, lines) = game(&[], "1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5");
+        assert_eq!(eco_group_for_game(&h, &lines, &cfg), eco::label_for_code("C50"));
+        let (h, lines) = game(&[("ECO", "B20")], "1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5");
+        assert_eq!(eco_group_for_game(&h, &lines, &cfg), eco::label_for_code("B20"));
+    }
+
+    #[test]
+    fn book_miss_falls_back_to_u00() {
+        let cfg = Config { eco_prefer_computed: true, ..Config::default() };
+        let (h, lines) = game(&[], "1-0");
+        assert_eq!(eco_group_for_game(&h, &lines, &cfg), "U00");
+    }
+
+    #[test]
+    fn set_up_games_keep_their_header_group() {
+        let cfg = Config { eco_prefer_computed: true, ..Config::default() };
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let (h, lines) = game(&[("SetUp", "1"), ("FEN", fen), ("ECO", "A40")], "1. e4 e5 2. Nf3 Nc6 3. Bc4");
+        assert_eq!(eco_group_for_game(&h, &lines, &cfg), eco::label_for_code("A40"));
+        let (h, lines) = game(&[("FEN", fen)], "1. e4 e5 2. Nf3 Nc6 3. Bc4");
+        assert_eq!(eco_group_for_game(&h, &lines, &cfg), "U00");
+    }
+
+    fn numbered_games(n: usize) -> Vec<String> {
+        (0..n)
+            .map(|i| {
+                format!(
+                    "[Event \"Rated Blitz game\"]\n[Site \"https://lichess.org/lim{:05}\"]\n[UTCDate \"2013.01.02\"]\n\
+                     [Result \"1-0\"]\n\n1. e4 e5 1-0\n\n",
+                    i
+                )
+            })
+            .collect()
+    }
+
+    fn preview(preview: Preview) -> Config {
+        Config { preview, ..Config::default() }
+    }
+
+    #[test]
+    fn sample_membership_is_deterministic() {
+        let games: Vec<Vec<String>> =
+            numbered_games(1_000).iter().map(|g| g.lines().map(str::to_string).collect()).collect();
+        let picks = |rate: f64| -> Vec<bool> { games.iter().map(|g| in_sample(g, rate)).collect() };
+        assert_eq!(picks(0.3), picks(0.3));
+        let kept = picks(0.3).iter().filter(|&&k| k).count();
+        assert!((200..400).contains(&kept), "kept {} of 1000", kept);
+        // a smaller rate keeps a subset of a larger one's games
+        assert!(picks(0.1).iter().zip(picks(0.3)).all(|(&small, large)| !small || large));
+        assert!(picks(1.0).iter().all(|&k| k));
+        assert!(picks(0.0).iter().all(|&k| !k));
+        // no Site: the header block decides, the same way every time
+        let no_site: Vec<String> = vec!["[Event \"x\"]".into(), "[White \"a\"]".into(), "".into(), "1-0".into()];
+        assert_eq!(in_sample(&no_site, 0.5), in_sample(&no_site, 0.5));
+    }
+
+    #[test]
+    fn max_games_stops_at_the_limit() {
+        let pgn = numbered_games(10).concat();
+        let cfg = preview(Preview { max_games: Some(3), ..Preview::default() });
+        let (agg, total) = aggregate_from_reader(pgn.as_bytes(), &cfg).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(agg.map.values().map(|c| c.outcomes.games).sum::<u64>(), 3);
+        assert!(agg.truncated);
+
+        let cfg = preview(Preview { max_games: Some(10), ..Preview::default() });
+        let (agg, total) = aggregate_from_reader(pgn.as_bytes(), &cfg).unwrap();
+        assert_eq!((total, agg.map.values().map(|c| c.outcomes.games).sum::<u64>()), (10, 10));
+    }
+
+    #[test]
+    fn max_bytes_keeps_only_whole_games() {
+        let games = numbered_games(10);
+        let pgn = games.concat();
+        let two = (games[0].len() + games[1].len()) as u64;
+        // exactly two games fit; one byte less cuts the second short
+        for (max, want) in [(two, 2), (two + 5, 2), (two - 1, 1)] {
+            let cfg = preview(Preview { max_bytes: Some(max), ..Preview::default() });
+            let (agg, total) = aggregate_from_reader(pgn.as_bytes(), &cfg).unwrap();
+            assert_eq!(total, want, "max_bytes={}", max);
+            assert_eq!(agg.map.values().map(|c| c.outcomes.games).sum::<u64>(), want as u64);
+            assert!(agg.truncated);
+        }
+    }
+
+    #[test]
+    fn scale_multiplies_every_counter_column() {
+        let pgn = format!("{}{}", BOT_GAME, HUMAN_GAMES);
+        let cfg = Config { draw_reasons: true, move_style: true, ..Config::default() };
+        let agg = aggregate(&pgn, &cfg);
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("lta-scale-{}-{}.csv", std::process::id(), name));
+        let read = |p: &Path| -> Vec<Vec<String>> {
+            let text = std::fs::read_to_string(p).unwrap();
+            std::fs::remove_file(p).unwrap();
+            text.lines().map(|l| l.split(',').map(str::to_string).collect()).collect()
+        };
+        write_csv(&agg.map, &path("x1"), 1.0, false).unwrap();
+        write_csv(&agg.map, &path("x4"), 4.0, false).unwrap();
+        let (plain, scaled) = (read(&path("x1")), read(&path("x4")));
+        assert_eq!(plain[0], scaled[0]);
+        let first_count = plain[0].len() - COUNTER_COLUMNS.len();
+        assert_eq!(plain.len(), agg.map.len() + 1);
+        for (a, b) in plain[1..].iter().zip(&scaled[1..]) {
+            assert_eq!(a[..first_count], b[..first_count]);
+            for (col, (x, y)) in COUNTER_COLUMNS.iter().zip(a[first_count..].iter().zip(&b[first_count..])) {
+                assert_eq!(x.parse::<u64>().unwrap() * 4, y.parse::<u64>().unwrap(), "{}", col);
+            }
+        }
+    }
+}
@@ -6,8 +6,19 @@ pub struct Cli {
     pub since: Option<String>, // "YYYY-MM" (lower bound, inclusive)
     pub until: Option<String>, // "YYYY-MM" (upper bound, inclusive)
     pub list_url: String,      // optional override (default from config)
+    pub filter: Option<String>, // header filter expression (overrides config)
     pub verbose: bool,
     pub save: bool,
+    pub rebuild_cohorts: bool, // recompute cohort tables from scratch (with --save)
+    pub sample_rate: Option<f64>, // preview: keep this fraction of games (by game ID)
+    pub max_games: Option<u64>,   // preview: stop after N games
+    pub max_bytes: Option<u64>,   // preview: stop after N PGN bytes
+    pub scale: bool,              // preview: scale sampled counts up in CSVs
+    pub source: Option<String>,   // local: input file name, recorded with the ingestion
+    pub force: bool,              // local: replace an input that was already saved
+    pub reaggregate: bool,        // save despite rows built with another ECO taxonomy or other compat settings, replacing them
+    pub rebucket: bool,           // re-map stored buckets to the configured edges
+    pub into: Option<String>,     // rebucket: write aggregates to this new table instead
     pub help: bool,
 }
 
@@ -17,8 +28,19 @@ pub fn parse() -> Cli {
     let mut since: Option<String> = None;
     let mut until: Option<String> = None;
     let mut list_url = String::new(); // ← no default here; config.toml is the default
+    let mut filter: Option<String> = None;
     let mut verbose = false;
     let mut save = false;
+    let mut rebuild_cohorts = false;
+    let mut sample_rate: Option<f64> = None;
+    let mut max_games: Option<u64> = None;
+    let mut max_bytes: Option<u64> = None;
+    let mut scale = false;
+    let mut source: Option<String> = None;
+    let mut force = false;
+    let mut reaggregate = false;
+    let mut rebucket = false;
+    let mut into: Option<String> = None;
     let mut help = false;
 
     let mut it = std::env::args().skip(1);
@@ -37,14 +59,41 @@ pub fn parse() -> Cli {
             "--list-url" => {
                 if let Some(u) = it.next() { list_url = u; }
             }
+            "--filter" => {
+                if let Some(f) = it.next() { filter = Some(f); }
+            }
             "--verbose" | "-v" => verbose = true,
             "--save" => save = true,
+            "--rebuild-cohorts" => rebuild_cohorts = true,
+            "--sample-rate" => {
+                if let Some(r) = it.next() {
+                    let r: f64 = r.parse().expect("--sample-rate expects a number");
+                    assert!(r > 0.0 && r <= 1.0, "--sample-rate must be in (0, 1]");
+                    sample_rate = Some(r);
+                }
+            }
+            "--max-games" => {
+                if let Some(n) = it.next() { max_games = Some(n.parse().expect("--max-games expects an integer")); }
+            }
+            "--max-bytes" => {
+                if let Some(n) = it.next() { max_bytes = Some(n.parse().expect("--max-bytes expects an integer")); }
+            }
+            "--scale" => scale = true,
+            "--source" => {
+                if let Some(n) = it.next() { source = Some(n); }
+            }
+            "--force" => force = true,
+            "--reaggregate" => reaggregate = true,
+            "--rebucket" => rebucket = true,
+            "--into" => {
+                if let Some(t) = it.next() { into = Some(t); }
+            }
             "--help" | "-h" => help = true,
             _ => {}
         }
     }
 
-    Cli { out, ingest_remote, since, until, list_url, verbose, save, help }
+    Cli { out, ingest_remote, since, until, list_url, filter, verbose, save, rebuild_cohorts, sample_rate, max_games, max_bytes, scale, source, force, reaggregate, rebucket, into, help }
 }
 
 pub fn print_help() {
@@ -53,10 +102,13 @@ r#"LichessTrends Aggregator
 
 Usage:
   Local file(s):
-    aggregator [--out agg.csv] [file1.zst [file2.zst ...]] [--save] [-v]
+    aggregator [--out agg.csv] [file1.zst [file2.zst ...]] [--filter EXPR] [--save [--force]] [-v]
 
   Remote ingest (stream from Lichess without saving .zst):
-    aggregator --remote [--since YYYY-MM] [--until YYYY-MM] [--out OUT] [--list-url URL] [--save] [-v]
+    aggregator --remote [--since YYYY-MM] [--until YYYY-MM] [--out OUT] [--list-url URL] [--filter EXPR] [--save [--reaggregate]] [-v]
+
+  Coarser rating buckets from the saved rows (no download):
+    aggregator --rebucket [--into TABLE] [--save] [-v]
 
 Options:
   --remote, --ingest-remote   Stream monthly dumps (oldest → newest).
@@ -67,8 +119,37 @@ Options:
                               - remote: directory for one CSV per month,
                                         or base filename (becomes base-YYYY-MM.ext)
   --list-url URL              Override the Lichess list.txt endpoint.
+  --filter EXPR               Only aggregate games whose headers match EXPR, e.g.
+                              'Event ~ "^Rated Blitz" and WhiteElo >= 2000'
+                              (overrides `filter` in config.toml).
+  --sample-rate R             Preview: keep a fraction R of games, chosen by a hash of
+                              the game ID (reruns pick the same games).
+  --max-games N               Preview: stop after reading N games.
+  --max-bytes N               Preview: stop after reading N bytes of PGN.
+  --scale                     Preview: multiply additive CSV counts by 1/R.
+                              Preview outputs go to <out>-preview.csv and can't be
+                              combined with --save.
+  --source NAME               Local: name of the input file (stored in local_ingestions).
+  --force                     Local: save an input that was already saved (same content
+                              hash), taking its earlier rows out instead of adding twice.
+  --reaggregate               With --save: allow saving although the database holds rows
+                              built with another ECO taxonomy or other bucket/ECO/filter
+                              settings (see the run manifests). Remote:
+                              re-ingests those months too (all must be in the plan);
+                              earlier local inputs are taken out, to be fed again
+                              with --force (local runs refuse while months need it).
   -v, --verbose               Detailed timings/logs.
   --save                      Persist to DATABASE_URL (run migrations, write rows).
+  --rebuild-cohorts           With --save: empty player_first_seen/player_cohorts and
+                              recompute them (remote: from every ingested month, oldest
+                              first; local: from stdin). Writes nothing else.
+  --rebucket                  Re-map the saved buckets to bucket_size / bucket_edges from
+                              config.toml (must align with the stored edges: every new
+                              edge an old one). Without --save only checks (it still
+                              connects to DATABASE_URL). With --save
+                              rewrites aggregates, endgames and player_cohorts.
+  --into TABLE                With --rebucket: write the rebucketed aggregates to a new
+                              table TABLE and leave everything else as it is.
   -h, --help                  Show this help.
 
 Notes:
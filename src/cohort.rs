@@ -0,0 +1,375 @@
+// src/cohort.rs
+// New-vs-returning players. A player is "new" in the month of their first
+// ingested game and "returning" in every later month. First months live in
+// `player_first_seen` (one row per lowercased username); each player's games
+// per month in `player_months` / `player_month_ecos`, which saves add to; the
+// per-month counts in `player_cohorts`, split by the player's mean-Elo bucket
+// and ECO group, are recomputed from those for every month a save touches.
+//
+// A pass holds at most `SPILL_AT` player-months in memory and spills the rest
+// to a temporary file; saving streams them to the database in chunks, and
+// only the usernames of a chunk are looked up, so neither the pass nor the
+// stored set has to fit in memory. A month saved before one of its players'
+// stored first month moves that first month earlier and recomputes the month
+// it moved from; `--rebuild-cohorts` recomputes everything from scratch.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::db::{self, Tx};
+use crate::pgn::Buckets;
+
+/// Player-months a pass keeps in memory before spilling them to disk
+/// (roughly 200 bytes each).
+pub const SPILL_AT: usize = 1_000_000;
+
+/// Player-months written to the database per round trip when saving.
+const SAVE_CHUNK: usize = 20_000;
+
+/// One player's games in one month.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlayerMonth {
+    pub games: u64,
+    pub elo_sum: u64,
+    pub elo_games: u64,
+    pub eco_games: HashMap<String, u64>,
+}
+
+impl PlayerMonth {
+    pub fn add_game(&mut self, elo: Option<u16>, eco_group: &str) {
+        self.games += 1;
+        if let Some(e) = elo {
+            self.elo_sum += e as u64;
+            self.elo_games += 1;
+        }
+        *self.eco_games.entry(eco_group.to_string()).or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: &PlayerMonth) {
+        self.games += other.games;
+        self.elo_sum += other.elo_sum;
+        self.elo_games += other.elo_games;
+        for (eco, n) in &other.eco_games {
+            *self.eco_games.entry(eco.clone()).or_default() += n;
+        }
+    }
+
+    /// Spill line: games, Elo sum and count, `eco=n` pairs, then the month
+    /// and the username last (so it may hold any character but a newline).
+    fn to_line(&self, player: &str, month: &str) -> String {
+        let ecos: Vec<String> = self.eco_games.iter().map(|(eco, n)| format!("{}={}", eco, n)).collect();
+        format!("{}\t{}\t{}\t{}\t{}\t{}", self.games, self.elo_sum, self.elo_games, ecos.join(","), month, player)
+    }
+
+    fn from_line(line: &str) -> Option<(String, String, PlayerMonth)> {
+        let mut fields = line.splitn(6, '\t');
+        let mut pm = PlayerMonth {
+            games: fields.next()?.parse().ok()?,
+            elo_sum: fields.next()?.parse().ok()?,
+            elo_games: fields.next()?.parse().ok()?,
+            eco_games: HashMap::new(),
+        };
+        for pair in fields.next()?.split(',').filter(|p| !p.is_empty()) {
+            let (eco, n) = pair.rsplit_once('=')?;
+            pm.eco_games.insert(eco.to_string(), n.parse().ok()?);
+        }
+        let month = fields.next()?.to_string();
+        Some((fields.next()?.to_string(), month, pm))
+    }
+}
+
+/// A pass's player-months: (lowercased username, month) -> games of that
+/// player in that month, in memory up to `SPILL_AT` entries and in a
+/// temporary file past that. The same key may appear in both; entries add up.
+#[derive(Default)]
+pub struct CohortPlayers {
+    players: HashMap<(String, String), PlayerMonth>,
+    spill: Option<Spill>,
+}
+
+/// Temporary file of spilled player-months, removed on drop.
+struct Spill {
+    path: PathBuf,
+    file: BufWriter<File>,
+    entries: u64,
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+static SPILLS: AtomicU64 = AtomicU64::new(0);
+
+impl CohortPlayers {
+    pub fn add_game(&mut self, player: String, month: String, elo: Option<u16>, eco_group: &str) {
+        self.players.entry((player, month)).or_default().add_game(elo, eco_group);
+    }
+
+    /// Merge a batch's players (batches never spill).
+    pub fn merge(&mut self, other: CohortPlayers) {
+        debug_assert!(other.spill.is_none());
+        for (k, pm) in other.players {
+            self.players.entry(k).or_default().merge(&pm);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty() && self.spill.is_none()
+    }
+
+    /// Move the in-memory entries to the spill file once there are `at` of them.
+    pub fn spill_if_large(&mut self, at: usize) -> io::Result<()> {
+        if self.players.len() < at { return Ok(()); }
+        if self.spill.is_none() {
+            let n = SPILLS.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!("lta-cohorts-{}-{}.tsv", std::process::id(), n));
+            let file = BufWriter::new(File::create(&path)?);
+            vprintln!("cohorts: spilling player-months to {}", path.display());
+            self.spill = Some(Spill { path, file, entries: 0 });
+        }
+        let spill = self.spill.as_mut().expect("spill file created above");
+        for ((player, month), pm) in self.players.drain() {
+            writeln!(spill.file, "{}", pm.to_line(&player, &month))?;
+            spill.entries += 1;
+        }
+        vprintln!("cohorts: {} player-months spilled so far", spill.entries);
+        spill.file.flush()
+    }
+
+    /// Every entry: the spilled ones first, then the ones in memory.
+    pub fn entries(&self) -> io::Result<impl Iterator<Item = io::Result<(String, String, PlayerMonth)>> + '_> {
+        let spilled = match &self.spill {
+            Some(spill) => Some(BufReader::new(File::open(&spill.path)?).lines()),
+            None => None,
+        };
+        let spilled = spilled.into_iter().flatten().map(|line| {
+            let line = line?;
+            PlayerMonth::from_line(&line)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("bad cohort spill line {:?}", line)))
+        });
+        let in_memory = self.players.iter().map(|((p, m), pm)| Ok((p.clone(), m.clone(), pm.clone())));
+        Ok(spilled.chain(in_memory))
+    }
+}
+
+/// Cell of the `player_cohorts` table; eco_group "*" = all groups.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct CohortKey {
+    pub month: String,
+    pub cohort: &'static str, // "new" | "returning"
+    pub rating_bucket: u16,   // bucket of the player's mean Elo that month
+    pub eco_group: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CohortCounts {
+    pub players: u64,
+    pub games: u64,
+}
+
+pub type CohortMap = HashMap<CohortKey, CohortCounts>;
+
+/// Add a pass's players to the stored player months and first months, then
+/// recompute `player_cohorts` for its months (and for months whose players'
+/// first month moved earlier). With `local`, the player months are also kept
+/// for that local input. Returns the recomputed rows of the pass's months.
+pub async fn save(
+    tx: &mut Tx,
+    players: &CohortPlayers,
+    buckets: &Buckets,
+    local: Option<&str>,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<CohortMap> {
+    let mut months = BTreeSet::new();
+    let mut moved = BTreeSet::new();
+    let mut chunk: HashMap<(String, String), PlayerMonth> = HashMap::new();
+    for entry in players.entries()? {
+        let (player, month, pm) = entry?;
+        chunk.entry((player, month)).or_default().merge(&pm);
+        if chunk.len() >= SAVE_CHUNK {
+            save_chunk(tx, &chunk, local, &mut months, &mut moved, cfg_chunk_size).await?;
+            chunk.clear();
+        }
+    }
+    save_chunk(tx, &chunk, local, &mut months, &mut moved, cfg_chunk_size).await?;
+
+    let refresh: BTreeSet<&str> = months.iter().chain(&moved).map(String::as_str).collect();
+    let refresh: Vec<&str> = refresh.into_iter().collect();
+    let mut cohorts = db::refresh_player_cohorts(tx, &refresh, buckets, cfg_chunk_size).await?;
+    cohorts.retain(|k, _| months.contains(&k.month));
+    Ok(cohorts)
+}
+
+/// One chunk of `save`: player months added, first months moved earlier
+/// where this chunk has an earlier one (their old month goes to `moved`).
+async fn save_chunk(
+    tx: &mut Tx,
+    chunk: &HashMap<(String, String), PlayerMonth>,
+    local: Option<&str>,
+    months: &mut BTreeSet<String>,
+    moved: &mut BTreeSet<String>,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    if chunk.is_empty() { return Ok(()); }
+    db::add_player_months(tx, chunk, local, cfg_chunk_size).await?;
+
+    let mut first: HashMap<&str, &str> = HashMap::new();
+    for (player, month) in chunk.keys() {
+        let f = first.entry(player).or_insert(month);
+        if month.as_str() < *f { *f = month; }
+        months.insert(month.clone());
+    }
+    let names: Vec<&str> = first.keys().copied().collect();
+    let known = db::load_first_seen(tx, &names).await?;
+    let mut first_seen = HashMap::new();
+    for (player, f) in first {
+        match known.get(player) {
+            Some(k) if k.as_str() <= f => {}
+            Some(k) => {
+                moved.insert(k.clone());
+                first_seen.insert(player.to_string(), f.to_string());
+            }
+            None => { first_seen.insert(player.to_string(), f.to_string()); }
+        }
+    }
+    db::upsert_first_seen(tx, &first_seen, cfg_chunk_size).await
+}
+
+/// Cells of one month from its grouped player months: (cohort, mean Elo,
+/// eco_group, players, games) rows, with the mean Elo mapped to its bucket.
+pub fn cohort_cells(month: &str, rows: &[(String, Option<i64>, String, i64, i64)], buckets: &Buckets) -> CohortMap {
+    let mut cohorts = CohortMap::new();
+    for (cohort, mean_elo, eco_group, players, games) in rows {
+        let cohort = if cohort == "new" { "new" } else { "returning" };
+        let rating_bucket = buckets.bucket(mean_elo.map(|e| e as u16));
+        let key = CohortKey { month: month.to_string(), cohort, rating_bucket, eco_group: eco_group.clone() };
+        let c = cohorts.entry(key).or_default();
+        c.players += *players as u64;
+        c.games += *games as u64;
+    }
+    cohorts
+}
+
+pub fn write_cohorts_csv(map: &CohortMap, out_path: &Path) -> io::Result<()> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| {
+        a.month
+            .cmp(&b.month)
+            .then_with(|| a.cohort.cmp(b.cohort))
+            .then_with(|| a.rating_bucket.cmp(&b.rating_bucket))
+            .then_with(|| a.eco_group.cmp(&b.eco_group))
+    });
+
+    let mut f = File::create(out_path)?;
+    writeln!(f, "month,cohort,rating_bucket,eco_group,players,games")?;
+    for (k, c) in entries {
+        writeln!(f, "{},{},{},{},{},{}", k.month, k.cohort, k.rating_bucket, k.eco_group, c.players, c.games)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn players(games: &[(&str, &str, Option<u16>, &str)]) -> CohortPlayers {
+        let mut players = CohortPlayers::default();
+        for &(player, month, elo, eco) in games {
+            players.add_game(player.to_string(), month.to_string(), elo, eco);
+        }
+        players
+    }
+
+    fn summed(players: &CohortPlayers) -> HashMap<(String, String), PlayerMonth> {
+        let mut all: HashMap<(String, String), PlayerMonth> = HashMap::new();
+        for entry in players.entries().unwrap() {
+            let (player, month, pm) = entry.unwrap();
+            all.entry((player, month)).or_default().merge(&pm);
+        }
+        all
+    }
+
+    #[test]
+    fn spilled_entries_add_up_with_the_ones_in_memory() {
+        let games = [
+            ("alice", "2024-01", Some(1500), "B20-B99"),
+            ("bob\tthe\tbuilder", "2024-01", None, "C00-C19"),
+            ("alice", "2024-02", Some(1600), "U00"),
+        ];
+        let expected = summed(&players(&games));
+
+        let mut spilled = players(&games);
+        spilled.spill_if_large(1).unwrap();
+        spilled.add_game("alice".to_string(), "2024-01".to_string(), Some(1700), "B20-B99");
+        let all = summed(&spilled);
+        assert_eq!(all.len(), 3);
+        let alice = &all[&("alice".to_string(), "2024-01".to_string())];
+        assert_eq!((alice.games, alice.elo_sum, alice.elo_games), (2, 3200, 2));
+        assert_eq!(alice.eco_games["B20-B99"], 2);
+        let bob = ("bob\tthe\tbuilder".to_string(), "2024-01".to_string());
+        assert_eq!(all[&bob], expected[&bob]);
+
+        // below the threshold nothing moves
+        let mut small = players(&games);
+        small.spill_if_large(10).unwrap();
+        assert!(small.spill.is_none());
+    }
+
+    fn cell(cohorts: &CohortMap, month: &str, cohort: &str, bucket: u16, eco: &str) -> Option<(u64, u64)> {
+        cohorts
+            .iter()
+            .find(|(k, _)| k.month == month && k.cohort == cohort && k.rating_bucket == bucket && k.eco_group == eco)
+            .map(|(_, c)| (c.players, c.games))
+    }
+
+    #[tokio::test]
+    async fn player_split_across_inputs_counts_once() {
+        let scratch = db::Scratch::open("cohort-test-split").await.unwrap();
+        let mut tx = scratch.db.begin().await.unwrap();
+        let buckets = Buckets::Every(200);
+
+        let first = players(&[("alice", "2024-01", Some(1500), "B20-B99"), ("bob", "2024-01", Some(1900), "B20-B99")]);
+        save(&mut tx, &first, &buckets, Some("input-a"), 100).await.unwrap();
+        let second = players(&[("alice", "2024-01", Some(1700), "C00-C19")]);
+        let cohorts = save(&mut tx, &second, &buckets, Some("input-b"), 100).await.unwrap();
+
+        // alice: 2 games with a mean of 1600 over both inputs; bob: 1 game at 1900
+        assert_eq!(cell(&cohorts, "2024-01", "new", 1600, "*"), Some((1, 2)));
+        assert_eq!(cell(&cohorts, "2024-01", "new", 1800, "*"), Some((1, 1)));
+        assert_eq!(cell(&cohorts, "2024-01", "new", 1600, "B20-B99"), Some((1, 1)));
+        assert_eq!(cell(&cohorts, "2024-01", "new", 1600, "C00-C19"), Some((1, 1)));
+        assert_eq!(cell(&cohorts, "2024-01", "new", 1400, "*"), None);
+        let players_total: u64 = cohorts.iter().filter(|(k, _)| k.eco_group == "*").map(|(_, c)| c.players).sum();
+        assert_eq!(players_total, 2);
+    }
+
+    #[tokio::test]
+    async fn earlier_month_moves_the_first_month() {
+        let scratch = db::Scratch::open("cohort-test-order").await.unwrap();
+        let mut tx = scratch.db.begin().await.unwrap();
+        let buckets = Buckets::Every(200);
+
+        let feb = players(&[("alice", "2024-02", Some(1500), "U00")]);
+        let cohorts = save(&mut tx, &feb, &buckets, None, 100).await.unwrap();
+        assert_eq!(cell(&cohorts, "2024-02", "new", 1400, "*"), Some((1, 1)));
+
+        let jan = players(&[("alice", "2024-01", Some(1500), "U00")]);
+        let cohorts = save(&mut tx, &jan, &buckets, None, 100).await.unwrap();
+        assert_eq!(cell(&cohorts, "2024-01", "new", 1400, "*"), Some((1, 1)));
+        assert!(cohorts.keys().all(|k| k.month == "2024-01"));
+
+        // February was recomputed: alice is returning there now
+        let db::Tx::Sqlite(tx) = &mut tx else { unreachable!() };
+        let feb: Vec<(String, i64)> =
+            sqlx::query_as("SELECT cohort, players FROM player_cohorts WHERE month = '2024-02' AND eco_group = '*'")
+                .fetch_all(&mut **tx)
+                .await
+                .unwrap();
+        assert_eq!(feb, vec![("returning".to_string(), 1)]);
+    }
+}
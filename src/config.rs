@@ -1,31 +1,338 @@
-use serde::Deserialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-#[derive(Debug, Deserialize, Clone)]
+use anyhow::Context;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::dedup::{DedupMode, SeenGames};
+use crate::filter::Filter;
+use crate::pgn::{elo_bucket_with_edges, elo_bucket_with_size, Buckets};
+
+/// What to do with games that have a BOT on either side.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BotPolicy {
+    /// Aggregate them; the title dimension tells them apart.
+    #[default]
+    Include,
+    /// Skip them (counted per ingestion).
+    Exclude,
+    /// Keep only them, skipping human-only games (counted per ingestion).
+    Isolate,
+}
+
+/// A string or a list of strings (`opening_book = "a.tsv"` or `["a.tsv", "b.tsv"]`).
+fn one_or_many<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(d)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+/// Postgres layout of `aggregates` (see `db::apply_pg_partitioning`).
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PgPartitioning {
+    /// One plain table.
+    #[default]
+    None,
+    /// One LIST partition per month (replacing a month truncates its partition).
+    Month,
+    /// One RANGE partition per year.
+    Year,
+}
+
+/// Quick-look limits from the CLI (`--sample-rate`, `--max-games`,
+/// `--max-bytes`, `--scale`). Preview runs can't be saved.
+#[derive(Debug, Clone, Default)]
+pub struct Preview {
+    /// Keep games whose ID hashes below this fraction (stable across reruns).
+    pub sample_rate: Option<f64>,
+    /// Stop after reading this many games.
+    pub max_games: Option<u64>,
+    /// Stop after reading this many (decompressed) PGN bytes.
+    pub max_bytes: Option<u64>,
+    /// Multiply additive CSV counts by 1 / sample_rate.
+    pub scale: bool,
+}
+
+impl Preview {
+    pub fn is_active(&self) -> bool {
+        self.sample_rate.is_some() || self.max_games.is_some() || self.max_bytes.is_some()
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        match self.sample_rate {
+            Some(rate) if self.scale => 1.0 / rate,
+            _ => 1.0,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(r) = self.sample_rate { parts.push(format!("sample_rate={}", r)); }
+        if let Some(n) = self.max_games { parts.push(format!("max_games={}", n)); }
+        if let Some(n) = self.max_bytes { parts.push(format!("max_bytes={}", n)); }
+        if self.scale_factor() != 1.0 { parts.push(format!("counts scaled x{}", self.scale_factor())); }
+        parts.join(", ")
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct Config {
     pub bucket_size: u16,
+    /// Custom bucket lower bounds (ascending, from 0); overrides `bucket_size`.
+    pub bucket_edges: Option<Vec<u16>>,
     pub list_url: String,
     pub batch_size: usize,
     pub db_batch_rows: usize,
     pub rayon_threads: Option<usize>,
+    /// Replay drawn games to split `draws` by reason (slow).
+    pub draw_reasons: bool,
+    /// Record the material signature at the first endgame position (slow).
+    pub endgames: bool,
+    /// Endgame threshold: non-pawn material of both sides (N/B=3, R=5, Q=9).
+    pub endgame_max_material: u16,
+    /// Count castling side/ply and early queen moves from SAN (no replay).
+    pub move_style: bool,
+    /// Classify games from their moves when the ECO header is missing/invalid.
+    pub eco_from_moves: bool,
+    /// Always use the move-based ECO (falls back to the header when no book line matches).
+    pub eco_prefer_computed: bool,
+    /// Opening book TSVs (eco, name, pgn): a file, a directory of `*.tsv`
+    /// files, or a list of either; built-in book when unset.
+    #[serde(deserialize_with = "one_or_many")]
+    pub opening_book: Vec<String>,
+    /// ECO groups TOML (version + ranges); built-in `openings/eco-groups.toml` when unset.
+    pub eco_taxonomy: Option<String>,
+    /// BOT games: "include" | "exclude" | "isolate".
+    pub bot_games: BotPolicy,
+    /// Rating histograms and p10..p90 quantiles per month and speed.
+    pub rating_quantiles: bool,
+    /// HyperLogLog sketches of distinct White/Black usernames per month.
+    pub distinct_players: bool,
+    /// Also sketch per (month, eco_group, speed).
+    pub distinct_players_by_group: bool,
+    /// HLL precision p (2^p registers, error ≈ 1.04 / sqrt(2^p)); 4..=14.
+    pub hll_precision: u8,
+    /// Bounded top-k of the most active players and most frequent `Opening`
+    /// names per month and speed.
+    pub heavy_hitters: bool,
+    /// Rows kept per (month, speed) in `top_players` / `top_openings`.
+    pub top_k: usize,
+    /// Items tracked per summary (more = more accurate, more memory).
+    pub top_capacity: usize,
+    /// Split each month's players into first-timers and returning players
+    /// (`player_first_seen`, `player_months` / `player_cohorts`; spills past `cohort::SPILL_AT` player-months).
+    pub cohorts: bool,
+    /// Skip games whose `Site` ID was already seen: "off" | "exact" | "bloom".
+    pub dedup: DedupMode,
+    /// Bloom filter false-positive rate (unique games wrongly skipped).
+    pub dedup_fp_rate: f64,
+    /// Games the Bloom filter is sized for; the rate degrades beyond it.
+    pub dedup_capacity: u64,
+    /// Keep the seen-set across runs in this file (per run when unset).
+    pub dedup_file: Option<String>,
+    /// Seen-set shared by every pass of the run, set by `Config::init_dedup`.
+    #[serde(skip)]
+    pub seen_games: Option<Arc<Mutex<SeenGames>>>,
+    /// Partition `aggregates` on Postgres: "none" | "month" | "year".
+    pub pg_partition_by: PgPartitioning,
+    /// Preview limits, set from the CLI.
+    #[serde(skip)]
+    pub preview: Preview,
+    /// Add an `eco_name` column (the group's name) to the main CSV.
+    pub csv_eco_names: bool,
+    /// Keep unfinished games ("*") out of `games` (still counted in `unfinished`).
+    pub exclude_unfinished: bool,
+    /// Header filter expression (see `filter.rs`); CLI `--filter` overrides.
+    pub filter: Option<String>,
+    /// Parsed `filter`, set by `Config::compile_filter`.
+    #[serde(skip)]
+    pub compiled_filter: Option<Filter>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             bucket_size: 200,
+            bucket_edges: None,
             list_url: "https://database.lichess.org/standard/list.txt".to_string(),
             batch_size: 1000,
             db_batch_rows: 1000,
             rayon_threads: None,
+            draw_reasons: false,
+            endgames: false,
+            endgame_max_material: 13,
+            move_style: false,
+            eco_from_moves: false,
+            eco_prefer_computed: false,
+            opening_book: Vec::new(),
+            eco_taxonomy: None,
+            bot_games: BotPolicy::Include,
+            rating_quantiles: false,
+            distinct_players: false,
+            distinct_players_by_group: false,
+            hll_precision: 12,
+            heavy_hitters: false,
+            top_k: 20,
+            top_capacity: 1000,
+            cohorts: false,
+            dedup: DedupMode::Off,
+            dedup_fp_rate: 0.001,
+            dedup_capacity: 10_000_000,
+            dedup_file: None,
+            seen_games: None,
+            pg_partition_by: PgPartitioning::None,
+            preview: Preview::default(),
+            csv_eco_names: false,
+            exclude_unfinished: false,
+            filter: None,
+            compiled_filter: None,
         }
     }
 }
 
 impl Config {
-    pub fn load() -> Self {
-        match std::fs::read_to_string("config.toml") {
-            Ok(s) => toml::from_str(&s).unwrap_or_else(|_| Self::default()),
-            Err(_) => Self::default(),
+    /// Settings from `config.toml` in the working directory; the defaults when
+    /// there is none, an error when it can't be read or parsed.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_from(Path::new("config.toml"))
+    }
+
+    fn load_from(path: &Path) -> anyhow::Result<Self> {
+        let src = match std::fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(anyhow::Error::new(e).context(format!("reading {}", path.display()))),
+        };
+        toml::from_str(&src).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Rating bucket layout (`bucket_edges` when set, else `bucket_size`).
+    pub fn buckets(&self) -> anyhow::Result<Buckets> {
+        match &self.bucket_edges {
+            Some(edges) => Buckets::edges(edges.clone()),
+            None => Ok(Buckets::Every(self.bucket_size)),
+        }
+    }
+
+    /// Bucket of one rating under `buckets()` (validated at startup).
+    pub fn elo_bucket(&self, elo: Option<u16>) -> u16 {
+        match &self.bucket_edges {
+            Some(edges) => elo_bucket_with_edges(elo, edges),
+            None => elo_bucket_with_size(elo, self.bucket_size),
+        }
+    }
+
+    /// These settings with a fresh per-run seen-set, for passes over games the
+    /// stored one already has (re-aggregated months, `--rebuild-cohorts`).
+    pub fn with_fresh_dedup(&self) -> Config {
+        let seen = SeenGames::new(self.dedup, self.dedup_capacity, self.dedup_fp_rate);
+        Config { dedup_file: None, seen_games: seen.map(|s| Arc::new(Mutex::new(s))), ..self.clone() }
+    }
+
+    /// Settings for `--rebuild-cohorts`: cohorts on, the slow per-game extras
+    /// off, and a fresh per-run seen-set (the stored one has every game already).
+    pub fn cohorts_only(&self) -> Config {
+        Config {
+            cohorts: true,
+            draw_reasons: false,
+            endgames: false,
+            move_style: false,
+            rating_quantiles: false,
+            distinct_players: false,
+            heavy_hitters: false,
+            ..self.with_fresh_dedup()
+        }
+    }
+
+    /// Create the seen-set, or load it from `dedup_file` when that exists.
+    pub fn init_dedup(&mut self) -> anyhow::Result<()> {
+        let seen = match self.dedup_file.as_deref().map(Path::new).filter(|p| p.exists()) {
+            Some(path) if self.dedup != DedupMode::Off => Some(SeenGames::load(path, self.dedup)?),
+            _ => SeenGames::new(self.dedup, self.dedup_capacity, self.dedup_fp_rate),
+        };
+        self.seen_games = seen.map(|s| Arc::new(Mutex::new(s)));
+        Ok(())
+    }
+
+    /// Start recording the games the next pass adds to the seen-set, for
+    /// `rollback_dedup` if that pass isn't saved.
+    pub fn begin_dedup_pass(&self) {
+        if let Some(seen) = self.seen_games.as_ref() {
+            seen.lock().unwrap().begin_pass();
+        }
+    }
+
+    /// Forget the games seen since `begin_dedup_pass`.
+    pub fn rollback_dedup(&self) {
+        if let Some(seen) = self.seen_games.as_ref() {
+            seen.lock().unwrap().rollback();
+        }
+    }
+
+    /// Write the seen-set back to `dedup_file` (after a saved ingestion).
+    pub fn persist_dedup(&self) -> anyhow::Result<()> {
+        if let (Some(path), Some(seen)) = (self.dedup_file.as_deref(), self.seen_games.as_ref()) {
+            seen.lock().unwrap().save(Path::new(path))?;
+        }
+        Ok(())
+    }
+
+    /// Parse the filter (`cli_filter` wins over config.toml).
+    pub fn compile_filter(&mut self, cli_filter: Option<&str>) -> anyhow::Result<()> {
+        if let Some(f) = cli_filter {
+            self.filter = Some(f.to_string());
+        }
+        self.compiled_filter = match self.filter.as_deref() {
+            Some(src) if !src.trim().is_empty() => Some(Filter::parse(src)?),
+            _ => None,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_text(name: &str, text: &str) -> anyhow::Result<Config> {
+        let path = std::env::temp_dir().join(format!("lta-config-{}-{}.toml", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        let cfg = Config::load_from(&path);
+        std::fs::remove_file(&path).unwrap();
+        cfg
+    }
+
+    #[test]
+    fn missing_file_gives_the_defaults() {
+        let path = std::env::temp_dir().join(format!("lta-config-{}-missing.toml", std::process::id()));
+        let cfg = Config::load_from(&path).unwrap();
+        assert_eq!(cfg.bucket_size, Config::default().bucket_size);
+    }
+
+    #[test]
+    fn settings_are_read() {
+        let cfg = load_text("ok", "bucket_size = 100\ndedup = \"exact\"\nopening_book = \"book.tsv\"\n").unwrap();
+        assert_eq!(cfg.bucket_size, 100);
+        assert_eq!(cfg.dedup, DedupMode::Exact);
+        assert_eq!(cfg.opening_book, ["book.tsv"]);
+    }
+
+    #[test]
+    fn unparsable_file_is_an_error() {
+        for (name, text) in [("syntax", "bucket_size = \n"), ("type", "bucket_size = \"wide\"\n"), ("enum", "dedup = \"fuzzy\"\n")] {
+            let err = load_text(name, text).expect_err("config was accepted");
+            assert!(format!("{:#}", err).contains("parsing"), "{}: {:#}", name, err);
         }
     }
 }
@@ -1,13 +1,21 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::time::Instant;
 
 use anyhow::Context;
-use sqlx::{PgPool, SqlitePool, MySqlPool};
+use sqlx::{MySql, PgPool, Postgres, Sqlite, SqlitePool, MySqlPool, Transaction};
+use sqlx::migrate::Migrator;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::mysql::MySqlPoolOptions;
 
-use crate::aggregator::AggMap;
+use crate::cohort::{self, CohortMap, PlayerMonth};
+use crate::config::PgPartitioning;
+use crate::eco;
+use crate::aggregator::{AggMap, Aggregation, EndgameMap, PlayerMap, RatingMap, TopMap};
+use crate::model::{MonthSpeed, PlayerCell, COUNTER_COLUMNS, RATING_QUANTILES};
+use crate::pgn::{Buckets, SPEEDS};
+use crate::sketch::{EloHistogram, Hll, TopK};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Backend { Sqlite, Postgres, Mysql }
@@ -18,6 +26,35 @@ pub enum Db {
     Mysql(MySqlPool),
 }
 
+/// An open transaction. Every write below goes through one, so callers decide
+/// what commits together (a whole month in remote save mode).
+pub enum Tx {
+    Sqlite(Transaction<'static, Sqlite>),
+    Postgres(Transaction<'static, Postgres>),
+    Mysql(Transaction<'static, MySql>),
+}
+
+impl Db {
+    pub async fn begin(&self) -> anyhow::Result<Tx> {
+        Ok(match self {
+            Db::Sqlite(pool) => Tx::Sqlite(pool.begin().await?),
+            Db::Postgres(pool) => Tx::Postgres(pool.begin().await?),
+            Db::Mysql(pool) => Tx::Mysql(pool.begin().await?),
+        })
+    }
+}
+
+impl Tx {
+    pub async fn commit(self) -> anyhow::Result<()> {
+        match self {
+            Tx::Sqlite(tx) => tx.commit().await?,
+            Tx::Postgres(tx) => tx.commit().await?,
+            Tx::Mysql(tx) => tx.commit().await?,
+        }
+        Ok(())
+    }
+}
+
 fn env_var(name: &str, default: &str) -> String {
     std::env::var(name).unwrap_or_else(|_| default.to_string())
 }
@@ -75,28 +112,63 @@ pub async fn connect_from_env() -> anyhow::Result<Db> {
     Ok(db)
 }
 
+/// One migration set per backend (`migrations/<backend>`). Versions shared by
+/// every backend keep identical files, so databases migrated from the old
+/// common directory still match their recorded checksums.
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/postgres");
+static MYSQL_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/mysql");
+
 pub async fn run_migrations(db: &Db) -> anyhow::Result<()> {
     vprintln!("db:migrate: start");
     let t0 = Instant::now();
     match db {
-        Db::Sqlite(pool) => sqlx::migrate!("./migrations").run(pool).await?,
+        Db::Sqlite(pool) => SQLITE_MIGRATIONS.run(pool).await?,
         Db::Postgres(pool) => {
             // serialize migrations on PG to avoid race (cheap advisory lock)
             let mut conn = pool.acquire().await?;
             let lock_key: i64 = 0x4A_67_67_72_45_31;
             sqlx::query("SELECT pg_advisory_lock($1)").bind(lock_key).execute(&mut *conn).await?;
-            let res = sqlx::migrate!("./migrations").run(&mut *conn).await;
+            let res = POSTGRES_MIGRATIONS.run(&mut *conn).await;
             let _ = sqlx::query("SELECT pg_advisory_unlock($1)").bind(lock_key).execute(&mut *conn).await;
             res?;
         }
         Db::Mysql(pool) => {
-            sqlx::migrate!("./migrations").run(pool).await?;
+            MYSQL_MIGRATIONS.run(pool).await?;
         }
     }
     vprintln!("db:migrate: done in {:.3}s", t0.elapsed().as_secs_f64());
     Ok(())
 }
 
+/// A throwaway SQLite database in the temp directory, migrated and removed
+/// on drop: the tables a dry run needs without touching DATABASE_URL.
+pub struct Scratch {
+    pub db: Db,
+    path: PathBuf,
+}
+
+impl Scratch {
+    pub async fn open(name: &str) -> anyhow::Result<Scratch> {
+        let path = std::env::temp_dir().join(format!("lta-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .with_context(|| format!("creating scratch database {}", path.display()))?;
+        let scratch = Scratch { db: Db::Sqlite(pool), path };
+        run_migrations(&scratch.db).await?;
+        Ok(scratch)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
 pub async fn already_ingested_months(db: &Db) -> anyhow::Result<HashSet<String>> {
     let t0 = Instant::now();
     let months: Vec<String> = match db {
@@ -127,88 +199,216 @@ pub async fn already_ingested_months(db: &Db) -> anyhow::Result<HashSet<String>>
 }
 
 pub async fn mark_ingestion_start(
-    db: &Db, month: &str, url: &str, started_iso: &str
+    db: &Db, month: &str, url: &str, started_iso: &str, filter: Option<&str>
 ) -> anyhow::Result<()> {
     vprintln!("db:mark start {} {}", month, url);
     match db {
         Db::Sqlite(pool) => {
             sqlx::query(
-                "INSERT INTO ingestions (month, url, started_at, status)
-                 VALUES (?, ?, ?, 'started')
+                "INSERT INTO ingestions (month, url, started_at, status, game_filter, attempts)
+                 VALUES (?, ?, ?, 'started', ?, 1)
                  ON CONFLICT(month) DO UPDATE SET
                    url=excluded.url,
                    started_at=excluded.started_at,
-                   status='started'"
+                   status='started',
+                   game_filter=excluded.game_filter,
+                   attempts=ingestions.attempts + 1"
             )
-            .bind(month).bind(url).bind(started_iso)
+            .bind(month).bind(url).bind(started_iso).bind(filter)
             .execute(pool).await?;
         }
         Db::Postgres(pool) => {
             sqlx::query(
-                "INSERT INTO ingestions (month, url, started_at, status)
-                 VALUES ($1, $2, $3, 'started')
+                "INSERT INTO ingestions (month, url, started_at, status, game_filter, attempts)
+                 VALUES ($1, $2, $3, 'started', $4, 1)
                  ON CONFLICT (month) DO UPDATE SET
                    url = EXCLUDED.url,
                    started_at = EXCLUDED.started_at,
-                   status = 'started'"
+                   status = 'started',
+                   game_filter = EXCLUDED.game_filter,
+                   attempts = ingestions.attempts + 1"
             )
-            .bind(month).bind(url).bind(started_iso)
+            .bind(month).bind(url).bind(started_iso).bind(filter)
             .execute(pool).await?;
         }
         Db::Mysql(pool) => {
             sqlx::query(
-                "INSERT INTO ingestions (month, url, started_at, status)
-                 VALUES (?, ?, ?, 'started')
+                "INSERT INTO ingestions (month, url, started_at, status, game_filter, attempts)
+                 VALUES (?, ?, ?, 'started', ?, 1)
                  ON DUPLICATE KEY UPDATE
                    url = VALUES(url),
                    started_at = VALUES(started_at),
-                   status = 'started'"
+                   status = 'started',
+                   game_filter = VALUES(game_filter),
+                   attempts = attempts + 1"
             )
-            .bind(month).bind(url).bind(started_iso)
+            .bind(month).bind(url).bind(started_iso).bind(filter)
             .execute(pool).await?;
         }
     }
     Ok(())
 }
 
-pub async fn mark_ingestion_finish(
-    db: &Db, month: &str, games: i64, duration_ms: i64, status: &str, finished_iso: &str
+/// Record a month whose ingestion errored (its transaction was rolled back);
+/// `already_ingested_months` only skips successes, so it's retried next run.
+pub async fn mark_ingestion_failed(
+    db: &Db, month: &str, error: &str, finished_iso: &str
 ) -> anyhow::Result<()> {
-    vprintln!("db:mark finish {} games={} dur_ms={} status={}", month, games, duration_ms, status);
+    vprintln!("db:mark failed {} {}", month, error);
     match db {
         Db::Sqlite(pool) => {
+            sqlx::query("UPDATE ingestions SET status = 'failed', error = ?, finished_at = ? WHERE month = ?")
+                .bind(error).bind(finished_iso).bind(month)
+                .execute(pool).await?;
+        }
+        Db::Postgres(pool) => {
+            sqlx::query("UPDATE ingestions SET status = 'failed', error = $2, finished_at = $3 WHERE month = $1")
+                .bind(month).bind(error).bind(finished_iso)
+                .execute(pool).await?;
+        }
+        Db::Mysql(pool) => {
+            sqlx::query("UPDATE ingestions SET status = 'failed', error = ?, finished_at = ? WHERE month = ?")
+                .bind(error).bind(finished_iso).bind(month)
+                .execute(pool).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Tables keyed by month that an ingestion writes.
+const MONTH_TABLES: &[&str] = &[
+    "aggregates",
+    "endgames",
+    "rating_quantiles",
+    "rating_histograms",
+    "player_sketches",
+    "top_players",
+    "top_openings",
+    "top_summaries",
+    "player_cohorts",
+    "player_months",
+    "player_month_ecos",
+];
+
+/// Drop everything a previous ingestion of `month` wrote, so the month can be
+/// rewritten in the same transaction (replace instead of add).
+pub async fn delete_month(tx: &mut Tx, month: &str) -> anyhow::Result<()> {
+    vprintln!("db:delete month {}", month);
+    for table in MONTH_TABLES {
+        match tx {
+            Tx::Sqlite(tx) => {
+                sqlx::query(&format!("DELETE FROM {} WHERE month = ?", table)).bind(month).execute(&mut **tx).await?;
+            }
+            Tx::Postgres(tx) => {
+                if *table == "aggregates" {
+                    if let Some(partition) = pg_month_partition(tx, month).await? {
+                        // emptied at once, no row-by-row delete (and, unlike a
+                        // DETACH, without locking the whole table until commit)
+                        sqlx::query(&format!("TRUNCATE {}", partition)).execute(&mut **tx).await?;
+                        continue;
+                    }
+                }
+                sqlx::query(&format!("DELETE FROM {} WHERE month = $1", table)).bind(month).execute(&mut **tx).await?;
+            }
+            Tx::Mysql(tx) => {
+                sqlx::query(&format!("DELETE FROM {} WHERE month = ?", table)).bind(month).execute(&mut **tx).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Per-month counters, ECO taxonomy and run manifest written when an ingestion finishes.
+pub struct FinishStats {
+    pub games: i64,
+    pub bot_excluded: i64,
+    pub human_excluded: i64,
+    pub filtered_out: i64,
+    pub duplicates: i64,
+    pub duration_ms: i64,
+    pub eco_taxonomy: &'static str, // version the month's rows were built with
+    pub manifest: String,           // TOML, see `manifest::render`
+}
+
+pub async fn mark_ingestion_finish(
+    tx: &mut Tx, month: &str, stats: &FinishStats, status: &str, finished_iso: &str
+) -> anyhow::Result<()> {
+    vprintln!(
+        "db:mark finish {} games={} bot_excluded={} human_excluded={} filtered_out={} duplicates={} dur_ms={} status={}",
+        month, stats.games, stats.bot_excluded, stats.human_excluded, stats.filtered_out, stats.duplicates, stats.duration_ms, status
+    );
+    match tx {
+        Tx::Sqlite(tx) => {
             sqlx::query(
                 "UPDATE ingestions
-                   SET games = ?, duration_ms = ?, status = ?, finished_at = ?
+                   SET games = ?, bot_excluded = ?, human_excluded = ?, filtered_out = ?, duplicates = ?, duration_ms = ?, status = ?,
+                       finished_at = ?, error = NULL, eco_taxonomy = ?, manifest = ?
                  WHERE month = ?"
             )
-            .bind(games).bind(duration_ms).bind(status).bind(finished_iso).bind(month)
-            .execute(pool).await?;
+            .bind(stats.games).bind(stats.bot_excluded).bind(stats.human_excluded).bind(stats.filtered_out).bind(stats.duplicates).bind(stats.duration_ms)
+            .bind(status).bind(finished_iso).bind(stats.eco_taxonomy).bind(&stats.manifest).bind(month)
+            .execute(&mut **tx).await?;
         }
-        Db::Postgres(pool) => {
+        Tx::Postgres(tx) => {
             sqlx::query(
                 "UPDATE ingestions
-                   SET games = $2, duration_ms = $3, status = $4, finished_at = $5
+                   SET games = $2, bot_excluded = $3, human_excluded = $4, filtered_out = $5, duplicates = $6, duration_ms = $7, status = $8,
+                       finished_at = $9, error = NULL, eco_taxonomy = $10, manifest = $11
                  WHERE month = $1"
             )
-            .bind(month).bind(games).bind(duration_ms).bind(status).bind(finished_iso)
-            .execute(pool).await?;
+            .bind(month).bind(stats.games).bind(stats.bot_excluded).bind(stats.human_excluded).bind(stats.filtered_out).bind(stats.duplicates).bind(stats.duration_ms)
+            .bind(status).bind(finished_iso).bind(stats.eco_taxonomy).bind(&stats.manifest)
+            .execute(&mut **tx).await?;
         }
-        Db::Mysql(pool) => {
+        Tx::Mysql(tx) => {
             sqlx::query(
                 "UPDATE ingestions
-                   SET games = ?, duration_ms = ?, status = ?, finished_at = ?
+                   SET games = ?, bot_excluded = ?, human_excluded = ?, filtered_out = ?, duplicates = ?, duration_ms = ?, status = ?,
+                       finished_at = ?, error = NULL, eco_taxonomy = ?, manifest = ?
                  WHERE month = ?"
             )
-            .bind(games).bind(duration_ms).bind(status).bind(finished_iso).bind(month)
-            .execute(pool).await?;
+            .bind(stats.games).bind(stats.bot_excluded).bind(stats.human_excluded).bind(stats.filtered_out).bind(stats.duplicates).bind(stats.duration_ms)
+            .bind(status).bind(finished_iso).bind(stats.eco_taxonomy).bind(&stats.manifest).bind(month)
+            .execute(&mut **tx).await?;
         }
     }
     Ok(())
 }
 
-pub async fn bulk_upsert_aggregates(
-    db: &Db,
+/// Every table of one pass (aggregates and side tables; cohorts are separate).
+pub async fn write_aggregation(
+    tx: &mut Tx,
+    agg: &Aggregation,
+    top_k: usize,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    bulk_upsert_aggregates(tx, &agg.map, cfg_chunk_size).await.context("aggregates")?;
+    bulk_upsert_endgames(tx, &agg.endgames, cfg_chunk_size).await.context("endgames")?;
+    upsert_rating_quantiles(tx, &agg.ratings, cfg_chunk_size).await.context("rating_quantiles")?;
+    upsert_player_sketches(tx, &agg.players, cfg_chunk_size).await.context("player_sketches")?;
+    replace_top_lists(tx, agg, top_k, cfg_chunk_size).await.context("top lists")?;
+    Ok(())
+}
+
+/// Primary key of `aggregates`.
+const AGG_KEY_COLUMNS: &str = "month, speed, eco_group, white_bucket, black_bucket, white_title, black_title";
+
+/// Column list shared by every backend's INSERT into `aggregates`.
+fn aggregate_insert_columns() -> String {
+    format!("{}, {}", AGG_KEY_COLUMNS, COUNTER_COLUMNS.join(", "))
+}
+
+/// `col = <target>.col + <new>.col, ...` over every counter column.
+fn additive_updates(target: &str, new: impl Fn(&str) -> String) -> String {
+    COUNTER_COLUMNS
+        .iter()
+        .map(|c| format!("{c} = {target}{c} + {}", new(c)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+async fn bulk_upsert_aggregates(
+    tx: &mut Tx,
     map: &AggMap,
     cfg_chunk_size: usize,
 ) -> anyhow::Result<()> {
@@ -218,151 +418,2290 @@ pub async fn bulk_upsert_aggregates(
     rows.sort_by(|(ka, _), (kb, _)| {
         ka.month
             .cmp(&kb.month)
+            .then_with(|| ka.speed.cmp(kb.speed))
             .then_with(|| ka.eco_group.cmp(&kb.eco_group))
             .then_with(|| ka.w_bucket.cmp(&kb.w_bucket))
             .then_with(|| ka.b_bucket.cmp(&kb.b_bucket))
+            .then_with(|| ka.w_title.cmp(&kb.w_title))
+            .then_with(|| ka.b_title.cmp(&kb.b_title))
     });
 
-    match db {
+    let cols = aggregate_insert_columns();
+    let n_params = 7 + COUNTER_COLUMNS.len();
+
+    match tx {
         // ------------- SQLite: batched upsert with accumulation -------------
-        Db::Sqlite(pool) => {
-            // 8 params per row; SQLite default param limit ~999 → 999/8 ~= 124
-            let max_sqlite_rows = 120usize;
+        Tx::Sqlite(tx) => {
+            // SQLite default param limit ~999 → rows per statement = 999 / params per row
+            let max_sqlite_rows = 999 / n_params;
             let chunk = cfg_chunk_size.min(max_sqlite_rows).max(1);
 
             vprintln!("db:upsert (sqlite) rows={} chunk={}", rows.len(), chunk);
             let t0 = std::time::Instant::now();
-            let mut tx = pool.begin().await?;
 
+            let placeholders = format!("({})", vec!["?"; n_params].join(","));
             for chunk_rows in rows.chunks(chunk) {
                 // INSERT ... ON CONFLICT (...) DO UPDATE SET col = col + excluded.col
-                let mut sql = String::from(
-                    "INSERT INTO aggregates \
-                     (month, eco_group, white_bucket, black_bucket, games, white_wins, black_wins, draws) \
-                     VALUES "
-                );
+                let mut sql = format!("INSERT INTO aggregates ({}) VALUES ", cols);
                 for i in 0..chunk_rows.len() {
                     if i > 0 { sql.push(','); }
-                    sql.push_str("(?,?,?,?,?,?,?,?)");
+                    sql.push_str(&placeholders);
                 }
-                sql.push_str(
-                    " ON CONFLICT (month, eco_group, white_bucket, black_bucket) DO UPDATE SET \
-                      games = aggregates.games + excluded.games, \
-                      white_wins = aggregates.white_wins + excluded.white_wins, \
-                      black_wins = aggregates.black_wins + excluded.black_wins, \
-                      draws = aggregates.draws + excluded.draws"
-                );
+                sql.push_str(&format!(" ON CONFLICT ({}) DO UPDATE SET ", AGG_KEY_COLUMNS));
+                sql.push_str(&additive_updates("aggregates.", |c| format!("excluded.{c}")));
 
                 let mut q = sqlx::query(&sql);
                 for (k, c) in chunk_rows {
                     q = q
                         .bind(&k.month)
+                        .bind(k.speed)
                         .bind(&k.eco_group)
                         .bind(k.w_bucket as i64)
                         .bind(k.b_bucket as i64)
-                        .bind(c.games as i64)
-                        .bind(c.white_wins as i64)
-                        .bind(c.black_wins as i64)
-                        .bind(c.draws as i64);
+                        .bind(&k.w_title)
+                        .bind(&k.b_title);
+                    for v in c.values() {
+                        q = q.bind(v as i64);
+                    }
                 }
-                q.execute(&mut *tx).await?;
+                q.execute(&mut **tx).await?;
             }
-
-            tx.commit().await?;
             vprintln!("db:upsert (sqlite) done in {:.3}s", t0.elapsed().as_secs_f64());
         }
 
-        // ------------- Postgres: accumulate using EXCLUDED + target table -------------
-        Db::Postgres(pool) => {
-            use sqlx::{Postgres, QueryBuilder};
-
-            let chunk = cfg_chunk_size.max(1);
-            vprintln!("db:upsert (postgres) rows={} chunk={}", rows.len(), chunk);
+        // ------------- Postgres: binary COPY into a temp table, then one merge -------------
+        Tx::Postgres(tx) => {
+            vprintln!("db:upsert (postgres) rows={} via COPY", rows.len());
             let t0 = std::time::Instant::now();
 
-            let mut tx = pool.begin().await?;
-            // safe for idempotent “additive” upserts
-            sqlx::query("SET LOCAL synchronous_commit = off").execute(&mut *tx).await?;
-
-            for chunk_rows in rows.chunks(chunk) {
-                vprintln!("db:upsert (postgres) batching {} rows", chunk_rows.len());
-
-                let mut qb = QueryBuilder::<Postgres>::new(
-                    "INSERT INTO aggregates \
-                     (month, eco_group, white_bucket, black_bucket, games, white_wins, black_wins, draws) "
-                );
-
-                qb.push_values(chunk_rows, |mut b, (k, c)| {
-                    b.push_bind(&k.month)
-                        .push_bind(&k.eco_group)
-                        .push_bind(k.w_bucket as i32)
-                        .push_bind(k.b_bucket as i32)
-                        .push_bind(c.games as i64)
-                        .push_bind(c.white_wins as i64)
-                        .push_bind(c.black_wins as i64)
-                        .push_bind(c.draws as i64);
-                });
+            let months: Vec<&str> = rows.iter().map(|(k, _)| k.month.as_str()).collect();
+            create_pg_partitions(tx, &months).await?;
 
-                qb.push(
-                    " ON CONFLICT (month, eco_group, white_bucket, black_bucket) DO UPDATE SET \
-                      games = aggregates.games + EXCLUDED.games, \
-                      white_wins = aggregates.white_wins + EXCLUDED.white_wins, \
-                      black_wins = aggregates.black_wins + EXCLUDED.black_wins, \
-                      draws = aggregates.draws + EXCLUDED.draws"
-                );
+            // temp tables skip the WAL; this one is dropped with the transaction
+            sqlx::query(
+                "CREATE TEMP TABLE IF NOT EXISTS aggregates_stage (LIKE aggregates INCLUDING DEFAULTS) ON COMMIT DROP"
+            )
+            .execute(&mut **tx)
+            .await?;
+            sqlx::query("TRUNCATE aggregates_stage").execute(&mut **tx).await?;
 
-                qb.build().execute(&mut *tx).await?;
+            let mut copy = tx
+                .copy_in_raw(&format!("COPY aggregates_stage ({}) FROM STDIN (FORMAT binary)", cols))
+                .await?;
+            let mut buf = PgBinaryCopy::new();
+            for (k, c) in &rows {
+                buf.tuple(n_params);
+                buf.text(&k.month);
+                buf.text(k.speed);
+                buf.text(&k.eco_group);
+                buf.int4(k.w_bucket as i32);
+                buf.int4(k.b_bucket as i32);
+                buf.text(&k.w_title);
+                buf.text(&k.b_title);
+                for v in c.values() {
+                    buf.int8(v as i64);
+                }
+                if buf.len() >= PG_COPY_CHUNK_BYTES {
+                    copy.send(buf.take()).await?;
+                }
             }
+            copy.send(buf.finish()).await?;
+            copy.finish().await?;
+            vprintln!("db:upsert (postgres) staged in {:.3}s", t0.elapsed().as_secs_f64());
+
+            sqlx::query(&format!(
+                "INSERT INTO aggregates ({cols}) SELECT {cols} FROM aggregates_stage ON CONFLICT ({}) DO UPDATE SET {}",
+                AGG_KEY_COLUMNS,
+                additive_updates("aggregates.", |c| format!("EXCLUDED.{c}"))
+            ))
+            .execute(&mut **tx)
+            .await?;
 
-            tx.commit().await?;
             vprintln!("db:upsert (postgres) done in {:.3}s", t0.elapsed().as_secs_f64());
         }
 
         // ------------- MySQL/TiDB: accumulate using target col + VALUES() -------------
-        Db::Mysql(pool) => {
+        Tx::Mysql(tx) => {
             use sqlx::{MySql, QueryBuilder};
 
             let chunk = cfg_chunk_size.max(1);
             vprintln!("db:upsert (mysql) rows={} chunk={}", rows.len(), chunk);
             let t0 = std::time::Instant::now();
 
-            let mut tx = pool.begin().await?;
+            // Accumulate into existing row
+            let on_dup = format!(
+                " ON DUPLICATE KEY UPDATE {}",
+                additive_updates("", |c| format!("VALUES({c})"))
+            );
 
             for chunk_rows in rows.chunks(chunk) {
                 vprintln!("db:upsert (mysql) batching {} rows", chunk_rows.len());
 
-                let mut qb = QueryBuilder::<MySql>::new(
-                    "INSERT INTO aggregates \
-                     (month, eco_group, white_bucket, black_bucket, games, white_wins, black_wins, draws) "
-                );
+                let mut qb = QueryBuilder::<MySql>::new(format!("INSERT INTO aggregates ({}) ", cols));
 
                 qb.push_values(chunk_rows, |mut b, (k, c)| {
                     b.push_bind(&k.month)
+                        .push_bind(k.speed)
                         .push_bind(&k.eco_group)
                         .push_bind(k.w_bucket as i32)
                         .push_bind(k.b_bucket as i32)
-                        .push_bind(c.games as i64)
-                        .push_bind(c.white_wins as i64)
-                        .push_bind(c.black_wins as i64)
-                        .push_bind(c.draws as i64);
+                        .push_bind(&k.w_title)
+                        .push_bind(&k.b_title);
+                    for v in c.values() {
+                        b.push_bind(v as i64);
+                    }
+                });
+
+                qb.push(&on_dup);
+
+                qb.build().execute(&mut **tx).await?;
+            }
+            vprintln!("db:upsert (mysql) done in {:.3}s", t0.elapsed().as_secs_f64());
+        }
+    }
+
+    Ok(())
+}
+
+/// Layout of `aggregates` as the catalog has it: LIST = by month, RANGE = by year.
+async fn pg_partitioning(tx: &mut Transaction<'static, Postgres>) -> anyhow::Result<PgPartitioning> {
+    let strategy: Option<String> = sqlx::query_scalar(
+        "SELECT partstrat::text FROM pg_partitioned_table WHERE partrelid = to_regclass('aggregates')"
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    Ok(match strategy.as_deref() {
+        Some("l") => PgPartitioning::Month,
+        Some("r") => PgPartitioning::Year,
+        _ => PgPartitioning::None,
+    })
+}
+
+/// Name and bound of the partition holding `month`. Months that aren't
+/// "YYYY-MM" (e.g., "unknown") go to the default partition.
+fn pg_partition(by: PgPartitioning, month: &str) -> (String, String) {
+    let b = month.as_bytes();
+    let valid = b.len() == 7 && b[4] == b'-' && month.chars().enumerate().all(|(i, c)| i == 4 || c.is_ascii_digit());
+    match by {
+        PgPartitioning::Month if valid => (
+            format!("aggregates_m{}", month.replace('-', "_")),
+            format!("FOR VALUES IN ('{}')", month),
+        ),
+        PgPartitioning::Year if valid => {
+            let year: u32 = month[..4].parse().unwrap_or(0);
+            (format!("aggregates_y{}", year), format!("FOR VALUES FROM ('{}') TO ('{}')", year, year + 1))
+        }
+        _ => ("aggregates_default".to_string(), "DEFAULT".to_string()),
+    }
+}
+
+/// The existing partition holding only `month`, when `aggregates` is partitioned by month.
+async fn pg_month_partition(tx: &mut Transaction<'static, Postgres>, month: &str) -> anyhow::Result<Option<String>> {
+    if pg_partitioning(tx).await? != PgPartitioning::Month { return Ok(None); }
+    let (name, bound) = pg_partition(PgPartitioning::Month, month);
+    if bound == "DEFAULT" { return Ok(None); }
+    let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(&name)
+        .fetch_one(&mut **tx)
+        .await?;
+    Ok(exists.then_some(name))
+}
+
+/// Create the partitions `months` need (no-op when `aggregates` isn't partitioned).
+async fn create_pg_partitions(tx: &mut Transaction<'static, Postgres>, months: &[&str]) -> anyhow::Result<()> {
+    let by = pg_partitioning(tx).await?;
+    if by == PgPartitioning::None { return Ok(()); }
+    let mut partitions: Vec<(String, String)> = months.iter().map(|m| pg_partition(by, m)).collect();
+    partitions.sort();
+    partitions.dedup();
+    for (name, bound) in partitions {
+        sqlx::query(&format!("CREATE TABLE IF NOT EXISTS {} PARTITION OF aggregates {}", name, bound))
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Bring `aggregates` on Postgres to the configured layout. A plain table is
+/// rebuilt as a partitioned one (rows copied over in one transaction, once);
+/// switching between month and year is refused. Other backends ignore the setting.
+pub async fn apply_pg_partitioning(db: &Db, by: PgPartitioning) -> anyhow::Result<()> {
+    let Db::Postgres(pool) = db else {
+        if by != PgPartitioning::None {
+            eprintln!("ℹ️ pg_partition_by only applies to Postgres; ignored.");
+        }
+        return Ok(());
+    };
+    let mut tx = pool.begin().await?;
+    let current = pg_partitioning(&mut tx).await?;
+    // "none" leaves an already partitioned table alone (writers follow the catalog)
+    if current == by || by == PgPartitioning::None { return Ok(()); }
+    anyhow::ensure!(
+        current == PgPartitioning::None,
+        "aggregates is already partitioned by {:?}; pg_partition_by = {:?} would need a manual rebuild",
+        current,
+        by
+    );
+
+    vprintln!("db:partition aggregates by {:?} (copies every row once)", by);
+    let t0 = Instant::now();
+    sqlx::query("LOCK TABLE aggregates IN ACCESS EXCLUSIVE MODE").execute(&mut *tx).await?;
+    let pk: String = sqlx::query_scalar(
+        "SELECT conname::text FROM pg_constraint WHERE conrelid = 'aggregates'::regclass AND contype = 'p'"
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query("ALTER TABLE aggregates RENAME TO aggregates_unpartitioned").execute(&mut *tx).await?;
+    sqlx::query(&format!("ALTER TABLE aggregates_unpartitioned RENAME CONSTRAINT {} TO aggregates_unpartitioned_pkey", pk))
+        .execute(&mut *tx)
+        .await?;
+    let strategy = if by == PgPartitioning::Month { "LIST" } else { "RANGE" };
+    sqlx::query(&format!(
+        "CREATE TABLE aggregates (LIKE aggregates_unpartitioned INCLUDING DEFAULTS) PARTITION BY {} (month)",
+        strategy
+    ))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(&format!("ALTER TABLE aggregates ADD CONSTRAINT aggregates_pkey PRIMARY KEY ({})", AGG_KEY_COLUMNS))
+        .execute(&mut *tx)
+        .await?;
+
+    let months: Vec<String> = sqlx::query_scalar("SELECT DISTINCT month FROM aggregates_unpartitioned")
+        .fetch_all(&mut *tx)
+        .await?;
+    let months: Vec<&str> = months.iter().map(String::as_str).collect();
+    create_pg_partitions(&mut tx, &months).await?;
+    let copied = sqlx::query("INSERT INTO aggregates SELECT * FROM aggregates_unpartitioned")
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    sqlx::query("DROP TABLE aggregates_unpartitioned").execute(&mut *tx).await?;
+    tx.commit().await?;
+    eprintln!("ℹ️ Partitioned aggregates by {:?} ({} rows moved in {:.1}s).", by, copied, t0.elapsed().as_secs_f64());
+    Ok(())
+}
+
+/// Bytes handed to `COPY ... FROM STDIN` per message.
+const PG_COPY_CHUNK_BYTES: usize = 1 << 20;
+
+/// Postgres binary COPY stream: signature header, tuples of length-prefixed
+/// big-endian fields, -1 trailer. Field encoders must match the column types.
+struct PgBinaryCopy {
+    buf: Vec<u8>,
+}
+
+impl PgBinaryCopy {
+    fn new() -> Self {
+        let mut buf = Vec::with_capacity(PG_COPY_CHUNK_BYTES + 4096);
+        buf.extend_from_slice(b"PGCOPY\n\xff\r\n\0");
+        buf.extend_from_slice(&0i32.to_be_bytes()); // flags
+        buf.extend_from_slice(&0i32.to_be_bytes()); // header extension length
+        Self { buf }
+    }
+
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn tuple(&mut self, fields: usize) {
+        self.buf.extend_from_slice(&(fields as i16).to_be_bytes());
+    }
+
+    fn text(&mut self, s: &str) {
+        self.buf.extend_from_slice(&(s.len() as i32).to_be_bytes());
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn int4(&mut self, v: i32) {
+        self.buf.extend_from_slice(&4i32.to_be_bytes());
+        self.buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn int8(&mut self, v: i64) {
+        self.buf.extend_from_slice(&8i32.to_be_bytes());
+        self.buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    /// Bytes so far (to send), leaving an empty buffer.
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.buf.extend_from_slice(&(-1i16).to_be_bytes());
+        self.buf
+    }
+}
+
+/// Column value bound for side-table upserts.
+enum Val<'a> {
+    Text(&'a str),
+    OptText(Option<&'a str>),
+    Int(i32),
+    BigInt(i64),
+}
+
+impl Val<'_> {
+    fn count(v: u64) -> Val<'static> {
+        Val::BigInt(v as i64)
+    }
+}
+
+/// How `upsert_rows` treats a row whose key already exists.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum OnConflict {
+    Add,     // count = count + new (like `aggregates`)
+    Replace, // value = new
+}
+
+/// Upsert rows (key columns first, then value columns) into a side table.
+async fn upsert_rows(
+    tx: &mut Tx,
+    table: &str,
+    key_cols: &[&str],
+    value_cols: &[&str],
+    rows: &[Vec<Val<'_>>],
+    cfg_chunk_size: usize,
+    on_conflict: OnConflict,
+) -> anyhow::Result<()> {
+    if rows.is_empty() { return Ok(()); }
+
+    let cols = key_cols.iter().chain(value_cols).copied().collect::<Vec<_>>().join(", ");
+    let keys = key_cols.join(", ");
+    let n_params = key_cols.len() + value_cols.len();
+    let updates = |target: &str, new: &dyn Fn(&str) -> String| {
+        value_cols
+            .iter()
+            .map(|c| match on_conflict {
+                OnConflict::Add => format!("{c} = {target}{c} + {}", new(c)),
+                OnConflict::Replace => format!("{c} = {}", new(c)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    vprintln!("db:upsert {} rows={}", table, rows.len());
+    let t0 = Instant::now();
+
+    match tx {
+        Tx::Sqlite(tx) => {
+            use sqlx::{QueryBuilder, Sqlite};
+            let chunk = cfg_chunk_size.min(999 / n_params).max(1);
+            let tail = format!(
+                " ON CONFLICT ({}) DO UPDATE SET {}",
+                keys,
+                updates(&format!("{table}."), &|c| format!("excluded.{c}"))
+            );
+            for chunk_rows in rows.chunks(chunk) {
+                let mut qb = QueryBuilder::<Sqlite>::new(format!("INSERT INTO {} ({}) ", table, cols));
+                qb.push_values(chunk_rows, |mut b, row| {
+                    for v in row {
+                        match v {
+                            Val::Text(s) => b.push_bind(s.to_string()),
+                            Val::OptText(s) => b.push_bind(s.map(str::to_string)),
+                            Val::Int(i) => b.push_bind(*i),
+                            Val::BigInt(i) => b.push_bind(*i),
+                        };
+                    }
+                });
+                qb.push(&tail);
+                qb.build().execute(&mut **tx).await?;
+            }
+        }
+        Tx::Postgres(tx) => {
+            use sqlx::{Postgres, QueryBuilder};
+            let chunk = cfg_chunk_size.max(1);
+            let tail = format!(
+                " ON CONFLICT ({}) DO UPDATE SET {}",
+                keys,
+                updates(&format!("{table}."), &|c| format!("EXCLUDED.{c}"))
+            );
+            for chunk_rows in rows.chunks(chunk) {
+                let mut qb = QueryBuilder::<Postgres>::new(format!("INSERT INTO {} ({}) ", table, cols));
+                qb.push_values(chunk_rows, |mut b, row| {
+                    for v in row {
+                        match v {
+                            Val::Text(s) => b.push_bind(s.to_string()),
+                            Val::OptText(s) => b.push_bind(s.map(str::to_string)),
+                            Val::Int(i) => b.push_bind(*i),
+                            Val::BigInt(i) => b.push_bind(*i),
+                        };
+                    }
+                });
+                qb.push(&tail);
+                qb.build().execute(&mut **tx).await?;
+            }
+        }
+        Tx::Mysql(tx) => {
+            use sqlx::{MySql, QueryBuilder};
+            let chunk = cfg_chunk_size.max(1);
+            let tail = format!(
+                " ON DUPLICATE KEY UPDATE {}",
+                updates("", &|c| format!("VALUES({c})"))
+            );
+            for chunk_rows in rows.chunks(chunk) {
+                let mut qb = QueryBuilder::<MySql>::new(format!("INSERT INTO {} ({}) ", table, cols));
+                qb.push_values(chunk_rows, |mut b, row| {
+                    for v in row {
+                        match v {
+                            Val::Text(s) => b.push_bind(s.to_string()),
+                            Val::OptText(s) => b.push_bind(s.map(str::to_string)),
+                            Val::Int(i) => b.push_bind(*i),
+                            Val::BigInt(i) => b.push_bind(*i),
+                        };
+                    }
                 });
+                qb.push(&tail);
+                qb.build().execute(&mut **tx).await?;
+            }
+        }
+    }
+
+    vprintln!("db:upsert {} done in {:.3}s", table, t0.elapsed().as_secs_f64());
+    Ok(())
+}
+
+/// Count columns of `endgames`.
+const ENDGAME_COLUMNS: &[&str] = &["games", "white_wins", "black_wins", "draws", "unfinished"];
+
+fn endgame_rows(map: &EndgameMap) -> Vec<Vec<Val<'_>>> {
+    map.iter()
+        .map(|(k, c)| {
+            vec![
+                Val::Text(&k.month),
+                Val::Text(k.speed),
+                Val::Int(k.w_bucket as i32),
+                Val::Int(k.b_bucket as i32),
+                Val::Text(&k.signature),
+                Val::count(c.games),
+                Val::count(c.white_wins),
+                Val::count(c.black_wins),
+                Val::count(c.draws),
+                Val::count(c.unfinished),
+            ]
+        })
+        .collect()
+}
+
+async fn bulk_upsert_endgames(
+    tx: &mut Tx,
+    map: &EndgameMap,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    upsert_rows(tx,
+        "endgames",
+        &["month", "speed", "white_bucket", "black_bucket", "signature"],
+        ENDGAME_COLUMNS,
+        &endgame_rows(map),
+        cfg_chunk_size,
+        OnConflict::Add,
+    )
+    .await
+}
+
+/// Histograms are added to the stored ones; the quantiles of every (month,
+/// speed) this pass saw are then recomputed from the summed histogram.
+async fn upsert_rating_quantiles(
+    tx: &mut Tx,
+    map: &RatingMap,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    upsert_rows(tx, "rating_histograms", &["month", "speed", "elo"], &["ratings"], &histogram_rows(map), cfg_chunk_size, OnConflict::Add)
+        .await?;
+    let cells: Vec<&MonthSpeed> = map.keys().collect();
+    refresh_rating_quantiles(tx, &cells, cfg_chunk_size).await
+}
+
+fn histogram_rows(map: &RatingMap) -> Vec<Vec<Val<'_>>> {
+    map.iter()
+        .flat_map(|(k, hist)| {
+            hist.bins().map(move |(elo, n)| {
+                vec![Val::Text(&k.month), Val::Text(k.speed), Val::Int(elo as i32), Val::count(n)]
+            })
+        })
+        .collect()
+}
+
+/// Stored rating histogram of one (month, speed): (elo, ratings) rows.
+async fn load_rating_histogram(tx: &mut Tx, cell: &MonthSpeed) -> anyhow::Result<Vec<(i32, i64)>> {
+    let rows = match tx {
+        Tx::Sqlite(tx) => {
+            sqlx::query_as("SELECT elo, ratings FROM rating_histograms WHERE month = ? AND speed = ? AND ratings > 0")
+                .bind(&cell.month).bind(cell.speed)
+                .fetch_all(&mut **tx)
+                .await?
+        }
+        Tx::Postgres(tx) => {
+            sqlx::query_as("SELECT elo, ratings FROM rating_histograms WHERE month = $1 AND speed = $2 AND ratings > 0")
+                .bind(&cell.month).bind(cell.speed)
+                .fetch_all(&mut **tx)
+                .await?
+        }
+        Tx::Mysql(tx) => {
+            sqlx::query_as("SELECT elo, ratings FROM rating_histograms WHERE month = ? AND speed = ? AND ratings > 0")
+                .bind(&cell.month).bind(cell.speed)
+                .fetch_all(&mut **tx)
+                .await?
+        }
+    };
+    Ok(rows)
+}
+
+/// Rewrite the `rating_quantiles` rows of `cells` from their stored histograms.
+async fn refresh_rating_quantiles(tx: &mut Tx, cells: &[&MonthSpeed], cfg_chunk_size: usize) -> anyhow::Result<()> {
+    let qs: Vec<f64> = RATING_QUANTILES.iter().map(|(_, q)| *q).collect();
+    let mut quantiles = Vec::with_capacity(cells.len());
+    for &cell in cells {
+        let mut hist = EloHistogram::default();
+        for (elo, n) in load_rating_histogram(tx, cell).await? {
+            hist.add_count(elo as u16, n as u64);
+        }
+        quantiles.push((cell, hist.count(), hist.quantiles(&qs)));
+    }
+    // histograms emptied by taking out a local input (see `remove_local_input`)
+    for (k, _, _) in quantiles.iter().filter(|(_, ratings, _)| *ratings == 0) {
+        match tx {
+            Tx::Sqlite(tx) => {
+                sqlx::query("DELETE FROM rating_quantiles WHERE month = ? AND speed = ?")
+                    .bind(&k.month).bind(k.speed)
+                    .execute(&mut **tx).await?;
+            }
+            Tx::Postgres(tx) => {
+                sqlx::query("DELETE FROM rating_quantiles WHERE month = $1 AND speed = $2")
+                    .bind(&k.month).bind(k.speed)
+                    .execute(&mut **tx).await?;
+            }
+            Tx::Mysql(tx) => {
+                sqlx::query("DELETE FROM rating_quantiles WHERE month = ? AND speed = ?")
+                    .bind(&k.month).bind(k.speed)
+                    .execute(&mut **tx).await?;
+            }
+        }
+    }
+    let rows: Vec<_> = quantiles
+        .iter()
+        .filter(|(_, ratings, _)| *ratings > 0)
+        .map(|(k, ratings, qs)| {
+            let mut row = vec![Val::Text(&k.month), Val::Text(k.speed), Val::count(*ratings)];
+            row.extend(qs.iter().map(|&q| Val::Int(q as i32)));
+            row
+        })
+        .collect();
+    let mut value_cols = vec!["ratings"];
+    value_cols.extend(RATING_QUANTILES.iter().map(|(name, _)| *name));
+    upsert_rows(tx, "rating_quantiles", &["month", "speed"], &value_cols, &rows, cfg_chunk_size, OnConflict::Replace)
+        .await
+}
+
+/// Stored sketches of one month: (eco_group, speed, precision, hex registers).
+async fn load_player_sketches(tx: &mut Tx, month: &str) -> anyhow::Result<Vec<(String, String, i32, String)>> {
+    let rows = match tx {
+        Tx::Sqlite(tx) => {
+            sqlx::query_as(
+                "SELECT eco_group, speed, hll_precision, registers FROM player_sketches WHERE month = ?"
+            )
+            .bind(month)
+            .fetch_all(&mut **tx)
+            .await?
+        }
+        Tx::Postgres(tx) => {
+            sqlx::query_as(
+                "SELECT eco_group, speed, hll_precision, registers FROM player_sketches WHERE month = $1"
+            )
+            .bind(month)
+            .fetch_all(&mut **tx)
+            .await?
+        }
+        Tx::Mysql(tx) => {
+            sqlx::query_as(
+                "SELECT eco_group, speed, hll_precision, registers FROM player_sketches WHERE month = ?"
+            )
+            .bind(month)
+            .fetch_all(&mut **tx)
+            .await?
+        }
+    };
+    Ok(rows)
+}
+
+/// Sketches are unioned with what is already stored (register max), so
+/// re-running a month or adding another input only adds unseen players.
+async fn upsert_player_sketches(
+    tx: &mut Tx,
+    map: &PlayerMap,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    if map.is_empty() { return Ok(()); }
 
-                // Accumulate into existing row
-                qb.push(
-                    " ON DUPLICATE KEY UPDATE \
-                      games = games + VALUES(games), \
-                      white_wins = white_wins + VALUES(white_wins), \
-                      black_wins = black_wins + VALUES(black_wins), \
-                      draws = draws + VALUES(draws)"
+    let mut merged = map.clone();
+    let mut months: Vec<&str> = map.keys().map(|k| k.month.as_str()).collect();
+    months.sort_unstable();
+    months.dedup();
+    for month in months {
+        for (eco_group, speed, p, hex) in load_player_sketches(tx, month).await? {
+            // back to the `&'static str` used in keys ("*" = all speeds)
+            let Some(&speed) = SPEEDS.iter().chain(&["*"]).find(|s| **s == speed) else { continue };
+            let cell = PlayerCell { month: month.to_string(), eco_group, speed };
+            if let Some(hll) = merged.get_mut(&cell) {
+                anyhow::ensure!(
+                    hll.precision() as i32 == p,
+                    "player_sketches {} {} {} has precision {}, config hll_precision gives {}",
+                    cell.month, cell.eco_group, cell.speed, p, hll.precision()
                 );
+                hll.merge(&Hll::from_hex(p as u8, &hex)?);
+            }
+        }
+    }
+
+    let hexes: Vec<(&PlayerCell, String, i64, i32)> = merged
+        .iter()
+        .map(|(k, hll)| (k, hll.to_hex(), hll.estimate().round() as i64, hll.precision() as i32))
+        .collect();
+    let rows: Vec<_> = hexes
+        .iter()
+        .map(|(k, hex, est, p)| {
+            vec![
+                Val::Text(&k.month),
+                Val::Text(&k.eco_group),
+                Val::Text(k.speed),
+                Val::Int(*p),
+                Val::Text(hex),
+                Val::BigInt(*est),
+            ]
+        })
+        .collect();
+    upsert_rows(tx,
+        "player_sketches",
+        &["month", "eco_group", "speed"],
+        &["hll_precision", "registers", "players_estimate"],
+        &rows,
+        cfg_chunk_size,
+        OnConflict::Replace,
+    )
+    .await
+}
+
+/// Stored Space-Saving summary of one (month, speed) list: (item, games, error) rows.
+async fn load_top_summary(tx: &mut Tx, cell: &MonthSpeed, list: &str) -> anyhow::Result<Vec<(String, i64, i64)>> {
+    let rows = match tx {
+        Tx::Sqlite(tx) => {
+            sqlx::query_as("SELECT item, games, error FROM top_summaries WHERE month = ? AND speed = ? AND list = ?")
+                .bind(&cell.month).bind(cell.speed).bind(list)
+                .fetch_all(&mut **tx)
+                .await?
+        }
+        Tx::Postgres(tx) => {
+            sqlx::query_as("SELECT item, games, error FROM top_summaries WHERE month = $1 AND speed = $2 AND list = $3")
+                .bind(&cell.month).bind(cell.speed).bind(list)
+                .fetch_all(&mut **tx)
+                .await?
+        }
+        Tx::Mysql(tx) => {
+            sqlx::query_as("SELECT item, games, error FROM top_summaries WHERE month = ? AND speed = ? AND list = ?")
+                .bind(&cell.month).bind(cell.speed).bind(list)
+                .fetch_all(&mut **tx)
+                .await?
+        }
+    };
+    Ok(rows)
+}
+
+/// Space-Saving summaries are merged with the stored ones (kept up to
+/// `top_capacity` items) and the top-k lists re-ranked from the result; ranks
+/// beyond the new list are deleted.
+async fn replace_top_list(
+    tx: &mut Tx,
+    table: &str,
+    item_col: &str,
+    list: &str,
+    map: &TopMap,
+    top_k: usize,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    let mut merged = Vec::with_capacity(map.len());
+    for (k, top) in map {
+        let stored = load_top_summary(tx, k, list).await?;
+        let mut summary =
+            TopK::from_summary(top.capacity(), stored.into_iter().map(|(item, n, err)| (item, n as u64, err as u64)));
+        summary.merge(top);
+        merged.push((k, summary));
+    }
+    store_top_list(tx, table, item_col, list, &merged, top_k, cfg_chunk_size).await
+}
 
-                qb.build().execute(&mut *tx).await?;
+/// Write back whole summaries (the `capacity` largest items of each) and re-rank
+/// their top-k lists.
+async fn store_top_list(
+    tx: &mut Tx,
+    table: &str,
+    item_col: &str,
+    list: &str,
+    merged: &[(&MonthSpeed, TopK)],
+    top_k: usize,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    for (k, _) in merged {
+        match tx {
+            Tx::Sqlite(tx) => {
+                sqlx::query("DELETE FROM top_summaries WHERE month = ? AND speed = ? AND list = ?")
+                    .bind(&k.month).bind(k.speed).bind(list)
+                    .execute(&mut **tx).await?;
+            }
+            Tx::Postgres(tx) => {
+                sqlx::query("DELETE FROM top_summaries WHERE month = $1 AND speed = $2 AND list = $3")
+                    .bind(&k.month).bind(k.speed).bind(list)
+                    .execute(&mut **tx).await?;
             }
+            Tx::Mysql(tx) => {
+                sqlx::query("DELETE FROM top_summaries WHERE month = ? AND speed = ? AND list = ?")
+                    .bind(&k.month).bind(k.speed).bind(list)
+                    .execute(&mut **tx).await?;
+            }
+        }
+    }
+    let summaries: Vec<_> = merged.iter().map(|(k, top)| (*k, top.top(top.capacity()))).collect();
+    let rows: Vec<_> = summaries
+        .iter()
+        .flat_map(|(k, items)| {
+            items.iter().map(move |(item, games, error)| {
+                vec![
+                    Val::Text(&k.month),
+                    Val::Text(k.speed),
+                    Val::Text(list),
+                    Val::Text(item),
+                    Val::count(*games),
+                    Val::count(*error),
+                ]
+            })
+        })
+        .collect();
+    upsert_rows(tx,
+        "top_summaries",
+        &["month", "speed", "list", "item"],
+        &["games", "error"],
+        &rows,
+        cfg_chunk_size,
+        OnConflict::Replace,
+    )
+    .await?;
 
-            tx.commit().await?;
-            vprintln!("db:upsert (mysql) done in {:.3}s", t0.elapsed().as_secs_f64());
+    let lists: Vec<_> = summaries.iter().map(|(k, items)| (*k, &items[..top_k.min(items.len())])).collect();
+    let rows: Vec<_> = lists
+        .iter()
+        .flat_map(|(k, list)| {
+            list.iter().enumerate().map(move |(rank, (item, games, error))| {
+                vec![
+                    Val::Text(&k.month),
+                    Val::Text(k.speed),
+                    Val::Int(rank as i32 + 1),
+                    Val::Text(item),
+                    Val::count(*games),
+                    Val::count(*error),
+                ]
+            })
+        })
+        .collect();
+    upsert_rows(tx,
+        table,
+        &["month", "speed", "rank_no"],
+        &[item_col, "games", "error"],
+        &rows,
+        cfg_chunk_size,
+        OnConflict::Replace,
+    )
+    .await?;
+
+    for (k, list) in &lists {
+        let n = list.len() as i32;
+        match tx {
+            Tx::Sqlite(tx) => {
+                sqlx::query(&format!("DELETE FROM {} WHERE month = ? AND speed = ? AND rank_no > ?", table))
+                    .bind(&k.month).bind(k.speed).bind(n)
+                    .execute(&mut **tx).await?;
+            }
+            Tx::Postgres(tx) => {
+                sqlx::query(&format!("DELETE FROM {} WHERE month = $1 AND speed = $2 AND rank_no > $3", table))
+                    .bind(&k.month).bind(k.speed).bind(n)
+                    .execute(&mut **tx).await?;
+            }
+            Tx::Mysql(tx) => {
+                sqlx::query(&format!("DELETE FROM {} WHERE month = ? AND speed = ? AND rank_no > ?", table))
+                    .bind(&k.month).bind(k.speed).bind(n)
+                    .execute(&mut **tx).await?;
+            }
         }
     }
+    Ok(())
+}
 
+async fn replace_top_lists(
+    tx: &mut Tx,
+    agg: &Aggregation,
+    top_k: usize,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    for (list, table, item_col) in TOP_LISTS {
+        let map = if list == "player" { &agg.top_players } else { &agg.top_openings };
+        replace_top_list(tx, table, item_col, list, map, top_k, cfg_chunk_size).await?;
+    }
     Ok(())
 }
+
+/// Stored first months of the given (lowercased) usernames.
+pub async fn load_first_seen(tx: &mut Tx, players: &[&str]) -> anyhow::Result<HashMap<String, String>> {
+    let t0 = Instant::now();
+    let mut out = HashMap::new();
+    for chunk in players.chunks(500) {
+        let rows: Vec<(String, String)> = match tx {
+            Tx::Sqlite(tx) => {
+                let sql = format!(
+                    "SELECT player, first_month FROM player_first_seen WHERE player IN ({})",
+                    vec!["?"; chunk.len()].join(", ")
+                );
+                let mut q = sqlx::query_as(&sql);
+                for p in chunk { q = q.bind(*p); }
+                q.fetch_all(&mut **tx).await?
+            }
+            Tx::Postgres(tx) => {
+                sqlx::query_as("SELECT player, first_month FROM player_first_seen WHERE player = ANY($1)")
+                    .bind(chunk)
+                    .fetch_all(&mut **tx)
+                    .await?
+            }
+            Tx::Mysql(tx) => {
+                let sql = format!(
+                    "SELECT player, first_month FROM player_first_seen WHERE player IN ({})",
+                    vec!["?"; chunk.len()].join(", ")
+                );
+                let mut q = sqlx::query_as(&sql);
+                for p in chunk { q = q.bind(*p); }
+                q.fetch_all(&mut **tx).await?
+            }
+        };
+        out.extend(rows);
+    }
+    vprintln!("db:loaded first-seen months = {} of {} players in {:.3}s", out.len(), players.len(), t0.elapsed().as_secs_f64());
+    Ok(out)
+}
+
+/// `first_seen` already holds min(stored, this pass), so rows are replaced.
+pub async fn upsert_first_seen(
+    tx: &mut Tx,
+    first_seen: &HashMap<String, String>,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    let rows: Vec<_> = first_seen
+        .iter()
+        .map(|(player, month)| vec![Val::Text(player), Val::Text(month)])
+        .collect();
+    upsert_rows(tx, "player_first_seen", &["player"], &["first_month"], &rows, cfg_chunk_size, OnConflict::Replace)
+        .await
+}
+
+/// Count columns of `player_cohorts`.
+const COHORT_COLUMNS: &[&str] = &["players", "games"];
+
+fn cohort_rows(map: &CohortMap) -> Vec<Vec<Val<'_>>> {
+    map.iter()
+        .map(|(k, c)| {
+            vec![
+                Val::Text(&k.month),
+                Val::Text(k.cohort),
+                Val::Int(k.rating_bucket as i32),
+                Val::Text(&k.eco_group),
+                Val::count(c.players),
+                Val::count(c.games),
+            ]
+        })
+        .collect()
+}
+
+/// Add player months to `player_months` / `player_month_ecos`, and with
+/// `local` to that input's own copies.
+pub async fn add_player_months(
+    tx: &mut Tx,
+    players: &HashMap<(String, String), PlayerMonth>,
+    local: Option<&str>,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    // rows of the month tables, or with the content hash first for the local copies
+    let months = |kept: bool| -> Vec<Vec<Val>> {
+        let hash = local.filter(|_| kept);
+        players
+            .iter()
+            .map(|((player, month), pm)| {
+                let row = [Val::Text(month), Val::Text(player), Val::count(pm.games), Val::count(pm.elo_sum), Val::count(pm.elo_games)];
+                hash.map(Val::Text).into_iter().chain(row).collect()
+            })
+            .collect()
+    };
+    let ecos = |kept: bool| -> Vec<Vec<Val>> {
+        let hash = local.filter(|_| kept);
+        players
+            .iter()
+            .flat_map(|((player, month), pm)| {
+                pm.eco_games.iter().map(move |(eco, n)| {
+                    let row = [Val::Text(month), Val::Text(player), Val::Text(eco), Val::count(*n)];
+                    hash.map(Val::Text).into_iter().chain(row).collect()
+                })
+            })
+            .collect()
+    };
+    let [.., (months_table, months_keys, months_sums), (ecos_table, ecos_keys, ecos_sums)] = additive_tables();
+    upsert_rows(tx, months_table, &months_keys, months_sums, &months(false), cfg_chunk_size, OnConflict::Add).await?;
+    upsert_rows(tx, ecos_table, &ecos_keys, ecos_sums, &ecos(false), cfg_chunk_size, OnConflict::Add).await?;
+    let Some(hash) = local else { return Ok(()) };
+    let local_tables = [(months_table, months_keys, months_sums, months(true)), (ecos_table, ecos_keys, ecos_sums, ecos(true))];
+    for (table, keys, sums, rows) in local_tables {
+        let keys: Vec<&str> = std::iter::once("content_hash").chain(keys).collect();
+        upsert_rows(tx, &format!("local_{}", table), &keys, sums, &rows, cfg_chunk_size, OnConflict::Add).await?;
+    }
+    vprintln!("db:kept {} player months for local input {}", players.len(), hash);
+    Ok(())
+}
+
+/// Recompute the `player_cohorts` rows of `months` from `player_months` /
+/// `player_month_ecos` and the first months; returns the new rows.
+pub async fn refresh_player_cohorts(
+    tx: &mut Tx,
+    months: &[&str],
+    buckets: &Buckets,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<CohortMap> {
+    // (cohort, mean Elo, eco_group, players, games), grouped by the first three
+    type Row = (String, Option<i64>, String, i64, i64);
+    let cohort = "CASE WHEN f.first_month = p.month THEN 'new' ELSE 'returning' END";
+    let from = "FROM player_months p JOIN player_first_seen f ON f.player = p.player";
+    let from_ecos = "FROM player_month_ecos e \
+        JOIN player_months p ON p.month = e.month AND p.player = e.player \
+        JOIN player_first_seen f ON f.player = e.player";
+    let select = |mean: &str, sum: &dyn Fn(&str) -> String, param: &str, param2: &str| {
+        format!(
+            "SELECT {cohort}, {mean}, '*', COUNT(*), {} {from} WHERE p.month = {param} GROUP BY 1, 2 \
+             UNION ALL \
+             SELECT {cohort}, {mean}, e.eco_group, COUNT(*), {} {from_ecos} WHERE e.month = {param2} GROUP BY 1, 2, 3",
+            sum("p.games"),
+            sum("e.games")
+        )
+    };
+    let mut all = CohortMap::new();
+    for &month in months {
+        let rows: Vec<Row> = match tx {
+            Tx::Sqlite(tx) => {
+                let sql = select("CASE WHEN p.elo_games > 0 THEN p.elo_sum / p.elo_games END", &|c| format!("SUM({c})"), "?", "?");
+                sqlx::query_as(&sql).bind(month).bind(month).fetch_all(&mut **tx).await?
+            }
+            Tx::Postgres(tx) => {
+                let sql = select(
+                    "CASE WHEN p.elo_games > 0 THEN p.elo_sum / p.elo_games END",
+                    &|c| format!("SUM({c})::BIGINT"),
+                    "$1",
+                    "$1",
+                );
+                sqlx::query_as(&sql).bind(month).fetch_all(&mut **tx).await?
+            }
+            Tx::Mysql(tx) => {
+                let sql = select(
+                    "CASE WHEN p.elo_games > 0 THEN p.elo_sum DIV p.elo_games END",
+                    &|c| format!("CAST(SUM({c}) AS SIGNED)"),
+                    "?",
+                    "?",
+                );
+                sqlx::query_as(&sql).bind(month).bind(month).fetch_all(&mut **tx).await?
+            }
+        };
+        let cells = cohort::cohort_cells(month, &rows, buckets);
+        match tx {
+            Tx::Sqlite(tx) => {
+                sqlx::query("DELETE FROM player_cohorts WHERE month = ?").bind(month).execute(&mut **tx).await?;
+            }
+            Tx::Postgres(tx) => {
+                sqlx::query("DELETE FROM player_cohorts WHERE month = $1").bind(month).execute(&mut **tx).await?;
+            }
+            Tx::Mysql(tx) => {
+                sqlx::query("DELETE FROM player_cohorts WHERE month = ?").bind(month).execute(&mut **tx).await?;
+            }
+        }
+        upsert_rows(tx,
+            "player_cohorts",
+            &["month", "cohort", "rating_bucket", "eco_group"],
+            COHORT_COLUMNS,
+            &cohort_rows(&cells),
+            cfg_chunk_size,
+            OnConflict::Replace,
+        )
+        .await?;
+        all.extend(cells);
+    }
+    vprintln!("db:refreshed player_cohorts of {} months", months.len());
+    Ok(all)
+}
+
+/// Empty every cohort table (start of `--rebuild-cohorts`), and the local
+/// inputs' own player months, which are no longer part of the sums.
+pub async fn reset_cohorts(tx: &mut Tx) -> anyhow::Result<()> {
+    let tables = [
+        "player_cohorts",
+        "player_first_seen",
+        "player_months",
+        "player_month_ecos",
+        "local_player_months",
+        "local_player_month_ecos",
+    ];
+    for table in tables {
+        let sql = format!("DELETE FROM {}", table);
+        match tx {
+            Tx::Sqlite(tx) => { sqlx::query(&sql).execute(&mut **tx).await?; }
+            Tx::Postgres(tx) => { sqlx::query(&sql).execute(&mut **tx).await?; }
+            Tx::Mysql(tx) => { sqlx::query(&sql).execute(&mut **tx).await?; }
+        }
+    }
+    Ok(())
+}
+
+/// Rewrite `eco_groups` from the loaded ECO taxonomy (plus the unknown group).
+pub async fn sync_eco_groups(db: &Db) -> anyhow::Result<()> {
+    let taxonomy = eco::taxonomy();
+    let codes: Vec<(String, String)> = taxonomy.groups.iter().map(|g| (g.start_code(), g.end_code())).collect();
+    let mut rows: Vec<Vec<Val>> = taxonomy
+        .groups
+        .iter()
+        .zip(&codes)
+        .map(|(g, (start, end))| {
+            vec![
+                Val::Text(&g.label),
+                Val::OptText(Some(start)),
+                Val::OptText(Some(end)),
+                Val::Text(&g.name),
+                Val::Text(&g.family),
+                Val::Text(&taxonomy.version),
+            ]
+        })
+        .collect();
+    rows.push(vec![
+        Val::Text(eco::UNKNOWN_LABEL),
+        Val::OptText(None),
+        Val::OptText(None),
+        Val::Text(eco::UNKNOWN_NAME),
+        Val::Text(eco::UNKNOWN_NAME),
+        Val::Text(&taxonomy.version),
+    ]);
+
+    let mut tx = db.begin().await?;
+    let sql = "DELETE FROM eco_groups";
+    match &mut tx {
+        Tx::Sqlite(tx) => { sqlx::query(sql).execute(&mut **tx).await?; }
+        Tx::Postgres(tx) => { sqlx::query(sql).execute(&mut **tx).await?; }
+        Tx::Mysql(tx) => { sqlx::query(sql).execute(&mut **tx).await?; }
+    }
+    upsert_rows(
+        &mut tx,
+        "eco_groups",
+        &["label"],
+        &["eco_start", "eco_end", "name", "family", "taxonomy"],
+        &rows,
+        rows.len(),
+        OnConflict::Replace,
+    )
+    .await?;
+    tx.commit().await?;
+    vprintln!("db: eco_groups synced ({} groups)", rows.len());
+    Ok(())
+}
+
+/// A saved local input, as recorded in `local_ingestions`.
+pub struct LocalIngestion {
+    pub content_hash: String,
+    pub file_name: Option<String>,
+    pub months: Vec<String>,
+    pub games: i64,
+    pub game_filter: Option<String>,
+    pub started_at: String,
+    pub finished_at: String,
+    pub attempts: i32,
+    pub eco_taxonomy: Option<String>,
+    pub manifest: Option<String>,
+    /// 'success', or 'failed' if no save of it succeeded yet.
+    pub status: String,
+    /// Why the last save failed; `None` once one succeeds.
+    pub error: Option<String>,
+}
+
+/// The earlier ingestion of an input with this content hash, if any.
+pub async fn find_local_ingestion(db: &Db, content_hash: &str) -> anyhow::Result<Option<LocalIngestion>> {
+    type Row = (
+        Option<String>, String, i64, Option<String>, String, String, i32, Option<String>, Option<String>, String,
+        Option<String>,
+    );
+    let row: Option<Row> = match db {
+        Db::Sqlite(pool) => {
+            sqlx::query_as(
+                "SELECT file_name, months, games, game_filter, started_at, finished_at, attempts, eco_taxonomy, manifest,
+                        status, error
+                   FROM local_ingestions WHERE content_hash = ?"
+            )
+            .bind(content_hash)
+            .fetch_optional(pool)
+            .await?
+        }
+        Db::Postgres(pool) => {
+            sqlx::query_as(
+                "SELECT file_name, months, games, game_filter, started_at, finished_at, attempts, eco_taxonomy, manifest,
+                        status, error
+                   FROM local_ingestions WHERE content_hash = $1"
+            )
+            .bind(content_hash)
+            .fetch_optional(pool)
+            .await?
+        }
+        Db::Mysql(pool) => {
+            sqlx::query_as(
+                "SELECT file_name, months, games, game_filter, started_at, finished_at, attempts, eco_taxonomy, manifest,
+                        status, error
+                   FROM local_ingestions WHERE content_hash = ?"
+            )
+            .bind(content_hash)
+            .fetch_optional(pool)
+            .await?
+        }
+    };
+    Ok(row.map(|(file_name, months, games, game_filter, started_at, finished_at, attempts, eco_taxonomy, manifest, status, error)| LocalIngestion {
+        content_hash: content_hash.to_string(),
+        file_name,
+        months: months.split(',').filter(|m| !m.is_empty()).map(str::to_string).collect(),
+        games,
+        game_filter,
+        started_at,
+        finished_at,
+        attempts,
+        eco_taxonomy,
+        manifest,
+        status,
+        error,
+    }))
+}
+
+pub async fn record_local_ingestion(tx: &mut Tx, li: &LocalIngestion) -> anyhow::Result<()> {
+    let months = li.months.join(",");
+    let row = vec![
+        Val::Text(&li.content_hash),
+        Val::OptText(li.file_name.as_deref()),
+        Val::Text(&months),
+        Val::BigInt(li.games),
+        Val::OptText(li.game_filter.as_deref()),
+        Val::Text(&li.started_at),
+        Val::Text(&li.finished_at),
+        Val::Int(li.attempts),
+        Val::OptText(li.eco_taxonomy.as_deref()),
+        Val::OptText(li.manifest.as_deref()),
+        Val::Text(&li.status),
+        Val::OptText(li.error.as_deref()),
+    ];
+    upsert_rows(
+        tx,
+        "local_ingestions",
+        &["content_hash"],
+        &[
+            "file_name", "months", "games", "game_filter", "started_at", "finished_at", "attempts", "eco_taxonomy",
+            "manifest", "status", "error",
+        ],
+        &[row],
+        1,
+        OnConflict::Replace,
+    )
+    .await
+}
+
+/// Record a local input whose save errored (its transaction was rolled back):
+/// a new input is recorded as 'failed', one saved before keeps its rows and
+/// record, plus the error; both count the attempt.
+pub async fn mark_local_ingestion_failed(
+    db: &Db, content_hash: &str, file_name: Option<&str>, error: &str, finished_iso: &str
+) -> anyhow::Result<()> {
+    vprintln!("db:mark local input failed {} {}", content_hash, error);
+    match db {
+        Db::Sqlite(pool) => {
+            sqlx::query(
+                "INSERT INTO local_ingestions (content_hash, file_name, months, started_at, finished_at, attempts, status, error)
+                 VALUES (?, ?, '', ?, ?, 1, 'failed', ?)
+                 ON CONFLICT(content_hash) DO UPDATE SET
+                   error=excluded.error,
+                   attempts=local_ingestions.attempts + 1"
+            )
+            .bind(content_hash).bind(file_name).bind(finished_iso).bind(finished_iso).bind(error)
+            .execute(pool).await?;
+        }
+        Db::Postgres(pool) => {
+            sqlx::query(
+                "INSERT INTO local_ingestions (content_hash, file_name, months, started_at, finished_at, attempts, status, error)
+                 VALUES ($1, $2, '', $3, $3, 1, 'failed', $4)
+                 ON CONFLICT (content_hash) DO UPDATE SET
+                   error = EXCLUDED.error,
+                   attempts = local_ingestions.attempts + 1"
+            )
+            .bind(content_hash).bind(file_name).bind(finished_iso).bind(error)
+            .execute(pool).await?;
+        }
+        Db::Mysql(pool) => {
+            sqlx::query(
+                "INSERT INTO local_ingestions (content_hash, file_name, months, started_at, finished_at, attempts, status, error)
+                 VALUES (?, ?, '', ?, ?, 1, 'failed', ?)
+                 ON DUPLICATE KEY UPDATE
+                   error = VALUES(error),
+                   attempts = attempts + 1"
+            )
+            .bind(content_hash).bind(file_name).bind(finished_iso).bind(finished_iso).bind(error)
+            .execute(pool).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Drop the `local_ingestions` record of an input (after `remove_local_input`),
+/// so it can be saved again without `--force`.
+pub async fn forget_local_ingestion(tx: &mut Tx, content_hash: &str) -> anyhow::Result<()> {
+    match tx {
+        Tx::Sqlite(tx) => {
+            sqlx::query("DELETE FROM local_ingestions WHERE content_hash = ?")
+                .bind(content_hash).execute(&mut **tx).await?;
+        }
+        Tx::Postgres(tx) => {
+            sqlx::query("DELETE FROM local_ingestions WHERE content_hash = $1")
+                .bind(content_hash).execute(&mut **tx).await?;
+        }
+        Tx::Mysql(tx) => {
+            sqlx::query("DELETE FROM local_ingestions WHERE content_hash = ?")
+                .bind(content_hash).execute(&mut **tx).await?;
+        }
+    }
+    Ok(())
+}
+
+// ---- Local inputs' own rows ----
+
+/// Month tables whose rows add up: (table, key columns, summed columns). Each
+/// has a `local_` copy keyed by `content_hash` that keeps every local input's
+/// own rows, so one input can be taken out of the sums again.
+fn additive_tables() -> [(&'static str, Vec<&'static str>, &'static [&'static str]); 5] {
+    [
+        ("aggregates", AGG_KEY_COLUMNS.split(", ").collect(), COUNTER_COLUMNS),
+        ("endgames", vec!["month", "speed", "white_bucket", "black_bucket", "signature"], ENDGAME_COLUMNS),
+        ("rating_histograms", vec!["month", "speed", "elo"], &["ratings"]),
+        ("player_months", vec!["month", "player"], &["games", "elo_sum", "elo_games"]),
+        ("player_month_ecos", vec!["month", "player", "eco_group"], &["games"]),
+    ]
+}
+
+/// Delete everything kept for one local input.
+async fn delete_local_rows(tx: &mut Tx, content_hash: &str) -> anyhow::Result<()> {
+    let tables = additive_tables().map(|(table, _, _)| format!("local_{}", table));
+    for table in tables.iter().map(String::as_str).chain(["local_top_summaries"]) {
+        match tx {
+            Tx::Sqlite(tx) => {
+                sqlx::query(&format!("DELETE FROM {} WHERE content_hash = ?", table))
+                    .bind(content_hash).execute(&mut **tx).await?;
+            }
+            Tx::Postgres(tx) => {
+                sqlx::query(&format!("DELETE FROM {} WHERE content_hash = $1", table))
+                    .bind(content_hash).execute(&mut **tx).await?;
+            }
+            Tx::Mysql(tx) => {
+                sqlx::query(&format!("DELETE FROM {} WHERE content_hash = ?", table))
+                    .bind(content_hash).execute(&mut **tx).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Keep the rows one local input's pass adds, replacing what was kept for it
+/// before (its player months are kept by `cohort::save`, after this).
+pub async fn save_local_rows(
+    tx: &mut Tx,
+    content_hash: &str,
+    agg: &Aggregation,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    delete_local_rows(tx, content_hash).await?;
+
+    let values: Vec<_> = agg.map.iter().map(|(k, c)| (k, c.values())).collect();
+    let aggregates: Vec<Vec<Val>> = values
+        .iter()
+        .map(|(k, values)| {
+            let mut row = vec![
+                Val::Text(&k.month),
+                Val::Text(k.speed),
+                Val::Text(&k.eco_group),
+                Val::Int(k.w_bucket as i32),
+                Val::Int(k.b_bucket as i32),
+                Val::Text(&k.w_title),
+                Val::Text(&k.b_title),
+            ];
+            row.extend(values.iter().map(|&v| Val::count(v)));
+            row
+        })
+        .collect();
+    let rows = [aggregates, endgame_rows(&agg.endgames), histogram_rows(&agg.ratings)];
+    for ((table, keys, sums), rows) in additive_tables().into_iter().zip(rows) {
+        let keys: Vec<&str> = std::iter::once("content_hash").chain(keys).collect();
+        let rows: Vec<Vec<Val>> =
+            rows.into_iter().map(|row| std::iter::once(Val::Text(content_hash)).chain(row).collect()).collect();
+        upsert_rows(tx, &format!("local_{}", table), &keys, sums, &rows, cfg_chunk_size, OnConflict::Add).await?;
+    }
+
+    let summaries: Vec<_> = [("player", &agg.top_players), ("opening", &agg.top_openings)]
+        .into_iter()
+        .flat_map(|(list, map)| map.iter().map(move |(k, top)| (list, k, top.top(top.capacity()))))
+        .collect();
+    let rows: Vec<_> = summaries
+        .iter()
+        .flat_map(|(list, k, items)| {
+            items.iter().map(move |(item, games, error)| {
+                vec![
+                    Val::Text(content_hash),
+                    Val::Text(&k.month),
+                    Val::Text(k.speed),
+                    Val::Text(list),
+                    Val::Text(item),
+                    Val::count(*games),
+                    Val::count(*error),
+                ]
+            })
+        })
+        .collect();
+    upsert_rows(tx,
+        "local_top_summaries",
+        &["content_hash", "month", "speed", "list", "item"],
+        &["games", "error"],
+        &rows,
+        cfg_chunk_size,
+        OnConflict::Replace,
+    )
+    .await
+}
+
+/// Add (`sign` 1) or take out (`sign` -1) the kept local rows where
+/// `column = value` to / from every additive month table.
+async fn apply_local_rows(tx: &mut Tx, column: &str, value: &str, sign: i64) -> anyhow::Result<()> {
+    for (table, keys, sums) in additive_tables() {
+        let keys = keys.join(", ");
+        let values: Vec<String> = sums.iter().map(|c| if sign < 0 { format!("-{c}") } else { c.to_string() }).collect();
+        let insert = format!(
+            "INSERT INTO {table} ({keys}, {}) SELECT {keys}, {} FROM local_{table}",
+            sums.join(", "),
+            values.join(", ")
+        );
+        let add = |target: &str, new: &dyn Fn(&str) -> String| {
+            sums.iter().map(|c| format!("{c} = {target}{c} + {}", new(c))).collect::<Vec<_>>().join(", ")
+        };
+        match tx {
+            Tx::Sqlite(tx) => {
+                let sql = format!(
+                    "{insert} WHERE {column} = ? ON CONFLICT ({keys}) DO UPDATE SET {}",
+                    add(&format!("{table}."), &|c| format!("excluded.{c}"))
+                );
+                sqlx::query(&sql).bind(value).execute(&mut **tx).await?;
+            }
+            Tx::Postgres(tx) => {
+                let sql = format!(
+                    "{insert} WHERE {column} = $1 ON CONFLICT ({keys}) DO UPDATE SET {}",
+                    add(&format!("{table}."), &|c| format!("EXCLUDED.{c}"))
+                );
+                sqlx::query(&sql).bind(value).execute(&mut **tx).await?;
+            }
+            Tx::Mysql(tx) => {
+                let sql = format!(
+                    "{insert} WHERE {column} = ? ON DUPLICATE KEY UPDATE {}",
+                    add(&format!("{table}."), &|c| format!("VALUES({c})"))
+                );
+                sqlx::query(&sql).bind(value).execute(&mut **tx).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Kept (month, speed) cells of `local_rating_histograms` where `column = value`.
+async fn local_rating_cells(tx: &mut Tx, column: &str, value: &str) -> anyhow::Result<Vec<MonthSpeed>> {
+    let rows: Vec<(String, String)> = match tx {
+        Tx::Sqlite(tx) => {
+            sqlx::query_as(&format!("SELECT DISTINCT month, speed FROM local_rating_histograms WHERE {} = ?", column))
+                .bind(value).fetch_all(&mut **tx).await?
+        }
+        Tx::Postgres(tx) => {
+            sqlx::query_as(&format!("SELECT DISTINCT month, speed FROM local_rating_histograms WHERE {} = $1", column))
+                .bind(value).fetch_all(&mut **tx).await?
+        }
+        Tx::Mysql(tx) => {
+            sqlx::query_as(&format!("SELECT DISTINCT month, speed FROM local_rating_histograms WHERE {} = ?", column))
+                .bind(value).fetch_all(&mut **tx).await?
+        }
+    };
+    Ok(rows.into_iter().filter_map(|(month, speed)| Some(MonthSpeed { month, speed: static_speed(&speed)? })).collect())
+}
+
+/// Months of the kept `local_player_months` rows where `column = value`.
+async fn local_player_months(tx: &mut Tx, column: &str, value: &str) -> anyhow::Result<Vec<String>> {
+    let sql = format!("SELECT DISTINCT month FROM local_player_months WHERE {} = ", column);
+    Ok(match tx {
+        Tx::Sqlite(tx) => sqlx::query_scalar(&format!("{}?", sql)).bind(value).fetch_all(&mut **tx).await?,
+        Tx::Postgres(tx) => sqlx::query_scalar(&format!("{}$1", sql)).bind(value).fetch_all(&mut **tx).await?,
+        Tx::Mysql(tx) => sqlx::query_scalar(&format!("{}?", sql)).bind(value).fetch_all(&mut **tx).await?,
+    })
+}
+
+/// A stored speed as the `&'static str` used in keys ("*" = all speeds).
+fn static_speed(speed: &str) -> Option<&'static str> {
+    SPEEDS.iter().chain(&["*"]).find(|s| **s == speed).copied()
+}
+
+/// Kept Space-Saving summaries where `column = value`, per (content hash, list, cell).
+async fn load_local_summaries(
+    tx: &mut Tx,
+    column: &str,
+    value: &str,
+    capacity: usize,
+) -> anyhow::Result<Vec<((String, String, MonthSpeed), TopK)>> {
+    type Row = (String, String, String, String, String, i64, i64);
+    let select = "SELECT content_hash, list, month, speed, item, games, error FROM local_top_summaries";
+    let rows: Vec<Row> = match tx {
+        Tx::Sqlite(tx) => {
+            sqlx::query_as(&format!("{} WHERE {} = ?", select, column)).bind(value).fetch_all(&mut **tx).await?
+        }
+        Tx::Postgres(tx) => {
+            sqlx::query_as(&format!("{} WHERE {} = $1", select, column)).bind(value).fetch_all(&mut **tx).await?
+        }
+        Tx::Mysql(tx) => {
+            sqlx::query_as(&format!("{} WHERE {} = ?", select, column)).bind(value).fetch_all(&mut **tx).await?
+        }
+    };
+    type Key = (String, String, MonthSpeed);
+    let mut items: HashMap<Key, Vec<(String, u64, u64)>> = HashMap::new();
+    for (hash, list, month, speed, item, games, error) in rows {
+        let Some(speed) = static_speed(&speed) else { continue };
+        items.entry((hash, list, MonthSpeed { month, speed })).or_default().push((item, games as u64, error as u64));
+    }
+    Ok(items.into_iter().map(|(k, items)| (k, TopK::from_summary(capacity, items))).collect())
+}
+
+/// Top lists and their `top_summaries` table: (list, table, item column).
+const TOP_LISTS: [(&str, &str, &str); 2] = [("player", "top_players", "player"), ("opening", "top_openings", "opening")];
+
+/// Whether rows were kept for this local input (inputs saved before
+/// `local_aggregates` existed have none).
+pub async fn has_local_rows(tx: &mut Tx, content_hash: &str) -> anyhow::Result<bool> {
+    let n: i64 = match tx {
+        Tx::Sqlite(tx) => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM local_aggregates WHERE content_hash = ?")
+                .bind(content_hash).fetch_one(&mut **tx).await?
+        }
+        Tx::Postgres(tx) => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM local_aggregates WHERE content_hash = $1")
+                .bind(content_hash).fetch_one(&mut **tx).await?
+        }
+        Tx::Mysql(tx) => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM local_aggregates WHERE content_hash = ?")
+                .bind(content_hash).fetch_one(&mut **tx).await?
+        }
+    };
+    Ok(n > 0)
+}
+
+/// Take a saved local input back out of the month tables (`--force`): its
+/// kept rows are subtracted, rows left at zero deleted, and quantiles, top
+/// lists and cohorts recomputed. Player sketches can't be subtracted and keep
+/// its players.
+pub async fn remove_local_input(
+    tx: &mut Tx,
+    input: &LocalIngestion,
+    buckets: &Buckets,
+    top_k: usize,
+    top_capacity: usize,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    let hash = input.content_hash.as_str();
+    vprintln!("db:remove local input {} (months {})", hash, input.months.join(","));
+    let cells = local_rating_cells(tx, "content_hash", hash).await?;
+    let player_months = local_player_months(tx, "content_hash", hash).await?;
+    apply_local_rows(tx, "content_hash", hash, -1).await?;
+    for (table, _, sums) in additive_tables() {
+        let zero: Vec<String> = sums.iter().map(|c| format!("{c} = 0")).collect();
+        for month in &input.months {
+            match tx {
+                Tx::Sqlite(tx) => {
+                    sqlx::query(&format!("DELETE FROM {} WHERE month = ? AND {}", table, zero.join(" AND ")))
+                        .bind(month).execute(&mut **tx).await?;
+                }
+                Tx::Postgres(tx) => {
+                    sqlx::query(&format!("DELETE FROM {} WHERE month = $1 AND {}", table, zero.join(" AND ")))
+                        .bind(month).execute(&mut **tx).await?;
+                }
+                Tx::Mysql(tx) => {
+                    sqlx::query(&format!("DELETE FROM {} WHERE month = ? AND {}", table, zero.join(" AND ")))
+                        .bind(month).execute(&mut **tx).await?;
+                }
+            }
+        }
+    }
+    let cells: Vec<&MonthSpeed> = cells.iter().collect();
+    refresh_rating_quantiles(tx, &cells, cfg_chunk_size).await?;
+    let player_months: Vec<&str> = player_months.iter().map(String::as_str).collect();
+    refresh_player_cohorts(tx, &player_months, buckets, cfg_chunk_size).await?;
+
+    let kept = load_local_summaries(tx, "content_hash", hash, top_capacity).await?;
+    for (list, table, item_col) in TOP_LISTS {
+        let mut subtracted = Vec::new();
+        for ((_, _, cell), top) in kept.iter().filter(|((_, l, _), _)| l == list) {
+            let stored = load_top_summary(tx, cell, list).await?;
+            let mut summary =
+                TopK::from_summary(top_capacity, stored.into_iter().map(|(item, n, err)| (item, n as u64, err as u64)));
+            summary.subtract(top);
+            subtracted.push((cell, summary));
+        }
+        store_top_list(tx, table, item_col, list, &subtracted, top_k, cfg_chunk_size).await?;
+    }
+    delete_local_rows(tx, hash).await
+}
+
+/// Add the kept rows of every local input back to `month` after its rows
+/// were deleted to re-ingest it (player sketches aren't kept and lose them).
+pub async fn restore_local_rows(
+    tx: &mut Tx,
+    month: &str,
+    buckets: &Buckets,
+    top_k: usize,
+    top_capacity: usize,
+    cfg_chunk_size: usize,
+) -> anyhow::Result<()> {
+    apply_local_rows(tx, "month", month, 1).await?;
+    let cells = local_rating_cells(tx, "month", month).await?;
+    let cells: Vec<&MonthSpeed> = cells.iter().collect();
+    refresh_rating_quantiles(tx, &cells, cfg_chunk_size).await?;
+    if !local_player_months(tx, "month", month).await?.is_empty() {
+        refresh_player_cohorts(tx, &[month], buckets, cfg_chunk_size).await?;
+    }
+
+    let kept = load_local_summaries(tx, "month", month, top_capacity).await?;
+    let mut inputs: Vec<&str> = kept.iter().map(|((hash, _, _), _)| hash.as_str()).collect();
+    inputs.sort_unstable();
+    inputs.dedup();
+    for hash in inputs {
+        for (list, table, item_col) in TOP_LISTS {
+            let map: TopMap = kept
+                .iter()
+                .filter(|((h, l, _), _)| h == hash && l == list)
+                .map(|((_, _, cell), top)| (cell.clone(), top.clone()))
+                .collect();
+            replace_top_list(tx, table, item_col, list, &map, top_k, cfg_chunk_size).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Saved months and local inputs built with an ECO taxonomy other than `version`.
+#[derive(Default)]
+pub struct TaxonomyMismatch {
+    pub months: Vec<(String, String)>,       // (month, version) from `ingestions`
+    pub local_inputs: Vec<(String, String, String)>, // (file name or hash, content hash, version) from `local_ingestions`
+}
+
+pub async fn taxonomy_mismatches(db: &Db, version: &str) -> anyhow::Result<TaxonomyMismatch> {
+    type LocalRow = (String, Option<String>, String);
+    let (months, locals): (Vec<(String, String)>, Vec<LocalRow>) = match db {
+        Db::Sqlite(pool) => (
+            sqlx::query_as("SELECT month, eco_taxonomy FROM ingestions WHERE eco_taxonomy <> ? ORDER BY month")
+                .bind(version)
+                .fetch_all(pool)
+                .await?,
+            sqlx::query_as("SELECT content_hash, file_name, eco_taxonomy FROM local_ingestions WHERE eco_taxonomy <> ?")
+                .bind(version)
+                .fetch_all(pool)
+                .await?,
+        ),
+        Db::Postgres(pool) => (
+            sqlx::query_as("SELECT month, eco_taxonomy FROM ingestions WHERE eco_taxonomy <> $1 ORDER BY month")
+                .bind(version)
+                .fetch_all(pool)
+                .await?,
+            sqlx::query_as("SELECT content_hash, file_name, eco_taxonomy FROM local_ingestions WHERE eco_taxonomy <> $1")
+                .bind(version)
+                .fetch_all(pool)
+                .await?,
+        ),
+        Db::Mysql(pool) => (
+            sqlx::query_as("SELECT month, eco_taxonomy FROM ingestions WHERE eco_taxonomy <> ? ORDER BY month")
+                .bind(version)
+                .fetch_all(pool)
+                .await?,
+            sqlx::query_as("SELECT content_hash, file_name, eco_taxonomy FROM local_ingestions WHERE eco_taxonomy <> ?")
+                .bind(version)
+                .fetch_all(pool)
+                .await?,
+        ),
+    };
+    let local_inputs = locals
+        .into_iter()
+        .map(|(hash, file_name, v)| (file_name.unwrap_or_else(|| hash[..12].to_string()), hash, v))
+        .collect();
+    Ok(TaxonomyMismatch { months, local_inputs })
+}
+
+/// Stored run manifests (rows saved before manifests have none).
+#[derive(Default)]
+pub struct SavedManifests {
+    pub months: Vec<(String, String)>,       // (month, manifest) from `ingestions`
+    pub local_inputs: Vec<(String, String, String)>, // (file name or hash, content hash, manifest) from `local_ingestions`
+}
+
+pub async fn saved_manifests(db: &Db) -> anyhow::Result<SavedManifests> {
+    const MONTHS: &str = "SELECT month, manifest FROM ingestions WHERE finished_at IS NOT NULL AND manifest IS NOT NULL ORDER BY month";
+    const LOCALS: &str = "SELECT content_hash, file_name, manifest FROM local_ingestions WHERE manifest IS NOT NULL";
+    type LocalRow = (String, Option<String>, String);
+    let (months, locals): (Vec<(String, String)>, Vec<LocalRow>) = match db {
+        Db::Sqlite(pool) => (sqlx::query_as(MONTHS).fetch_all(pool).await?, sqlx::query_as(LOCALS).fetch_all(pool).await?),
+        Db::Postgres(pool) => (sqlx::query_as(MONTHS).fetch_all(pool).await?, sqlx::query_as(LOCALS).fetch_all(pool).await?),
+        Db::Mysql(pool) => (sqlx::query_as(MONTHS).fetch_all(pool).await?, sqlx::query_as(LOCALS).fetch_all(pool).await?),
+    };
+    let local_inputs = locals
+        .into_iter()
+        .map(|(hash, file_name, manifest)| (file_name.unwrap_or_else(|| hash[..12].to_string()), hash, manifest))
+        .collect();
+    Ok(SavedManifests { months, local_inputs })
+}
+
+// ---- Rebucketing ----
+
+/// Tables keyed by rating buckets: (table, key columns, summed columns), with
+/// the local inputs' own rows (see `save_local_rows`) after the month tables.
+/// Key columns ending in `_bucket` are re-mapped; every other column is a count.
+fn bucket_tables() -> Vec<(String, Vec<&'static str>, &'static [&'static str])> {
+    let tables: Vec<_> = additive_tables()
+        .into_iter()
+        .filter(|(_, keys, _)| keys.iter().any(|k| k.ends_with("_bucket")))
+        .collect();
+    let local: Vec<_> = tables
+        .iter()
+        .map(|(table, keys, sums)| {
+            (format!("local_{}", table), std::iter::once("content_hash").chain(keys.iter().copied()).collect(), *sums)
+        })
+        .collect();
+    // derived from player_months, but summed like the rest to keep the rows saved before those
+    let cohorts = ("player_cohorts".to_string(), vec!["month", "cohort", "rating_bucket", "eco_group"], COHORT_COLUMNS);
+    tables.into_iter().map(|(table, keys, sums)| (table.to_string(), keys, sums)).chain(local).chain([cohorts]).collect()
+}
+
+/// `SELECT` of `table`'s rows summed into the `new` buckets (which must align
+/// with the stored ones).
+fn rebucket_select(table: &str, keys: &[&str], sums: &[&str], new: &Buckets) -> String {
+    let key_exprs: Vec<String> = keys
+        .iter()
+        .map(|k| if k.ends_with("_bucket") { new.sql(k) } else { k.to_string() })
+        .collect();
+    let columns: Vec<String> = keys
+        .iter()
+        .zip(&key_exprs)
+        .map(|(k, e)| format!("{} AS {}", e, k))
+        .chain(sums.iter().map(|c| format!("SUM({c}) AS {c}")))
+        .collect();
+    format!("SELECT {} FROM {} GROUP BY {}", columns.join(", "), table, key_exprs.join(", "))
+}
+
+impl Tx {
+    async fn execute(&mut self, sql: &str) -> anyhow::Result<u64> {
+        Ok(match self {
+            Tx::Sqlite(tx) => sqlx::query(sql).execute(&mut **tx).await?.rows_affected(),
+            Tx::Postgres(tx) => sqlx::query(sql).execute(&mut **tx).await?.rows_affected(),
+            Tx::Mysql(tx) => sqlx::query(sql).execute(&mut **tx).await?.rows_affected(),
+        })
+    }
+
+    async fn count(&mut self, table: &str) -> anyhow::Result<i64> {
+        let sql = format!("SELECT COUNT(*) FROM {}", table);
+        Ok(match self {
+            Tx::Sqlite(tx) => sqlx::query_scalar(&sql).fetch_one(&mut **tx).await?,
+            Tx::Postgres(tx) => sqlx::query_scalar(&sql).fetch_one(&mut **tx).await?,
+            Tx::Mysql(tx) => sqlx::query_scalar(&sql).fetch_one(&mut **tx).await?,
+        })
+    }
+}
+
+/// Distinct `white_bucket` / `black_bucket` values stored in `aggregates`.
+pub async fn stored_buckets(db: &Db) -> anyhow::Result<Vec<u16>> {
+    const SQL: &str = "SELECT white_bucket FROM aggregates UNION SELECT black_bucket FROM aggregates";
+    let buckets: Vec<i32> = match db {
+        Db::Sqlite(pool) => sqlx::query_scalar(SQL).fetch_all(pool).await?,
+        Db::Postgres(pool) => sqlx::query_scalar(SQL).fetch_all(pool).await?,
+        Db::Mysql(pool) => sqlx::query_scalar(SQL).fetch_all(pool).await?,
+    };
+    let mut buckets: Vec<u16> = buckets.into_iter().map(|b| b as u16).collect();
+    buckets.sort_unstable();
+    Ok(buckets)
+}
+
+/// Rows of a table before and after rebucketing.
+pub struct Rebucketed {
+    pub table: String,
+    pub rows_before: i64,
+    pub rows_after: i64,
+}
+
+/// Re-map every bucket table (aggregates, endgames, player_cohorts and the
+/// local inputs' copies) to `new` in one transaction, and record the new edges in the stored run manifests
+/// (`rewrite_manifest` gets each stored manifest).
+pub async fn rebucket_in_place(
+    db: &Db,
+    new: &Buckets,
+    rewrite_manifest: impl Fn(&str) -> String,
+) -> anyhow::Result<Vec<Rebucketed>> {
+    let mut tx = db.begin().await?;
+    let drop_stage = match &tx {
+        Tx::Mysql(_) => "DROP TEMPORARY TABLE rebucket_stage", // a plain DROP TABLE would commit
+        _ => "DROP TABLE rebucket_stage",
+    };
+    let mut done = Vec::new();
+    for (table, keys, sums) in bucket_tables() {
+        let t0 = Instant::now();
+        let rows_before = tx.count(&table).await?;
+        tx.execute(&format!("CREATE TEMPORARY TABLE rebucket_stage AS {}", rebucket_select(&table, &keys, sums, new)))
+            .await
+            .with_context(|| format!("rebucketing {}", table))?;
+        tx.execute(&format!("DELETE FROM {}", table)).await?;
+        let columns = format!("{}, {}", keys.join(", "), sums.join(", "));
+        let rows_after = tx
+            .execute(&format!("INSERT INTO {table} ({columns}) SELECT {columns} FROM rebucket_stage"))
+            .await? as i64;
+        tx.execute(drop_stage).await?;
+        vprintln!("db:rebucket {} {} -> {} rows in {:.3}s", table, rows_before, rows_after, t0.elapsed().as_secs_f64());
+        done.push(Rebucketed { table, rows_before, rows_after });
+    }
+
+    for (table, key) in [("ingestions", "month"), ("local_ingestions", "content_hash")] {
+        let select = format!("SELECT {}, manifest FROM {} WHERE manifest IS NOT NULL", key, table);
+        let rows: Vec<(String, String)> = match &mut tx {
+            Tx::Sqlite(tx) => sqlx::query_as(&select).fetch_all(&mut **tx).await?,
+            Tx::Postgres(tx) => sqlx::query_as(&select).fetch_all(&mut **tx).await?,
+            Tx::Mysql(tx) => sqlx::query_as(&select).fetch_all(&mut **tx).await?,
+        };
+        for (id, manifest) in rows {
+            let manifest = rewrite_manifest(&manifest);
+            match &mut tx {
+                Tx::Sqlite(tx) => {
+                    sqlx::query(&format!("UPDATE {} SET manifest = ? WHERE {} = ?", table, key))
+                        .bind(manifest).bind(id).execute(&mut **tx).await?;
+                }
+                Tx::Postgres(tx) => {
+                    sqlx::query(&format!("UPDATE {} SET manifest = $1 WHERE {} = $2", table, key))
+                        .bind(manifest).bind(id).execute(&mut **tx).await?;
+                }
+                Tx::Mysql(tx) => {
+                    sqlx::query(&format!("UPDATE {} SET manifest = ? WHERE {} = ?", table, key))
+                        .bind(manifest).bind(id).execute(&mut **tx).await?;
+                }
+            }
+        }
+    }
+    tx.commit().await?;
+    Ok(done)
+}
+
+/// Write `aggregates` summed into the `new` buckets to a new table `name`
+/// (same columns and key); nothing else changes.
+pub async fn rebucket_into(db: &Db, name: &str, new: &Buckets) -> anyhow::Result<Rebucketed> {
+    anyhow::ensure!(
+        !name.is_empty()
+            && name.len() <= 63
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !name.starts_with(|c: char| c.is_ascii_digit()),
+        "table name {:?} must be letters, digits and _ (at most 63)", name
+    );
+    let mut tx = db.begin().await?;
+    match &mut tx {
+        Tx::Sqlite(tx) => {
+            let create: String = sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'aggregates'")
+                .fetch_one(&mut **tx)
+                .await?;
+            let columns = &create[create.find('(').context("unexpected aggregates schema")?..];
+            sqlx::query(&format!("CREATE TABLE {} {}", name, columns)).execute(&mut **tx).await?;
+        }
+        Tx::Postgres(tx) => {
+            sqlx::query(&format!("CREATE TABLE {} (LIKE aggregates INCLUDING DEFAULTS)", name)).execute(&mut **tx).await?;
+            sqlx::query(&format!("ALTER TABLE {} ADD PRIMARY KEY ({})", name, AGG_KEY_COLUMNS)).execute(&mut **tx).await?;
+        }
+        Tx::Mysql(tx) => {
+            sqlx::query(&format!("CREATE TABLE {} LIKE aggregates", name)).execute(&mut **tx).await?;
+        }
+    }
+    let [(table, keys, sums), ..] = additive_tables();
+    let rows_before = tx.count(table).await?;
+    let columns = format!("{}, {}", keys.join(", "), sums.join(", "));
+    let rows_after = tx
+        .execute(&format!("INSERT INTO {} ({}) {}", name, columns, rebucket_select(table, &keys, sums, new)))
+        .await? as i64;
+    tx.commit().await?;
+    Ok(Rebucketed { table: table.to_string(), rows_before, rows_after })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    use sqlx::Connection;
+
+    use super::*;
+    use crate::aggregator::aggregate_from_reader;
+    use crate::config::Config;
+
+    /// Version of `0018_aggregate_speed.sql` in every migration set.
+    const SPEED_VERSION: i64 = 18;
+
+    /// The migrations of `m` before `version` (a database an older build left behind).
+    fn before(m: &Migrator, version: i64) -> Migrator {
+        let migrations = m.migrations.iter().filter(|x| x.version < version).cloned().collect();
+        Migrator { migrations: Cow::Owned(migrations), ..Migrator::DEFAULT }
+    }
+
+    const POPULATE: &str = "INSERT INTO aggregates \
+        (month, eco_group, white_bucket, black_bucket, white_title, black_title, \
+         games, white_wins, black_wins, draws, unfinished, white_elo_sum) VALUES \
+        ('2024-01', 'B20-B99', 1600, 1800, 'none', 'none', 10, 4, 5, 1, 0, 16500), \
+        ('2024-01', 'C00-C19', 2200, 2200, 'titled', 'bot', 7, 3, 3, 1, 0, 15600), \
+        ('2024-02', 'U00', 0, 0, 'none', 'none', 3, 1, 1, 0, 1, 0)";
+
+    const CHECK: &str = "SELECT month, speed, eco_group, white_title, black_title, \
+        games, white_wins, black_wins, draws, unfinished, white_elo_sum \
+        FROM aggregates ORDER BY month, eco_group";
+
+    type Row = (String, String, String, String, String, i64, i64, i64, i64, i64, i64);
+
+    fn expected() -> Vec<Row> {
+        let row = |m: &str, e: &str, wt: &str, bt: &str, v: [i64; 6]| {
+            (m.into(), "*".into(), e.into(), wt.into(), bt.into(), v[0], v[1], v[2], v[3], v[4], v[5])
+        };
+        vec![
+            row("2024-01", "B20-B99", "none", "none", [10, 4, 5, 1, 0, 16500]),
+            row("2024-01", "C00-C19", "titled", "bot", [7, 3, 3, 1, 0, 15600]),
+            row("2024-02", "U00", "none", "none", [3, 1, 1, 0, 1, 0]),
+        ]
+    }
+
+    /// Populate `aggregates` at the version before the speed column, migrate to
+    /// the latest version, and check every row survives under speed '*' while
+    /// the new key takes a second speed for the same cell.
+    macro_rules! check_speed_migration {
+        ($conn:expr, $migrator:expr) => {{
+            before(&$migrator, SPEED_VERSION).run(&mut *$conn).await.unwrap();
+            sqlx::query(POPULATE).execute(&mut *$conn).await.unwrap();
+
+            $migrator.run(&mut *$conn).await.unwrap();
+
+            let rows: Vec<Row> = sqlx::query_as(CHECK).fetch_all(&mut *$conn).await.unwrap();
+            assert_eq!(rows, expected());
+
+            let same_cell = "INSERT INTO aggregates \
+                (month, speed, eco_group, white_bucket, black_bucket, white_title, black_title, games, white_wins, black_wins, draws) \
+                VALUES ('2024-01', 'blitz', 'B20-B99', 1600, 1800, 'none', 'none', 1, 1, 0, 0)";
+            sqlx::query(same_cell).execute(&mut *$conn).await.unwrap();
+            assert!(sqlx::query(same_cell).execute(&mut *$conn).await.is_err());
+        }};
+    }
+
+    #[tokio::test]
+    async fn sqlite_speed_migration_keeps_rows() {
+        let mut conn = sqlx::SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        check_speed_migration!(&mut conn, SQLITE_MIGRATIONS);
+    }
+
+    fn month_pgn(site_prefix: &str, date: &str, n: usize) -> String {
+        (0..n)
+            .map(|i| {
+                format!(
+                    "[Event \"Rated Blitz game\"]\n[Site \"https://lichess.org/{}{:04}\"]\n[UTCDate \"{}\"]\n\
+                     [White \"p{}\"]\n[Black \"p{}\"]\n[WhiteElo \"{}\"]\n[BlackElo \"{}\"]\n[Result \"1-0\"]\n\n\
+                     1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0\n\n",
+                    site_prefix, i, date, i % 5, i % 7, 1500 + 10 * i, 1600 - 10 * i
+                )
+            })
+            .collect()
+    }
+
+    fn month_cfg() -> Config {
+        Config {
+            rating_quantiles: true,
+            distinct_players: true,
+            heavy_hitters: true,
+            cohorts: true,
+            ..Config::default()
+        }
+    }
+
+    /// Every row of every month table, as text, for comparing saves.
+    async fn month_rows(db: &Db) -> BTreeMap<&'static str, Vec<String>> {
+        let Db::Sqlite(pool) = db else { unreachable!() };
+        let mut tables = BTreeMap::new();
+        for table in MONTH_TABLES {
+            let columns: Vec<(String,)> = sqlx::query_as(&format!("SELECT name FROM pragma_table_info('{}')", table))
+                .fetch_all(pool)
+                .await
+                .unwrap();
+            let row = columns.iter().map(|(c,)| format!("quote({})", c)).collect::<Vec<_>>().join(" || ',' || ");
+            let rows: Vec<(String,)> = sqlx::query_as(&format!("SELECT {} AS r FROM {} ORDER BY r", row, table))
+                .fetch_all(pool)
+                .await
+                .unwrap();
+            tables.insert(*table, rows.into_iter().map(|(r,)| r).collect());
+        }
+        tables
+    }
+
+    /// What `ingest_month` does in the database for one remote month.
+    async fn save_month(db: &Db, agg: &Aggregation, cfg: &Config) {
+        let mut tx = db.begin().await.unwrap();
+        delete_month(&mut tx, "2024-01").await.unwrap();
+        write_aggregation(&mut tx, agg, cfg.top_k, cfg.db_batch_rows).await.unwrap();
+        let buckets = cfg.buckets().unwrap();
+        cohort::save(&mut tx, &agg.cohort_players, &buckets, None, cfg.db_batch_rows).await.unwrap();
+        restore_local_rows(&mut tx, "2024-01", &buckets, cfg.top_k, cfg.top_capacity, cfg.db_batch_rows)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    /// What a local `--save` does in the database (without taking anything out).
+    async fn save_local(db: &Db, agg: &Aggregation, hash: &str, cfg: &Config) {
+        let mut tx = db.begin().await.unwrap();
+        write_aggregation(&mut tx, agg, cfg.top_k, cfg.db_batch_rows).await.unwrap();
+        save_local_rows(&mut tx, hash, agg, cfg.db_batch_rows).await.unwrap();
+        let buckets = cfg.buckets().unwrap();
+        cohort::save(&mut tx, &agg.cohort_players, &buckets, Some(hash), cfg.db_batch_rows).await.unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn saving_a_month_twice_keeps_one_copy() {
+        let scratch = Scratch::open("db-test-month").await.unwrap();
+        let cfg = month_cfg();
+        let remote = aggregate_from_reader(month_pgn("rem0", "2024.01.02", 40).as_bytes(), &cfg).unwrap().0;
+
+        save_month(&scratch.db, &remote, &cfg).await;
+        let once = month_rows(&scratch.db).await;
+        for table in ["aggregates", "rating_quantiles", "player_sketches", "top_players", "player_cohorts", "player_months"] {
+            assert!(!once[table].is_empty(), "{} is empty", table);
+        }
+        save_month(&scratch.db, &remote, &cfg).await;
+        assert_eq!(month_rows(&scratch.db).await, once);
+
+        // a local input of the same month comes back after every re-save
+        let local = aggregate_from_reader(month_pgn("loc0", "2024.01.02", 25).as_bytes(), &cfg).unwrap().0;
+        save_local(&scratch.db, &local, "input-a", &cfg).await;
+        let with_local = month_rows(&scratch.db).await;
+        assert_ne!(with_local["aggregates"], once["aggregates"]);
+
+        for _ in 0..2 {
+            save_month(&scratch.db, &remote, &cfg).await;
+            let rows = month_rows(&scratch.db).await;
+            for table in MONTH_TABLES {
+                // player sketches aren't kept per input: the local input's are lost
+                let want = if *table == "player_sketches" { &once[table] } else { &with_local[table] };
+                assert_eq!(&rows[table], want, "{}", table);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn forced_local_input_replaces_its_rows() {
+        let scratch = Scratch::open("db-test-force").await.unwrap();
+        let cfg = month_cfg();
+        let remote = aggregate_from_reader(month_pgn("rem0", "2024.01.02", 40).as_bytes(), &cfg).unwrap().0;
+        save_month(&scratch.db, &remote, &cfg).await;
+        let remote_only = month_rows(&scratch.db).await;
+
+        // January overlaps the remote month's rows; February is the input's alone
+        let pgn = month_pgn("loc0", "2024.01.02", 25) + &month_pgn("loc1", "2024.02.03", 15);
+        let local = aggregate_from_reader(pgn.as_bytes(), &cfg).unwrap().0;
+        save_local(&scratch.db, &local, "input-a", &cfg).await;
+        let saved = month_rows(&scratch.db).await;
+        assert!(saved["aggregates"].iter().any(|r| r.starts_with("'2024-02'")));
+
+        let input = LocalIngestion {
+            content_hash: "input-a".into(),
+            file_name: None,
+            months: crate::local::months_of(&local),
+            games: 40,
+            game_filter: None,
+            started_at: "t0".into(),
+            finished_at: "t1".into(),
+            attempts: 1,
+            eco_taxonomy: None,
+            manifest: None,
+            status: "success".into(),
+            error: None,
+        };
+        assert_eq!(input.months, ["2024-01", "2024-02"]);
+        let buckets = cfg.buckets().unwrap();
+        let mut tx = scratch.db.begin().await.unwrap();
+        remove_local_input(&mut tx, &input, &buckets, cfg.top_k, cfg.top_capacity, cfg.db_batch_rows).await.unwrap();
+        tx.commit().await.unwrap();
+        // back to the remote month's rows: the input's February rows came to zero and are gone
+        let removed = month_rows(&scratch.db).await;
+        for (table, _, _) in additive_tables() {
+            assert_eq!(removed[table], remote_only[table], "{}", table);
+        }
+        for table in ["player_cohorts", "rating_quantiles"] {
+            assert_eq!(removed[table], remote_only[table], "{}", table);
+        }
+
+        // --force adds it anew: the same counts as after the first save
+        save_local(&scratch.db, &local, "input-a", &cfg).await;
+        assert_eq!(month_rows(&scratch.db).await, saved);
+    }
+
+    #[tokio::test]
+    async fn saved_manifests_skip_rows_without_one() {
+        let scratch = Scratch::open("db-test-manifests").await.unwrap();
+        let Db::Sqlite(pool) = &scratch.db else { unreachable!() };
+        sqlx::query(
+            "INSERT INTO ingestions (month, url, started_at, finished_at, status, manifest) VALUES
+               ('2013-01', 'u', 't', 't', 'success', NULL),
+               ('2013-02', 'u', 't', 't', 'success', '[compat]'),
+               ('2013-03', 'u', 't', NULL, 'started', '[compat]')",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO local_ingestions (content_hash, months, started_at, finished_at, manifest) VALUES
+               (?, '2013-01', 't', 't', NULL), (?, '2013-01', 't', 't', '[compat]')",
+        )
+        .bind("a".repeat(64))
+        .bind("b".repeat(64))
+        .execute(pool)
+        .await
+        .unwrap();
+        let saved = saved_manifests(&scratch.db).await.unwrap();
+        assert_eq!(saved.months, [("2013-02".to_string(), "[compat]".to_string())]);
+        let hashes: Vec<&str> = saved.local_inputs.iter().map(|(_, hash, _)| hash.as_str()).collect();
+        assert_eq!(hashes, ["b".repeat(64)]);
+    }
+
+    #[tokio::test]
+    async fn failed_attempts_are_counted_and_cleared_on_success() {
+        let scratch = Scratch::open("db-test-attempts").await.unwrap();
+        let db = &scratch.db;
+        let Db::Sqlite(pool) = db else { unreachable!() };
+        let month_state = || async {
+            sqlx::query_as::<_, (String, i32, Option<String>)>(
+                "SELECT status, attempts, error FROM ingestions WHERE month = '2024-01'",
+            )
+            .fetch_one(pool)
+            .await
+            .unwrap()
+        };
+
+        mark_ingestion_start(db, "2024-01", "https://x/2024-01", "t0", None).await.unwrap();
+        mark_ingestion_failed(db, "2024-01", "stream+aggregate: reset", "t1").await.unwrap();
+        assert_eq!(month_state().await, ("failed".into(), 1, Some("stream+aggregate: reset".into())));
+        assert!(already_ingested_months(db).await.unwrap().is_empty());
+
+        mark_ingestion_start(db, "2024-01", "https://x/2024-01", "t2", None).await.unwrap();
+        assert_eq!(month_state().await.1, 2);
+        let stats = FinishStats {
+            games: 3,
+            bot_excluded: 0,
+            human_excluded: 0,
+            filtered_out: 0,
+            duplicates: 0,
+            duration_ms: 1,
+            eco_taxonomy: "lta-1",
+            manifest: String::new(),
+        };
+        let mut tx = db.begin().await.unwrap();
+        mark_ingestion_finish(&mut tx, "2024-01", &stats, "success", "t3").await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(month_state().await, ("success".into(), 2, None));
+        assert!(already_ingested_months(db).await.unwrap().contains("2024-01"));
+
+        // a local input: failed saves are recorded, then replaced by the first success
+        mark_local_ingestion_failed(db, "hash-a", Some("a.pgn"), "cohorts: disk full", "t4").await.unwrap();
+        mark_local_ingestion_failed(db, "hash-a", Some("a.pgn"), "commit: locked", "t5").await.unwrap();
+        let failed = find_local_ingestion(db, "hash-a").await.unwrap().unwrap();
+        assert_eq!((failed.status.as_str(), failed.attempts), ("failed", 2));
+        assert_eq!(failed.error.as_deref(), Some("commit: locked"));
+
+        let record = LocalIngestion {
+            content_hash: "hash-a".into(),
+            file_name: Some("a.pgn".into()),
+            months: vec!["2024-01".into()],
+            games: 3,
+            game_filter: None,
+            started_at: "t6".into(),
+            finished_at: "t7".into(),
+            attempts: failed.attempts + 1,
+            eco_taxonomy: Some("lta-1".into()),
+            manifest: None,
+            status: "success".into(),
+            error: None,
+        };
+        let mut tx = db.begin().await.unwrap();
+        record_local_ingestion(&mut tx, &record).await.unwrap();
+        tx.commit().await.unwrap();
+        let saved = find_local_ingestion(db, "hash-a").await.unwrap().unwrap();
+        assert_eq!((saved.status.as_str(), saved.attempts, saved.error), ("success", 3, None));
+
+        // a failed --force keeps the saved record, with the error
+        mark_local_ingestion_failed(db, "hash-a", Some("a.pgn"), "write: constraint", "t8").await.unwrap();
+        let forced = find_local_ingestion(db, "hash-a").await.unwrap().unwrap();
+        assert_eq!((forced.status.as_str(), forced.attempts, forced.months), ("success", 4, vec!["2024-01".to_string()]));
+        assert_eq!(forced.error.as_deref(), Some("write: constraint"));
+    }
+
+    /// Needs `TEST_POSTGRES_URL`; runs in a throwaway schema.
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL; run with --ignored"]
+    async fn postgres_speed_migration_keeps_rows() {
+        let url = std::env::var("TEST_POSTGRES_URL").expect("TEST_POSTGRES_URL not set");
+        let mut conn = sqlx::PgConnection::connect(&url).await.unwrap();
+        let schema = format!("migration_test_{}", std::process::id());
+        sqlx::query(&format!("CREATE SCHEMA {schema}")).execute(&mut conn).await.unwrap();
+        sqlx::query(&format!("SET search_path TO {schema}")).execute(&mut conn).await.unwrap();
+        check_speed_migration!(&mut conn, POSTGRES_MIGRATIONS);
+        sqlx::query(&format!("DROP SCHEMA {schema} CASCADE")).execute(&mut conn).await.unwrap();
+    }
+
+    /// A pool on a throwaway schema of `TEST_POSTGRES_URL`, migrated.
+    async fn pg_schema(name: &str) -> (Db, String) {
+        let url = std::env::var("TEST_POSTGRES_URL").expect("TEST_POSTGRES_URL not set");
+        let schema = format!("{}_{}", name, std::process::id());
+        let mut conn = sqlx::PgConnection::connect(&url).await.unwrap();
+        sqlx::query(&format!("CREATE SCHEMA {schema}")).execute(&mut conn).await.unwrap();
+        let options: sqlx::postgres::PgConnectOptions = url.parse().unwrap();
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.options([("search_path", schema.as_str())]))
+            .await
+            .unwrap();
+        let db = Db::Postgres(pool);
+        run_migrations(&db).await.unwrap();
+        (db, schema)
+    }
+
+    async fn drop_pg_schema(db: Db, schema: &str) {
+        let Db::Postgres(pool) = db else { unreachable!() };
+        sqlx::query(&format!("DROP SCHEMA {schema} CASCADE")).execute(&pool).await.unwrap();
+        pool.close().await;
+    }
+
+    /// Needs `TEST_POSTGRES_URL`; runs in a throwaway schema.
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL; run with --ignored"]
+    async fn postgres_copy_upsert_round_trips() {
+        use sqlx::Row;
+
+        let (db, schema) = pg_schema("copy_test").await;
+        let cfg = Config { draw_reasons: true, move_style: true, ..Config::default() };
+        let pgn = month_pgn("cpy0", "2024.01.02", 40) + &month_pgn("cpy1", "2024.02.03", 15);
+        let agg = aggregate_from_reader(pgn.as_bytes(), &cfg).unwrap().0;
+        let expected = |times: u64| -> Vec<String> {
+            let mut rows: Vec<String> = agg
+                .map
+                .iter()
+                .map(|(k, c)| {
+                    let values: Vec<String> = c.values().iter().map(|v| (v * times).to_string()).collect();
+                    format!(
+                        "{}|{}|{}|{}|{}|{}|{}|{}",
+                        k.month, k.speed, k.eco_group, k.w_bucket, k.b_bucket, k.w_title, k.b_title, values.join(",")
+                    )
+                })
+                .collect();
+            rows.sort();
+            rows
+        };
+
+        // the second write goes through the same stage and adds to the stored rows
+        for times in 1..=2 {
+            let mut tx = db.begin().await.unwrap();
+            write_aggregation(&mut tx, &agg, cfg.top_k, 7).await.unwrap();
+            tx.commit().await.unwrap();
+            let Db::Postgres(pool) = &db else { unreachable!() };
+            let sql = format!("SELECT {}, {} FROM aggregates", AGG_KEY_COLUMNS, COUNTER_COLUMNS.join(", "));
+            let mut rows: Vec<String> = sqlx::query(&sql)
+                .fetch_all(pool)
+                .await
+                .unwrap()
+                .iter()
+                .map(|row| {
+                    let values: Vec<String> =
+                        (7..7 + COUNTER_COLUMNS.len()).map(|i| row.get::<i64, _>(i).to_string()).collect();
+                    format!(
+                        "{}|{}|{}|{}|{}|{}|{}|{}",
+                        row.get::<String, _>(0),
+                        row.get::<String, _>(1),
+                        row.get::<String, _>(2),
+                        row.get::<i32, _>(3),
+                        row.get::<i32, _>(4),
+                        row.get::<String, _>(5),
+                        row.get::<String, _>(6),
+                        values.join(",")
+                    )
+                })
+                .collect();
+            rows.sort();
+            assert_eq!(rows, expected(times));
+        }
+        drop_pg_schema(db, &schema).await;
+    }
+
+    /// Needs `TEST_POSTGRES_URL`; runs in a throwaway schema.
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL; run with --ignored"]
+    async fn postgres_delete_month_truncates_its_partition() {
+        let (db, schema) = pg_schema("partition_test").await;
+        apply_pg_partitioning(&db, PgPartitioning::Month).await.unwrap();
+        let cfg = Config::default();
+        let pgn = month_pgn("prt0", "2024.01.02", 20) + &month_pgn("prt1", "2024.02.03", 10);
+        let agg = aggregate_from_reader(pgn.as_bytes(), &cfg).unwrap().0;
+        let mut tx = db.begin().await.unwrap();
+        write_aggregation(&mut tx, &agg, cfg.top_k, cfg.db_batch_rows).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let Db::Postgres(pool) = &db else { unreachable!() };
+        // TRUNCATE gives a table new storage, a DELETE keeps it
+        let filenode = |month: &'static str| async move {
+            let (name, _) = pg_partition(PgPartitioning::Month, month);
+            sqlx::query_scalar::<_, i64>(&format!("SELECT pg_relation_filenode('{}')::BIGINT", name))
+                .fetch_one(pool)
+                .await
+                .unwrap()
+        };
+        let games = |month: &'static str| async move {
+            sqlx::query_scalar::<_, Option<i64>>("SELECT SUM(games)::BIGINT FROM aggregates WHERE month = $1")
+                .bind(month)
+                .fetch_one(pool)
+                .await
+                .unwrap()
+        };
+        let (jan, feb) = (filenode("2024-01").await, filenode("2024-02").await);
+        assert_eq!((games("2024-01").await, games("2024-02").await), (Some(20), Some(10)));
+
+        let mut tx = db.begin().await.unwrap();
+        delete_month(&mut tx, "2024-01").await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!((games("2024-01").await, games("2024-02").await), (None, Some(10)));
+        assert_ne!(filenode("2024-01").await, jan);
+        assert_eq!(filenode("2024-02").await, feb);
+        drop_pg_schema(db, &schema).await;
+    }
+
+    /// Needs `TEST_MYSQL_URL`; runs in a throwaway database.
+    #[tokio::test]
+    #[ignore = "needs TEST_MYSQL_URL; run with --ignored"]
+    async fn mysql_speed_migration_keeps_rows() {
+        let url = std::env::var("TEST_MYSQL_URL").expect("TEST_MYSQL_URL not set");
+        let mut conn = sqlx::MySqlConnection::connect(&url).await.unwrap();
+        let database = format!("migration_test_{}", std::process::id());
+        sqlx::query(&format!("CREATE DATABASE {database}")).execute(&mut conn).await.unwrap();
+        sqlx::query(&format!("USE {database}")).execute(&mut conn).await.unwrap();
+        check_speed_migration!(&mut conn, MYSQL_MIGRATIONS);
+        sqlx::query(&format!("DROP DATABASE {database}")).execute(&mut conn).await.unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+// src/dedup.rs
+// Skip games already counted, keyed by the Lichess game ID in `Site`
+// ("https://lichess.org/abcd1234"). The seen-set is either exact (8 bytes per
+// game) or a Bloom filter (fixed size; a false positive drops a unique game
+// with probability `dedup_fp_rate`). It lives for one run, or in `dedup_file`
+// across runs: loaded at start, written back after each saved ingestion.
+//
+// File layout (little-endian): 8-byte tag, then
+//   "EXACT\0\0\0" u64 count, count × u64 keys (sorted)
+//   "BLOOM\0\0\0" u32 k, u64 words, words × u64
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sketch::{stable_hash64, Bloom};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupMode {
+    #[default]
+    Off,
+    Exact,
+    Bloom,
+}
+
+/// The set, plus what undoes the current pass (`begin_pass`/`rollback`): the
+/// keys it inserted for an exact set, a copy of the bits for a Bloom filter.
+pub enum SeenGames {
+    Exact { set: HashSet<u64>, journal: Option<Vec<u64>> },
+    Bloom { filter: Bloom, before: Option<Vec<u64>> },
+}
+
+impl fmt::Debug for SeenGames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeenGames::Exact { set, .. } => write!(f, "SeenGames::Exact({} ids)", set.len()),
+            SeenGames::Bloom { filter, .. } => {
+                write!(f, "SeenGames::Bloom({} words, k={})", filter.words().len(), filter.k())
+            }
+        }
+    }
+}
+
+const TAG_EXACT: &[u8; 8] = b"EXACT\0\0\0";
+const TAG_BLOOM: &[u8; 8] = b"BLOOM\0\0\0";
+
+/// Dedup key of a `Site` header. Lichess IDs are 8 base62 characters and pack
+/// exactly into 48 bits; anything else is hashed (with the top bit set, so the
+/// two kinds never collide).
+pub fn game_key(site: &str) -> Option<u64> {
+    let id = site.trim().trim_end_matches('/').rsplit('/').next()?;
+    if id.is_empty() || id == "?" { return None; }
+    if id.len() == 8 && id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        let packed = id.bytes().fold(0u64, |acc, b| {
+            let d = match b {
+                b'0'..=b'9' => b - b'0',
+                b'a'..=b'z' => b - b'a' + 10,
+                _ => b - b'A' + 36,
+            };
+            acc * 62 + d as u64
+        });
+        return Some(packed);
+    }
+    Some(stable_hash64(site.trim().as_bytes()) | 1 << 63)
+}
+
+/// `Site` of one game (header lines only).
+pub fn site_of(game_lines: &[String]) -> Option<&str> {
+    game_lines
+        .iter()
+        .take_while(|l| l.starts_with('['))
+        .find_map(|l| l.strip_prefix("[Site \"")?.strip_suffix("\"]"))
+}
+
+impl SeenGames {
+    pub fn new(mode: DedupMode, capacity: u64, fp_rate: f64) -> Option<Self> {
+        match mode {
+            DedupMode::Off => None,
+            DedupMode::Exact => Some(SeenGames::Exact { set: HashSet::new(), journal: None }),
+            DedupMode::Bloom => Some(SeenGames::Bloom { filter: Bloom::new(capacity, fp_rate), before: None }),
+        }
+    }
+
+    /// Record the key; true if it was (probably, for Bloom) seen before.
+    pub fn check_and_insert(&mut self, key: u64) -> bool {
+        match self {
+            SeenGames::Exact { set, journal } => {
+                let new = set.insert(key);
+                if let (true, Some(journal)) = (new, journal.as_mut()) {
+                    journal.push(key);
+                }
+                !new
+            }
+            SeenGames::Bloom { filter, .. } => filter.check_and_insert(stable_hash64(&key.to_le_bytes())),
+        }
+    }
+
+    /// Start recording what the next pass adds, dropping the previous pass's record.
+    pub fn begin_pass(&mut self) {
+        match self {
+            SeenGames::Exact { journal, .. } => *journal = Some(Vec::new()),
+            SeenGames::Bloom { filter, before } => *before = Some(filter.words().to_vec()),
+        }
+    }
+
+    /// Forget the games seen since `begin_pass` (its pass wasn't saved).
+    pub fn rollback(&mut self) {
+        match self {
+            SeenGames::Exact { set, journal } => {
+                for key in journal.take().unwrap_or_default() {
+                    set.remove(&key);
+                }
+            }
+            SeenGames::Bloom { filter, before } => {
+                if let Some(words) = before.take() {
+                    *filter = Bloom::from_parts(filter.k(), words);
+                }
+            }
+        }
+    }
+
+    pub fn load(path: &Path, mode: DedupMode) -> anyhow::Result<Self> {
+        let bytes = fs::read(path)?;
+        anyhow::ensure!(bytes.len() >= 8, "{}: not a seen-games file", path.display());
+        let (tag, rest) = bytes.split_at(8);
+        let u64s = |b: &[u8]| -> Vec<u64> {
+            b.chunks_exact(8).map(|c| u64::from_le_bytes(c.try_into().unwrap())).collect()
+        };
+        let seen = match (tag, mode) {
+            (t, DedupMode::Exact) if t == TAG_EXACT => {
+                anyhow::ensure!(rest.len() >= 8, "{}: truncated", path.display());
+                let keys = u64s(&rest[8..]);
+                anyhow::ensure!(keys.len() as u64 == u64s(&rest[..8])[0], "{}: truncated", path.display());
+                SeenGames::Exact { set: keys.into_iter().collect(), journal: None }
+            }
+            (t, DedupMode::Bloom) if t == TAG_BLOOM => {
+                anyhow::ensure!(rest.len() >= 12, "{}: truncated", path.display());
+                let k = u32::from_le_bytes(rest[..4].try_into().unwrap());
+                let words = u64s(&rest[4..12])[0] as usize;
+                let bits = u64s(&rest[12..]);
+                anyhow::ensure!(bits.len() == words && words > 0, "{}: truncated", path.display());
+                SeenGames::Bloom { filter: Bloom::from_parts(k, bits), before: None }
+            }
+            _ => anyhow::bail!("{} was not written with dedup = {:?}", path.display(), mode),
+        };
+        Ok(seen)
+    }
+
+    /// Write atomically (temp file + rename).
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut out = Vec::new();
+        match self {
+            SeenGames::Exact { set, .. } => {
+                let mut keys: Vec<u64> = set.iter().copied().collect();
+                keys.sort_unstable();
+                out.extend_from_slice(TAG_EXACT);
+                out.extend_from_slice(&(keys.len() as u64).to_le_bytes());
+                keys.iter().for_each(|k| out.extend_from_slice(&k.to_le_bytes()));
+            }
+            SeenGames::Bloom { filter: b, .. } => {
+                out.extend_from_slice(TAG_BLOOM);
+                out.extend_from_slice(&b.k().to_le_bytes());
+                out.extend_from_slice(&(b.words().len() as u64).to_le_bytes());
+                b.words().iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+            }
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &out)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("lta-dedup-{}-{}.bin", std::process::id(), name))
+    }
+
+    #[test]
+    fn game_key_packs_lichess_ids() {
+        let key = game_key("https://lichess.org/abcd1234").unwrap();
+        assert!(key < 1 << 48);
+        assert_eq!(game_key("https://lichess.org/abcd1234/"), Some(key));
+        assert_eq!(game_key("abcd1234"), Some(key));
+        assert_ne!(game_key("https://lichess.org/abcd1235"), Some(key));
+        assert_ne!(game_key("https://lichess.org/ABCD1234"), Some(key));
+    }
+
+    #[test]
+    fn game_key_hashes_other_sites() {
+        let key = game_key("https://example.com/game/42").unwrap();
+        assert!(key >= 1 << 63);
+        assert_eq!(game_key(" https://example.com/game/42 "), Some(key));
+        assert_eq!(game_key("?"), None);
+        assert_eq!(game_key(""), None);
+    }
+
+    #[test]
+    fn site_of_reads_headers_only() {
+        let lines = |ls: &[&str]| -> Vec<String> { ls.iter().map(|l| l.to_string()).collect() };
+        let site = "[Site \"https://lichess.org/abcd1234\"]";
+        let game = lines(&["[Event \"Rated Blitz game\"]", site, "", "1. e4 1-0"]);
+        assert_eq!(site_of(&game), Some("https://lichess.org/abcd1234"));
+        assert_eq!(site_of(&lines(&["1. e4 1-0", site])), None);
+    }
+
+    #[test]
+    fn exact_set_survives_save_and_load() {
+        let mut seen = SeenGames::new(DedupMode::Exact, 0, 0.0).unwrap();
+        for key in [3u64, 1, 1 << 63 | 7, 42] {
+            assert!(!seen.check_and_insert(key));
+        }
+        let path = temp_path("exact");
+        seen.save(&path).unwrap();
+        let mut loaded = SeenGames::load(&path, DedupMode::Exact).unwrap();
+        assert!(SeenGames::load(&path, DedupMode::Bloom).is_err());
+        fs::remove_file(&path).unwrap();
+        match &loaded {
+            SeenGames::Exact { set, .. } => assert_eq!(set.len(), 4),
+            SeenGames::Bloom { .. } => panic!("loaded a Bloom filter"),
+        }
+        for key in [3u64, 1, 1 << 63 | 7, 42] {
+            assert!(loaded.check_and_insert(key));
+        }
+        assert!(!loaded.check_and_insert(5));
+    }
+
+    #[test]
+    fn bloom_filter_survives_save_and_load() {
+        let mut seen = SeenGames::new(DedupMode::Bloom, 10_000, 0.001).unwrap();
+        for key in 0..1_000u64 {
+            seen.check_and_insert(key);
+        }
+        let path = temp_path("bloom");
+        seen.save(&path).unwrap();
+        let mut loaded = SeenGames::load(&path, DedupMode::Bloom).unwrap();
+        assert!(SeenGames::load(&path, DedupMode::Exact).is_err());
+        fs::remove_file(&path).unwrap();
+        match (&seen, &loaded) {
+            (SeenGames::Bloom { filter: a, .. }, SeenGames::Bloom { filter: b, .. }) => {
+                assert_eq!(a.k(), b.k());
+                assert_eq!(a.words(), b.words());
+            }
+            _ => panic!("loaded an exact set"),
+        }
+        assert!((0..1_000u64).all(|key| loaded.check_and_insert(key)));
+    }
+
+    #[test]
+    fn truncated_file_is_refused() {
+        let mut seen = SeenGames::new(DedupMode::Exact, 0, 0.0).unwrap();
+        seen.check_and_insert(1);
+        seen.check_and_insert(2);
+        let path = temp_path("truncated");
+        seen.save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 8]).unwrap();
+        assert!(SeenGames::load(&path, DedupMode::Exact).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rollback_forgets_only_the_pass() {
+        for mode in [DedupMode::Exact, DedupMode::Bloom] {
+            let mut seen = SeenGames::new(mode, 10_000, 0.001).unwrap();
+            assert!(!seen.check_and_insert(1));
+            seen.begin_pass();
+            assert!(!seen.check_and_insert(2));
+            assert!(seen.check_and_insert(1));
+            seen.rollback();
+            assert!(seen.check_and_insert(1), "{:?}", mode);
+            assert!(!seen.check_and_insert(2), "{:?}", mode);
+
+            // a new pass starts a new record: the previous one's games stay seen
+            seen.begin_pass();
+            assert!(!seen.check_and_insert(3));
+            seen.begin_pass();
+            seen.rollback();
+            assert!(seen.check_and_insert(2), "{:?}", mode);
+            assert!(seen.check_and_insert(3), "{:?}", mode);
+        }
+    }
+}
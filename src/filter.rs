@@ -0,0 +1,317 @@
+// src/filter.rs
+// Small predicate language over PGN headers, used to select which games get
+// aggregated (config `filter` or CLI `--filter`).
+//
+//   expr  := or
+//   or    := and ( ("or" | "||") and )*
+//   and   := not ( ("and" | "&&") not )*
+//   not   := ("not" | "!") not | "(" expr ")" | cmp
+//   cmp   := Tag op value
+//   op    := == | != | ~ | !~ | < | <= | > | >=
+//   value := "quoted string" | bare word / number
+//
+// `~` / `!~` match a regex; `<`.. `>=` compare numerically. A missing tag
+// fails every comparison except `!=` and `!~`.
+//
+// Examples:
+//   Event ~ "^Rated Blitz"
+//   WhiteElo >= 2000 and BlackElo >= 2000
+//   not SetUp == "1"
+
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+
+#[derive(Clone)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Filter({:?})", self.source)
+    }
+}
+
+#[derive(Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp { tag: String, op: Op, value: String },
+    Match { tag: String, negate: bool, re: Regex },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op { Eq, Ne, Lt, Le, Gt, Ge }
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Tok {
+    Word(String),
+    Str(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+fn tokenize(src: &str) -> anyhow::Result<Vec<Tok>> {
+    let mut toks = Vec::new();
+    let chars: Vec<char> = src.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => { toks.push(Tok::LParen); i += 1; }
+            ')' => { toks.push(Tok::RParen); i += 1; }
+            '"' => {
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => anyhow::bail!("filter: unterminated string"),
+                        Some('"') => { i += 1; break; }
+                        Some('\\') if chars.get(i + 1).is_some() => { s.push(chars[i + 1]); i += 2; }
+                        Some(&ch) => { s.push(ch); i += 1; }
+                    }
+                }
+                toks.push(Tok::Str(s));
+            }
+            '=' if next == Some('=') => { toks.push(Tok::Op("==")); i += 2; }
+            '!' if next == Some('=') => { toks.push(Tok::Op("!=")); i += 2; }
+            '!' if next == Some('~') => { toks.push(Tok::Op("!~")); i += 2; }
+            '!' => { toks.push(Tok::Op("!")); i += 1; }
+            '~' => { toks.push(Tok::Op("~")); i += 1; }
+            '<' if next == Some('=') => { toks.push(Tok::Op("<=")); i += 2; }
+            '>' if next == Some('=') => { toks.push(Tok::Op(">=")); i += 2; }
+            '<' => { toks.push(Tok::Op("<")); i += 1; }
+            '>' => { toks.push(Tok::Op(">")); i += 1; }
+            '&' if next == Some('&') => { toks.push(Tok::Op("&&")); i += 2; }
+            '|' if next == Some('|') => { toks.push(Tok::Op("||")); i += 2; }
+            c if c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+' | '/') => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.' | '-' | '+' | '/')) {
+                    i += 1;
+                }
+                toks.push(Tok::Word(chars[start..i].iter().collect()));
+            }
+            _ => anyhow::bail!("filter: unexpected character '{}'", c),
+        }
+    }
+    Ok(toks)
+}
+
+struct Parser {
+    toks: Vec<Tok>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> { self.toks.get(self.pos) }
+
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn is_keyword(&self, kw: &str, sym: &str) -> bool {
+        match self.peek() {
+            Some(Tok::Word(w)) => w.eq_ignore_ascii_case(kw),
+            Some(Tok::Op(o)) => *o == sym,
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> anyhow::Result<Expr> {
+        let mut lhs = self.and()?;
+        while self.is_keyword("or", "||") {
+            self.pos += 1;
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Expr> {
+        let mut lhs = self.not()?;
+        while self.is_keyword("and", "&&") {
+            self.pos += 1;
+            lhs = Expr::And(Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> anyhow::Result<Expr> {
+        if self.is_keyword("not", "!") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Tok::LParen) {
+            self.pos += 1;
+            let e = self.or()?;
+            anyhow::ensure!(self.next() == Some(Tok::RParen), "filter: expected ')'");
+            return Ok(e);
+        }
+        self.cmp()
+    }
+
+    fn cmp(&mut self) -> anyhow::Result<Expr> {
+        let tag = match self.next() {
+            Some(Tok::Word(w)) => w,
+            other => anyhow::bail!("filter: expected a tag name, got {:?}", other),
+        };
+        let op = match self.next() {
+            Some(Tok::Op(o)) => o,
+            other => anyhow::bail!("filter: expected an operator after {}, got {:?}", tag, other),
+        };
+        let value = match self.next() {
+            Some(Tok::Word(w)) | Some(Tok::Str(w)) => w,
+            other => anyhow::bail!("filter: expected a value after {} {}, got {:?}", tag, op, other),
+        };
+        let op = match op {
+            "~" | "!~" => {
+                let re = Regex::new(&value).map_err(|e| anyhow::anyhow!("filter: bad regex {:?}: {}", value, e))?;
+                return Ok(Expr::Match { tag, negate: op == "!~", re });
+            }
+            "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            _ => anyhow::bail!("filter: unexpected operator {}", op),
+        };
+        Ok(Expr::Cmp { tag, op, value })
+    }
+}
+
+impl Filter {
+    pub fn parse(src: &str) -> anyhow::Result<Filter> {
+        let mut p = Parser { toks: tokenize(src)?, pos: 0 };
+        let expr = p.or()?;
+        anyhow::ensure!(p.pos == p.toks.len(), "filter: unexpected {:?}", p.peek());
+        Ok(Filter { source: src.trim().to_string(), expr })
+    }
+
+    /// The expression as written (stored with each ingestion).
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, h: &HashMap<String, String>) -> bool {
+        eval(&self.expr, h)
+    }
+}
+
+fn eval(e: &Expr, h: &HashMap<String, String>) -> bool {
+    match e {
+        Expr::Or(a, b) => eval(a, h) || eval(b, h),
+        Expr::And(a, b) => eval(a, h) && eval(b, h),
+        Expr::Not(a) => !eval(a, h),
+        Expr::Match { tag, negate, re } => match h.get(tag) {
+            Some(v) => re.is_match(v) != *negate,
+            None => *negate,
+        },
+        Expr::Cmp { tag, op, value } => {
+            let Some(v) = h.get(tag) else { return *op == Op::Ne; };
+            match op {
+                Op::Eq => v == value,
+                Op::Ne => v != value,
+                _ => match (v.parse::<f64>(), value.parse::<f64>()) {
+                    (Ok(a), Ok(b)) => match op {
+                        Op::Lt => a < b,
+                        Op::Le => a <= b,
+                        Op::Gt => a > b,
+                        _ => a >= b,
+                    },
+                    _ => false,
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn check(src: &str, pairs: &[(&str, &str)]) -> bool {
+        Filter::parse(src).unwrap().matches(&headers(pairs))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // a or (b and c)
+        let src = "Event == x or WhiteElo > 2000 and BlackElo > 2000";
+        assert!(check(src, &[("Event", "x")]));
+        assert!(!check(src, &[("WhiteElo", "2100"), ("BlackElo", "1900")]));
+        assert!(check(src, &[("WhiteElo", "2100"), ("BlackElo", "2100")]));
+        // parentheses override
+        let src = "(Event == x or WhiteElo > 2000) and BlackElo > 2000";
+        assert!(!check(src, &[("Event", "x")]));
+    }
+
+    #[test]
+    fn not_binds_tightest() {
+        let src = "not Event == x and Site == y";
+        assert!(check(src, &[("Event", "z"), ("Site", "y")]));
+        assert!(!check(src, &[("Event", "x"), ("Site", "y")]));
+        assert!(check("! (Event == x || Site == y)", &[("Event", "z")]));
+        assert!(check("NOT Event == x AND Site == y", &[("Site", "y")]));
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert!(check(r#"Event == "Rated Blitz game""#, &[("Event", "Rated Blitz game")]));
+        assert!(check(r#"Black == "say \"hi\"""#, &[("Black", r#"say "hi""#)]));
+        assert!(Filter::parse(r#"Event == "open"#).is_err());
+    }
+
+    #[test]
+    fn regex_match() {
+        assert!(check(r#"Event ~ "^Rated (Blitz|Bullet)""#, &[("Event", "Rated Bullet game")]));
+        assert!(!check(r#"Event !~ "^Rated""#, &[("Event", "Rated Bullet game")]));
+        let err = Filter::parse(r#"Event ~ "(unclosed""#).unwrap_err().to_string();
+        assert!(err.contains("bad regex"), "{}", err);
+    }
+
+    #[test]
+    fn missing_tags_pass_only_negations() {
+        for src in ["Event == x", "Event ~ x", "WhiteElo > 0", "WhiteElo <= 3000"] {
+            assert!(!check(src, &[]), "{}", src);
+        }
+        assert!(check("Event != x", &[]));
+        assert!(check("Event !~ x", &[]));
+    }
+
+    #[test]
+    fn numeric_comparisons() {
+        assert!(check("WhiteElo >= 2000", &[("WhiteElo", "2000")]));
+        assert!(!check("WhiteElo > 2000", &[("WhiteElo", "2000")]));
+        // numeric, not lexicographic
+        assert!(check("WhiteElo < 1000", &[("WhiteElo", "999")]));
+        assert!(check("Diff > -5.5", &[("Diff", "-3")]));
+        // a non-number never compares
+        assert!(!check("WhiteElo < 1000", &[("WhiteElo", "?")]));
+        assert!(!check("WhiteElo >= 1000", &[("WhiteElo", "?")]));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for src in ["", "Event", "Event ==", "== x", "(Event == x", "Event == x)", "Event == x and", "Event = x", "Event == x $"] {
+            assert!(Filter::parse(src).is_err(), "{:?} should not parse", src);
+        }
+    }
+
+    #[test]
+    fn keeps_source() {
+        assert_eq!(Filter::parse("  Event == x ").unwrap().source(), "Event == x");
+    }
+}
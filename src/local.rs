@@ -0,0 +1,116 @@
+// src/local.rs
+// Local (stdin) ingestions are identified by the SHA-256 of the PGN text they
+// read, so the same input is recognised whatever its file name. A hash that
+// was already saved is refused unless `--force`, which takes the rows it added
+// before back out (`db::remove_local_input`) instead of adding them twice.
+// A file on stdin (`< file`) is hashed before the pass, so a saved one is
+// refused without aggregating it; a pipe is hashed as it is read.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use sha2::{Digest, Sha256};
+
+use crate::aggregator::Aggregation;
+
+/// Passes a reader through while hashing every byte consumed.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: BufRead> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, hasher: Sha256::new() }
+    }
+
+    /// Hex SHA-256 of everything read so far.
+    pub fn hex_digest(&self) -> String {
+        hex(self.hasher.clone())
+    }
+}
+
+impl<R: BufRead> Read for HashingReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(out)?;
+        self.hasher.update(&out[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for HashingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // the buffer is already filled, so this doesn't read again
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.hasher.update(&buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+fn hex(hasher: Sha256) -> String {
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hex SHA-256 of the rest of `file`, which is left where it was.
+fn hash_rest<F: Read + Seek>(file: &mut F) -> io::Result<String> {
+    let start = file.stream_position()?;
+    let mut hasher = Sha256::new();
+    io::copy(file, &mut hasher)?;
+    file.seek(SeekFrom::Start(start))?;
+    Ok(hex(hasher))
+}
+
+/// What `HashingReader` will hash of stdin, read ahead when stdin is a regular
+/// file; `None` for a pipe (or a terminal), which can only be read once.
+pub fn hash_stdin_file() -> io::Result<Option<String>> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsFd;
+        // the duplicated descriptor shares stdin's offset, which `hash_rest` restores
+        let mut file = std::fs::File::from(io::stdin().as_fd().try_clone_to_owned()?);
+        if file.metadata()?.is_file() {
+            return hash_rest(&mut file).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Months an aggregation wrote rows for, sorted.
+pub fn months_of(agg: &Aggregation) -> Vec<String> {
+    let months: BTreeSet<&str> = agg
+        .map
+        .keys()
+        .map(|k| k.month.as_str())
+        .chain(agg.ratings.keys().map(|k| k.month.as_str()))
+        .collect();
+    months.into_iter().map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_ahead_matches_the_hashing_reader() {
+        let text = b"[Event \"Rated Blitz game\"]\n\n1. e4 1-0\n".repeat(1_000);
+        let mut file = io::Cursor::new(text.to_vec());
+        file.seek(SeekFrom::Start(10)).unwrap();
+        let ahead = hash_rest(&mut file).unwrap();
+        assert_eq!(file.position(), 10);
+
+        let mut reader = HashingReader::new(io::BufReader::with_capacity(64, file));
+        let mut lines = 0;
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            lines += 1;
+            line.clear();
+        }
+        assert_eq!(lines, 3_000);
+        assert_eq!(reader.hex_digest(), ahead);
+    }
+}
@@ -4,18 +4,28 @@ mod verbose;
 
 mod aggregator;
 mod cli;
+mod cohort;
 mod config;
 mod db;
+mod dedup;
 mod model;
 mod pgn;
 mod eco;
+mod local;
+mod manifest;
+mod filter;
+mod openings;
 mod remote;
+mod replay;
+mod sketch;
 
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::path::{Path, PathBuf};
+use anyhow::Context;
 use chrono::Utc;
 
 #[tokio::main(flavor = "multi_thread")]
-async fn main() -> std::io::Result<()> {
+async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
     let args = cli::parse();
     if args.help {
@@ -23,15 +33,92 @@ async fn main() -> std::io::Result<()> {
         return Ok(());
     }
 
-    let cfg = config::Config::load();
+    let mut cfg = config::Config::load().expect("invalid config.toml");
+    cfg.compile_filter(args.filter.as_deref()).expect("invalid filter");
+    cfg.buckets().expect("invalid bucket_edges");
+    cfg.init_dedup().expect("dedup seen-set load failed");
+    cfg.preview = config::Preview {
+        sample_rate: args.sample_rate,
+        max_games: args.max_games,
+        max_bytes: args.max_bytes,
+        scale: args.scale,
+    };
+    if cfg.preview.is_active() && args.save {
+        // partial counts must never reach the main tables
+        eprintln!("❌ --sample-rate/--max-games/--max-bytes are previews and can't be combined with --save.");
+        std::process::exit(2);
+    }
+    if cfg.preview.is_active() {
+        eprintln!("🔎 Preview run ({}); outputs are partial and not savable.", cfg.preview.describe());
+    }
     verbose::set(args.verbose);
     if let Some(n) = cfg.rayon_threads {
         let _ = rayon::ThreadPoolBuilder::new().num_threads(n).build_global();
     }
+    eco::init(cfg.eco_taxonomy.as_deref()).expect("ECO taxonomy load failed");
+    if cfg.eco_from_moves || cfg.eco_prefer_computed {
+        openings::init(&cfg.opening_book).expect("opening book load failed");
+    }
 
     // list_url lives in config; CLI --list-url can override
     let list_url = if args.list_url.is_empty() { cfg.list_url.clone() } else { args.list_url.clone() };
 
+    if args.rebucket {
+        let dbh = db::connect_from_env().await.expect("DB connect failed");
+        db::run_migrations(&dbh).await.expect("DB migrations failed");
+        rebucket(&dbh, &cfg, args.into.as_deref(), args.save).await;
+        return Ok(());
+    }
+
+    if args.rebuild_cohorts {
+        if !args.save {
+            eprintln!("ℹ️ --rebuild-cohorts only makes sense with --save.");
+            return Ok(());
+        }
+        let dbh = db::connect_from_env().await.expect("DB connect failed");
+        db::run_migrations(&dbh).await.expect("DB migrations failed");
+        check_compatibility(&dbh, &cfg, args.reaggregate).await;
+        let cfg = cfg.cohorts_only();
+
+        // reset and rebuild in one transaction: a failed month leaves the old rows
+        let mut tx = dbh.begin().await.expect("DB begin failed");
+        db::reset_cohorts(&mut tx).await.expect("DB cohorts reset failed");
+        if args.ingest_remote {
+            let done = db::already_ingested_months(&dbh).await.expect("DB query failed");
+            let mut plan = remote::plan_no_db(&list_url, args.since.as_deref(), args.until.as_deref())
+                .await
+                .expect("build plan (no DB) failed");
+            plan.retain(|it| done.contains(&it.month));
+            for item in plan {
+                let res: anyhow::Result<_> = async {
+                    let (map, games, dur_ms, _) = remote::stream_and_aggregate_async(&item.url, None, &cfg)
+                        .await
+                        .context("stream+aggregate")?;
+                    record_cohorts(Some(&mut tx), &map, None, &cfg, None).await.context("cohorts")?;
+                    Ok((games, dur_ms))
+                }
+                .await;
+                match res {
+                    Ok((games, dur_ms)) => {
+                        eprintln!("{} | {:.3}s | games={} | cohorts rebuilt", item.month, (dur_ms as f64)/1000.0, games);
+                    }
+                    Err(e) => {
+                        eprintln!("❌ {} | failed: {:#}", item.month, e);
+                        eprintln!("❌ Cohorts not rebuilt; player_first_seen/player_cohorts are left as they were.");
+                        std::process::exit(1);
+                    }
+                }
+            }
+        } else {
+            let (map, _) =
+                aggregator::aggregate_from_reader(std::io::BufReader::new(std::io::stdin().lock()), &cfg)?;
+            record_cohorts(Some(&mut tx), &map, args.out.as_deref(), &cfg, None).await.expect("DB cohorts write failed");
+        }
+        tx.commit().await.expect("DB commit failed");
+        eprintln!("✅ Cohorts rebuilt.");
+        return Ok(());
+    }
+
     // --- REMOTE MODE ---
     if args.ingest_remote {
         eprintln!("➡️ Remote ingest starting...");
@@ -39,48 +126,72 @@ async fn main() -> std::io::Result<()> {
             // save: DB on, migrations, skip already ingested, upsert
             let dbh = db::connect_from_env().await.expect("DB connect failed");
             db::run_migrations(&dbh).await.expect("DB migrations failed");
+            db::apply_pg_partitioning(&dbh, cfg.pg_partition_by).await.expect("DB partitioning failed");
+            let redo = check_compatibility(&dbh, &cfg, args.reaggregate).await;
+            let redo_inputs = redo_local_inputs(&dbh, &redo).await;
 
             vprintln!("remote: building plan from {}", list_url);
-            let plan = remote::build_plan(&dbh, &list_url, args.since.as_deref(), args.until.as_deref())
+            let plan = remote::build_plan(&dbh, &list_url, args.since.as_deref(), args.until.as_deref(), &redo.months)
                 .await
                 .expect("build plan failed");
             vprintln!("remote: plan size after filters = {}", plan.len());
 
+            // every month built with other settings is redone, or none
+            let mut left_out: Vec<&String> =
+                redo.months.iter().filter(|m| !plan.iter().any(|it| &it.month == *m)).collect();
+            if !left_out.is_empty() {
+                left_out.sort();
+                eprintln!(
+                    "❌ --reaggregate must redo every month built with other settings, but {} {} outside the plan (--since/--until, list); nothing written.",
+                    left_out.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(","),
+                    if left_out.len() == 1 { "is" } else { "are" }
+                );
+                std::process::exit(1);
+            }
+            if !redo_inputs.is_empty() {
+                let mut tx = dbh.begin().await.expect("DB begin failed");
+                take_out_local_inputs(&mut tx, &redo_inputs, &cfg).await.expect("DB local input removal failed");
+                tx.commit().await.expect("DB commit failed");
+            }
+
             if plan.is_empty() {
+                sync_eco_groups(&dbh).await;
                 eprintln!("ℹ️ No remote files were processed.");
                 return Ok(());
             }
 
+            // re-aggregated months' games are in the stored seen-set already
+            let redo_cfg = cfg.with_fresh_dedup();
             let mut processed = 0usize;
+            let mut failed: Vec<(String, String)> = Vec::new();
             for item in plan {
-                let start_iso = Utc::now().to_rfc3339();
-                db::mark_ingestion_start(&dbh, &item.month, &item.url, &start_iso)
-                    .await
-                    .expect("mark start failed");
-
                 let out_csv = make_monthly_out_path(args.out.as_deref(), &item.month);
-
-                let (map, games, dur_ms) =
-                    remote::stream_and_aggregate_async(&item.url, out_csv.as_deref(), &cfg)
-                        .await
-                        .expect("stream+aggregate failed");
-
-                db::bulk_upsert_aggregates(&dbh, &map, cfg.db_batch_rows)
-                    .await
-                    .expect("DB bulk upsert failed");
-
-                let finish_iso = Utc::now().to_rfc3339();
-                db::mark_ingestion_finish(
-                    &dbh, &item.month, games as i64, dur_ms as i64, "success", &finish_iso,
-                )
-                .await
-                .expect("mark finish failed");
-
-                eprintln!("{} | {:.3}s | games={}", item.month, (dur_ms as f64)/1000.0, games);
-                processed += 1;
+                let cfg = if redo.months.contains(&item.month) { &redo_cfg } else { &cfg };
+                cfg.begin_dedup_pass();
+                match ingest_month(&dbh, &item, out_csv.as_deref(), cfg).await {
+                    Ok((map, games, dur_ms)) => {
+                        cfg.persist_dedup().expect("dedup seen-set write failed");
+                        eprintln!("{} | {:.3}s | games={}{}", item.month, (dur_ms as f64)/1000.0, games, excluded_note(&map));
+                        processed += 1;
+                    }
+                    Err(e) => {
+                        // the month's transaction was rolled back, and its games
+                        // must not count as seen on a retry; record why and move on
+                        cfg.rollback_dedup();
+                        let msg = format!("{:#}", e);
+                        eprintln!("❌ {} | failed: {}", item.month, msg);
+                        let finish_iso = Utc::now().to_rfc3339();
+                        if let Err(e2) = db::mark_ingestion_failed(&dbh, &item.month, &msg, &finish_iso).await {
+                            eprintln!("❌ {} | could not mark as failed: {:#}", item.month, e2);
+                        }
+                        failed.push((item.month.clone(), msg));
+                    }
+                }
             }
+            sync_eco_groups(&dbh).await;
 
             eprintln!("✅ Remote ingest completed ({} month{}).", processed, if processed==1 {""} else {"s"});
+            exit_on_failures(&failed);
             return Ok(());
         } else {
             // DRY-RUN remote: no DB touches at all
@@ -96,18 +207,31 @@ async fn main() -> std::io::Result<()> {
             }
 
             let mut processed = 0usize;
+            let mut failed: Vec<(String, String)> = Vec::new();
             for item in plan {
                 let out_csv = make_monthly_out_path(args.out.as_deref(), &item.month);
-                let (_map, games, dur_ms) =
-                    remote::stream_and_aggregate_async(&item.url, out_csv.as_deref(), &cfg)
+                cfg.begin_dedup_pass();
+                let res = match remote::stream_and_aggregate_async(&item.url, out_csv.as_deref(), &cfg).await {
+                    Ok((map, games, dur_ms, _)) => record_cohorts(None, &map, out_csv.as_deref(), &cfg, None)
                         .await
-                        .expect("stream+aggregate failed (dry-run)");
-
-                eprintln!("{} | {:.3}s | games={}", item.month, (dur_ms as f64)/1000.0, games);
-                processed += 1;
+                        .map(|_| (map, games, dur_ms)),
+                    Err(e) => Err(e),
+                };
+                match res {
+                    Ok((map, games, dur_ms)) => {
+                        eprintln!("{} | {:.3}s | games={}{}", item.month, (dur_ms as f64)/1000.0, games, excluded_note(&map));
+                        processed += 1;
+                    }
+                    Err(e) => {
+                        cfg.rollback_dedup();
+                        eprintln!("❌ {} | failed: {:#}", item.month, e);
+                        failed.push((item.month.clone(), format!("{:#}", e)));
+                    }
+                }
             }
 
             eprintln!("✅ Remote ingest completed ({} month{}).", processed, if processed==1 {""} else {"s"});
+            exit_on_failures(&failed);
             return Ok(());
         }
     }
@@ -118,14 +242,82 @@ async fn main() -> std::io::Result<()> {
         // connect + upsert
         let dbh = db::connect_from_env().await.expect("DB connect failed");
         db::run_migrations(&dbh).await.expect("DB migrations failed");
+        db::apply_pg_partitioning(&dbh, cfg.pg_partition_by).await.expect("DB partitioning failed");
+        let redo = check_compatibility(&dbh, &cfg, args.reaggregate).await;
+        if !redo.months.is_empty() {
+            let mut months: Vec<&str> = redo.months.iter().map(String::as_str).collect();
+            months.sort_unstable();
+            eprintln!(
+                "❌ Remote months {} were built with other settings; re-aggregate them first with --remote --reaggregate --save. Nothing written.",
+                months.join(",")
+            );
+            std::process::exit(1);
+        }
+        let redo_inputs = redo_local_inputs(&dbh, &redo).await;
 
-        let (map, total_games) =
-            aggregator::aggregate_from_reader(std::io::BufReader::new(std::io::stdin().lock()), &cfg)?;
-        db::bulk_upsert_aggregates(&dbh, &map, cfg.db_batch_rows).await.expect("DB bulk upsert failed");
+        let mut cfg = cfg;
+        if args.force && cfg.dedup_file.is_some() {
+            // a persisted seen-set already holds this input's games
+            cfg.dedup_file = None;
+            cfg.init_dedup()?;
+        }
+        // a file on stdin is hashed first, so a saved input is refused before the pass
+        let early = match local::hash_stdin_file()? {
+            Some(hash) => Some(prior_local_ingestion(&dbh, &hash, args.force).await?),
+            None => None,
+        };
+        let start_iso = Utc::now().to_rfc3339();
+        let mut reader = local::HashingReader::new(std::io::BufReader::new(std::io::stdin().lock()));
+        let (map, total_games) = aggregator::aggregate_from_reader(&mut reader, &cfg)?;
+        let content_hash = reader.hex_digest();
+        let prior = match early {
+            Some(prior) => prior,
+            None => prior_local_ingestion(&dbh, &content_hash, args.force).await?,
+        };
+        // an input whose every save failed has no rows to refuse or replace
+        let saved = prior.as_ref().filter(|p| p.status == "success");
+
+        let mut record = db::LocalIngestion {
+            manifest: Some(manifest::render(&cfg, args.source.as_deref().unwrap_or("stdin"), &content_hash)),
+            content_hash,
+            file_name: args.source.clone(),
+            months: local::months_of(&map),
+            games: total_games as i64,
+            game_filter: cfg.compiled_filter.as_ref().map(|f| f.source().to_string()),
+            started_at: start_iso,
+            finished_at: String::new(),
+            attempts: prior.as_ref().map_or(1, |p| p.attempts + 1),
+            eco_taxonomy: Some(eco::taxonomy().version.clone()),
+            status: "success".to_string(),
+            error: None,
+        };
+        // the other inputs built with other settings come out with this one's save
+        let others: Vec<db::LocalIngestion> =
+            redo_inputs.into_iter().filter(|input| input.content_hash != record.content_hash).collect();
+        if let Err(e) = save_local_input(&dbh, &map, &mut record, saved, &others, args.out.as_deref(), &cfg).await {
+            // the transaction was rolled back; record why, like a failed month
+            let msg = format!("{:#}", e);
+            let name = args.source.as_deref().unwrap_or(&record.content_hash[..12]);
+            eprintln!("❌ {} | failed: {}", name, msg);
+            let finish_iso = Utc::now().to_rfc3339();
+            if let Err(e2) =
+                db::mark_local_ingestion_failed(&dbh, &record.content_hash, args.source.as_deref(), &msg, &finish_iso).await
+            {
+                eprintln!("❌ {} | could not mark as failed: {:#}", name, e2);
+            }
+            eprintln!("❌ Local input {} failed; nothing written.", name);
+            std::process::exit(1);
+        }
+        cfg.persist_dedup().context("dedup seen-set write")?;
+        sync_eco_groups(&dbh).await;
         if let Some(out) = args.out.as_deref() {
-            aggregator::write_csv(&map, Path::new(out)).expect("CSV write failed");
+            aggregator::write_csvs(&map, Path::new(out), &cfg).context("CSV write")?;
         }
         println!("{}", total_games);
+        if map.filtered_out > 0 { eprintln!("ℹ️ Skipped {} games (filter).", map.filtered_out); }
+        if map.bot_excluded > 0 { eprintln!("ℹ️ Skipped {} BOT games (bot_games policy).", map.bot_excluded); }
+        if map.human_excluded > 0 { eprintln!("ℹ️ Skipped {} non-BOT games (bot_games policy).", map.human_excluded); }
+        if map.duplicates > 0 { eprintln!("ℹ️ Skipped {} duplicate games (dedup).", map.duplicates); }
         eprintln!("✅ Local ingest completed.");
         return Ok(());
     } else {
@@ -133,14 +325,361 @@ async fn main() -> std::io::Result<()> {
         let (map, total_games) =
             aggregator::aggregate_from_reader(std::io::BufReader::new(std::io::stdin().lock()), &cfg)?;
         if let Some(out) = args.out.as_deref() {
-            aggregator::write_csv(&map, Path::new(out)).expect("CSV write failed");
+            aggregator::write_csvs(&map, Path::new(out), &cfg).expect("CSV write failed");
         }
+        record_cohorts(None, &map, args.out.as_deref(), &cfg, None).await.expect("CSV write failed");
         println!("{}", total_games);
+        if map.filtered_out > 0 { eprintln!("ℹ️ Skipped {} games (filter).", map.filtered_out); }
+        if map.bot_excluded > 0 { eprintln!("ℹ️ Skipped {} BOT games (bot_games policy).", map.bot_excluded); }
+        if map.human_excluded > 0 { eprintln!("ℹ️ Skipped {} non-BOT games (bot_games policy).", map.human_excluded); }
+        if map.duplicates > 0 { eprintln!("ℹ️ Skipped {} duplicate games (dedup).", map.duplicates); }
+        if map.sampled_out > 0 { eprintln!("🔎 Left out {} games (sample).", map.sampled_out); }
+        if map.truncated { eprintln!("🔎 Stopped early at the preview limit."); }
         eprintln!("✅ Local ingest completed.");
         return Ok(());
     }
 }
 
+/// Stream, aggregate and save one remote month. Everything after
+/// `mark_ingestion_start` happens in one transaction, so an error leaves no rows behind.
+async fn ingest_month(
+    dbh: &db::Db,
+    item: &remote::PlanItem,
+    out_csv: Option<&Path>,
+    cfg: &config::Config,
+) -> anyhow::Result<(aggregator::Aggregation, usize, u128)> {
+    let start_iso = Utc::now().to_rfc3339();
+    db::mark_ingestion_start(dbh, &item.month, &item.url, &start_iso, cfg.compiled_filter.as_ref().map(|f| f.source()))
+        .await
+        .context("mark start")?;
+
+    let (map, games, dur_ms, source_sha256) = remote::stream_and_aggregate_async(&item.url, out_csv, cfg)
+        .await
+        .context("stream+aggregate")?;
+
+    // one transaction per month: a crash leaves either the old rows or the
+    // new ones plus 'success', never a half-added month
+    let mut tx = dbh.begin().await?;
+    db::delete_month(&mut tx, &item.month).await.context("delete month")?;
+    db::write_aggregation(&mut tx, &map, cfg.top_k, cfg.db_batch_rows).await?;
+    record_cohorts(Some(&mut tx), &map, out_csv, cfg, None).await.context("cohorts")?;
+    // local inputs' rows of this month were deleted with it
+    let buckets = cfg.buckets()?;
+    db::restore_local_rows(&mut tx, &item.month, &buckets, cfg.top_k, cfg.top_capacity, cfg.db_batch_rows)
+        .await
+        .context("restore local rows")?;
+
+    let finish_iso = Utc::now().to_rfc3339();
+    let stats = db::FinishStats {
+        games: games as i64,
+        bot_excluded: map.bot_excluded as i64,
+        human_excluded: map.human_excluded as i64,
+        filtered_out: map.filtered_out as i64,
+        duplicates: map.duplicates as i64,
+        duration_ms: dur_ms as i64,
+        eco_taxonomy: &eco::taxonomy().version,
+        manifest: manifest::render(cfg, &item.url, &source_sha256),
+    };
+    db::mark_ingestion_finish(&mut tx, &item.month, &stats, "success", &finish_iso)
+        .await
+        .context("mark finish")?;
+    tx.commit().await.context("commit")?;
+    Ok((map, games, dur_ms))
+}
+
+/// Refuse to save into a database holding rows built with settings this run
+/// can't be mixed with: another ECO taxonomy (their `eco_group`s aren't
+/// comparable) or other `[compat]` settings of the run manifest (bucket
+/// edges, ...), unless `--reaggregate`. Returns what to redo.
+async fn check_compatibility(dbh: &db::Db, cfg: &config::Config, reaggregate: bool) -> Redo {
+    let version = &eco::taxonomy().version;
+    let mismatch = db::taxonomy_mismatches(dbh, version).await.expect("DB query failed");
+    let saved = db::saved_manifests(dbh).await.expect("DB query failed");
+    let compat = manifest::Compat::new(cfg);
+
+    // what differs -> (months, local inputs' hashes)
+    let mut conflicts: BTreeMap<String, (Vec<String>, Vec<String>)> = BTreeMap::new();
+    let mut names = BTreeMap::new();
+    for (month, v) in mismatch.months {
+        conflicts.entry(format!("eco_taxonomy: {} (this run: {})", v, version)).or_default().0.push(month);
+    }
+    for (name, hash, v) in mismatch.local_inputs {
+        conflicts.entry(format!("eco_taxonomy: {} (this run: {})", v, version)).or_default().1.push(hash.clone());
+        names.insert(hash, name);
+    }
+    for (month, m) in &saved.months {
+        for diff in compat.differences(m) {
+            conflicts.entry(diff).or_default().0.push(month.clone());
+        }
+    }
+    for (name, hash, m) in &saved.local_inputs {
+        for diff in compat.differences(m) {
+            conflicts.entry(diff).or_default().1.push(hash.clone());
+            names.insert(hash.clone(), name.clone());
+        }
+    }
+    if conflicts.is_empty() { return Redo::default(); }
+
+    eprintln!(
+        "{} Saved rows were built with settings this run can't be mixed with:",
+        if reaggregate { "ℹ️" } else { "❌" }
+    );
+    let mut redo = Redo::default();
+    for (diff, (m, l)) in &conflicts {
+        let mut whom = Vec::new();
+        match m.as_slice() {
+            [] => {}
+            [one] => whom.push(format!("month {}", one)),
+            [first, .., last] => whom.push(format!("{} months {}..{}", m.len(), first, last)),
+        }
+        if !l.is_empty() {
+            let l: Vec<&str> = l.iter().map(|hash| names[hash].as_str()).collect();
+            whom.push(format!("local input{} {}", if l.len()==1 {""} else {"s"}, l.join(", ")));
+        }
+        eprintln!("   {} | {}", diff, whom.join(", "));
+        redo.months.extend(m.iter().cloned());
+        redo.inputs.extend(l.iter().map(|hash| (hash.clone(), names[hash].clone())));
+    }
+    if !reaggregate {
+        eprintln!("   Restore those settings (eco_taxonomy, config.toml, --filter), or re-aggregate with --reaggregate.");
+        std::process::exit(1);
+    }
+    eprintln!("ℹ️ Re-aggregating them with this run's settings.");
+    redo
+}
+
+/// What `--reaggregate` redoes: the remote months to re-ingest, and the local
+/// inputs (content hash -> name) to take out until they are fed again.
+#[derive(Default)]
+struct Redo {
+    months: HashSet<String>,
+    inputs: BTreeMap<String, String>,
+}
+
+/// The saved local inputs `redo` takes out. Inputs saved before each input's
+/// rows were kept can't be told apart from the rest, so they are refused.
+async fn redo_local_inputs(dbh: &db::Db, redo: &Redo) -> Vec<db::LocalIngestion> {
+    let mut inputs = Vec::new();
+    let mut tx = dbh.begin().await.expect("DB begin failed");
+    for (hash, name) in &redo.inputs {
+        let Some(input) = db::find_local_ingestion(dbh, hash).await.expect("DB query failed") else { continue };
+        if !input.months.is_empty() && !db::has_local_rows(&mut tx, hash).await.expect("DB query failed") {
+            eprintln!(
+                "❌ Local input {} was saved before each input's rows were kept, so its rows can't be taken out of months {}; nothing written.",
+                name,
+                input.months.join(",")
+            );
+            std::process::exit(1);
+        }
+        inputs.push(input);
+    }
+    inputs
+}
+
+/// Take local inputs built with other settings out of the month tables and
+/// forget them, so no saved row mixes settings; each must be fed again.
+async fn take_out_local_inputs(tx: &mut db::Tx, inputs: &[db::LocalIngestion], cfg: &config::Config) -> anyhow::Result<()> {
+    for input in inputs {
+        db::remove_local_input(tx, input, &cfg.buckets()?, cfg.top_k, cfg.top_capacity, cfg.db_batch_rows).await?;
+        db::forget_local_ingestion(tx, &input.content_hash).await?;
+        eprintln!(
+            "ℹ️ Took local input {} out (months {}); feed it again with --save --force.",
+            input.file_name.as_deref().unwrap_or(&input.content_hash[..12]),
+            input.months.join(",")
+        );
+    }
+    Ok(())
+}
+
+/// Rewrite `eco_groups` from this run's taxonomy, once no saved rows were
+/// built with another version (until then they keep the labels they had).
+async fn sync_eco_groups(dbh: &db::Db) {
+    let mismatch = db::taxonomy_mismatches(dbh, &eco::taxonomy().version).await.expect("DB query failed");
+    if mismatch.months.is_empty() && mismatch.local_inputs.is_empty() {
+        db::sync_eco_groups(dbh).await.expect("DB eco_groups sync failed");
+    }
+}
+
+/// The earlier ingestion of the input with this content hash. Exits when it
+/// was saved and this run can't replace it: without --force, or when its
+/// old rows weren't kept.
+async fn prior_local_ingestion(dbh: &db::Db, content_hash: &str, force: bool) -> anyhow::Result<Option<db::LocalIngestion>> {
+    let prior = db::find_local_ingestion(dbh, content_hash).await?;
+    let Some(saved) = prior.as_ref().filter(|p| p.status == "success") else { return Ok(prior) };
+    if !force {
+        eprintln!(
+            "❌ This input ({}) was already saved on {} as {} ({} games, months {}); nothing written. Use --force to replace it.",
+            &content_hash[..12],
+            saved.finished_at,
+            saved.file_name.as_deref().unwrap_or("stdin"),
+            saved.games,
+            saved.months.join(",")
+        );
+        std::process::exit(1);
+    }
+    // --force: only this input's old rows come out; other inputs and remote months stay
+    if !saved.months.is_empty() && !db::has_local_rows(&mut dbh.begin().await?, content_hash).await? {
+        eprintln!(
+            "❌ This input ({}) was saved before each input's rows were kept, so its old rows can't be told apart from the rest of months {}; nothing written.",
+            &content_hash[..12],
+            saved.months.join(",")
+        );
+        std::process::exit(1);
+    }
+    Ok(prior)
+}
+
+/// One local input's save, in one transaction: `others` (inputs built with
+/// other settings) and, with --force, its `prior` rows come out first.
+async fn save_local_input(
+    dbh: &db::Db,
+    map: &aggregator::Aggregation,
+    record: &mut db::LocalIngestion,
+    prior: Option<&db::LocalIngestion>,
+    others: &[db::LocalIngestion],
+    out: Option<&Path>,
+    cfg: &config::Config,
+) -> anyhow::Result<()> {
+    let mut tx = dbh.begin().await?;
+    take_out_local_inputs(&mut tx, others, cfg).await.context("local input removal")?;
+    if let Some(prior) = prior {
+        db::remove_local_input(&mut tx, prior, &cfg.buckets()?, cfg.top_k, cfg.top_capacity, cfg.db_batch_rows)
+            .await
+            .context("local input removal")?;
+    }
+    db::write_aggregation(&mut tx, map, cfg.top_k, cfg.db_batch_rows).await?;
+    db::save_local_rows(&mut tx, &record.content_hash, map, cfg.db_batch_rows).await.context("local rows")?;
+    record_cohorts(Some(&mut tx), map, out, cfg, Some(&record.content_hash)).await.context("cohorts")?;
+    record.finished_at = Utc::now().to_rfc3339();
+    db::record_local_ingestion(&mut tx, record).await.context("local ingestion record")?;
+    tx.commit().await.context("commit")?;
+    Ok(())
+}
+
+/// `--rebucket`: sum the saved rows into the configured buckets. The stored
+/// edges come from the run manifests; refuses new edges that would split a
+/// stored bucket.
+async fn rebucket(dbh: &db::Db, cfg: &config::Config, into: Option<&str>, save: bool) {
+    let new = cfg.buckets().expect("invalid bucket_edges");
+    let saved = db::saved_manifests(dbh).await.expect("DB query failed");
+    let recorded: BTreeSet<String> = saved
+        .months
+        .iter()
+        .map(|(_, m)| m)
+        .chain(saved.local_inputs.iter().map(|(_, _, m)| m))
+        .filter_map(|m| manifest::bucket_edges(m))
+        .collect();
+    let stored = db::stored_buckets(dbh).await.expect("DB query failed");
+    let recorded: Vec<&String> = recorded.iter().collect();
+    let old = match recorded.as_slice() {
+        // rows saved before manifests: the widest width every stored bucket is a multiple of
+        [] => pgn::Buckets::Every(stored.iter().fold(0, |g, &b| gcd(g, b))),
+        [one] => pgn::Buckets::parse(one).unwrap_or_else(|| {
+            eprintln!("❌ Unreadable bucket_edges {:?} in the run manifests.", one);
+            std::process::exit(1);
+        }),
+        several => {
+            let several: Vec<&str> = several.iter().map(|s| s.as_str()).collect();
+            eprintln!("❌ Saved rows use several bucket layouts ({}); rebucketing needs one.", several.join("; "));
+            std::process::exit(1);
+        }
+    };
+    if let Some(b) = stored.iter().find(|&&b| !old.is_edge(b)) {
+        eprintln!("❌ aggregates holds bucket {}, which isn't an edge of the recorded {}.", b, old);
+        std::process::exit(1);
+    }
+    if new == old {
+        eprintln!("ℹ️ The saved rows already use {}; nothing to rebucket.", new);
+        return;
+    }
+    if !new.aligns_with(&old) {
+        eprintln!(
+            "❌ {} doesn't align with the stored {}: every new edge must be a stored one, so that no stored bucket is split.",
+            new, old
+        );
+        std::process::exit(1);
+    }
+    if !save {
+        match into {
+            Some(table) => eprintln!("ℹ️ {} → {} aligns; add --save to write the rebucketed aggregates to {}.", old, new, table),
+            None => eprintln!("ℹ️ {} → {} aligns; add --save to rewrite aggregates, endgames and player_cohorts.", old, new),
+        }
+        return;
+    }
+
+    match into {
+        Some(table) => {
+            let done = db::rebucket_into(dbh, table, &new).await.expect("DB rebucket failed");
+            eprintln!("{} | {} rows → {} rows in {}", done.table, done.rows_before, done.rows_after, table);
+            eprintln!(
+                "✅ Wrote {} ({}). The saved rows still use {}, so --save runs with these settings are refused.",
+                table, new, old
+            );
+        }
+        None => {
+            let done = db::rebucket_in_place(dbh, &new, |m| manifest::rebucketed(m, &new))
+                .await
+                .expect("DB rebucket failed");
+            for t in done {
+                eprintln!("{} | {} rows → {} rows", t.table, t.rows_before, t.rows_after);
+            }
+            eprintln!("✅ Rebucketed {} → {}.", old, new);
+        }
+    }
+}
+
+fn gcd(a: u16, b: u16) -> u16 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Summary of failed months; exits non-zero when there are any.
+fn exit_on_failures(failed: &[(String, String)]) {
+    if failed.is_empty() { return; }
+    eprintln!("❌ {} month{} failed (retried on the next run):", failed.len(), if failed.len()==1 {""} else {"s"});
+    for (month, err) in failed {
+        eprintln!("   {} | {}", month, err);
+    }
+    std::process::exit(1);
+}
+
+/// New/returning split of one pass: against the stored first months (and
+/// saved, kept for the `local` input if set) when `tx` is set, within the pass
+/// in a scratch database otherwise; `<out>-cohorts` CSV with --out.
+async fn record_cohorts(
+    tx: Option<&mut db::Tx>,
+    agg: &aggregator::Aggregation,
+    out: Option<&Path>,
+    cfg: &config::Config,
+    local: Option<&str>,
+) -> anyhow::Result<()> {
+    if agg.cohort_players.is_empty() { return Ok(()); }
+    let buckets = cfg.buckets()?;
+    let cohorts = match tx {
+        Some(tx) => cohort::save(tx, &agg.cohort_players, &buckets, local, cfg.db_batch_rows).await?,
+        None => {
+            let scratch = db::Scratch::open("cohorts").await?;
+            let mut tx = scratch.db.begin().await?;
+            cohort::save(&mut tx, &agg.cohort_players, &buckets, None, cfg.db_batch_rows).await?
+        }
+    };
+    if let Some(out) = out {
+        let out = aggregator::output_path(out, cfg);
+        cohort::write_cohorts_csv(&cohorts, &aggregator::sibling_path(&out, "cohorts"))?;
+    }
+    Ok(())
+}
+
+fn excluded_note(agg: &aggregator::Aggregation) -> String {
+    let mut note = String::new();
+    if agg.filtered_out > 0 { note.push_str(&format!(" | filtered_out={}", agg.filtered_out)); }
+    if agg.bot_excluded > 0 { note.push_str(&format!(" | bot_excluded={}", agg.bot_excluded)); }
+    if agg.human_excluded > 0 { note.push_str(&format!(" | human_excluded={}", agg.human_excluded)); }
+    if agg.duplicates > 0 { note.push_str(&format!(" | duplicates={}", agg.duplicates)); }
+    if agg.sampled_out > 0 { note.push_str(&format!(" | sampled_out={}", agg.sampled_out)); }
+    if agg.truncated { note.push_str(" | stopped early (preview limit)"); }
+    note
+}
+
 fn make_monthly_out_path(base: Option<&Path>, month: &str) -> Option<PathBuf> {
     base.map(|p| {
         let mut name = p.to_path_buf();
@@ -0,0 +1,170 @@
+// src/manifest.rs
+// Provenance of saved rows. Each saved month (`ingestions.manifest`) and local
+// input (`local_ingestions.manifest`) records, as TOML, the binary and
+// settings that built it and the SHA-256 of its source (the downloaded .zst,
+// or the PGN read from stdin). The `[compat]` part holds the settings that
+// change what a row counts; a save run whose settings differ from a stored
+// manifest is refused, since e.g. 100- and 200-point buckets can't be summed.
+// Settings that only add or skip side tables (draw_reasons, cohorts, ...) or
+// tune performance are recorded in `config_hash` but never refused.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::{BotPolicy, Config};
+use crate::eco;
+use crate::openings;
+use crate::pgn::Buckets;
+
+/// Settings every saved row of a database must share.
+#[derive(Serialize, Debug)]
+pub struct Compat {
+    /// Rating bucket edges, e.g. "every 200" (see `pgn::Buckets`).
+    pub bucket_edges: String,
+    /// Where `eco_group` comes from: "header" | "header, else moves" | "moves, else header".
+    pub eco_source: String,
+    /// SHA-256 of the opening book ("" when ECOs come from headers only).
+    pub opening_book: String,
+    pub bot_games: BotPolicy,
+    pub exclude_unfinished: bool,
+    /// Header filter expression ("" when none).
+    pub filter: String,
+}
+
+impl Compat {
+    /// Settings of this run (call after `openings::init`).
+    pub fn new(cfg: &Config) -> Compat {
+        let from_moves = cfg.eco_from_moves || cfg.eco_prefer_computed;
+        Compat {
+            bucket_edges: cfg.buckets().expect("bucket_edges checked at startup").to_string(),
+            eco_source: match (cfg.eco_prefer_computed, cfg.eco_from_moves) {
+                (true, _) => "moves, else header",
+                (false, true) => "header, else moves",
+                (false, false) => "header",
+            }
+            .to_string(),
+            opening_book: if from_moves { openings::book().sha256().to_string() } else { String::new() },
+            bot_games: cfg.bot_games,
+            exclude_unfinished: cfg.exclude_unfinished,
+            filter: cfg.compiled_filter.as_ref().map_or(String::new(), |f| f.source().to_string()),
+        }
+    }
+
+    /// How the `[compat]` table of a stored manifest differs from these settings,
+    /// one "key: stored (this run: current)" line per setting. Keys only one side
+    /// knows (manifests of other versions) are skipped; an unreadable manifest
+    /// yields nothing.
+    pub fn differences(&self, stored_manifest: &str) -> Vec<String> {
+        let Ok(stored) = stored_manifest.parse::<toml::Table>() else { return Vec::new() };
+        let Some(toml::Value::Table(stored)) = stored.get("compat") else { return Vec::new() };
+        let Ok(current) = toml::Table::try_from(self) else { return Vec::new() };
+        let mut diffs = Vec::new();
+        for (key, was) in stored {
+            match current.get(key) {
+                Some(now) if now != was => diffs.push(format!("{}: {} (this run: {})", key, show(was), show(now))),
+                _ => {}
+            }
+        }
+        diffs
+    }
+}
+
+fn show(v: &toml::Value) -> String {
+    match v {
+        toml::Value::String(s) if s.is_empty() => "none".to_string(),
+        toml::Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    tool_version: &'static str,
+    config_hash: String,
+    eco_taxonomy: &'a str,
+    source: &'a str,
+    source_sha256: &'a str,
+    compat: Compat,
+}
+
+/// Manifest of one saved ingestion, as TOML.
+pub fn render(cfg: &Config, source: &str, source_sha256: &str) -> String {
+    let manifest = Manifest {
+        tool_version: env!("CARGO_PKG_VERSION"),
+        config_hash: config_hash(cfg),
+        eco_taxonomy: &eco::taxonomy().version,
+        source,
+        source_sha256,
+        compat: Compat::new(cfg),
+    };
+    toml::to_string(&manifest).expect("manifest serializes")
+}
+
+/// SHA-256 of the effective settings (config.toml plus CLI overrides).
+fn config_hash(cfg: &Config) -> String {
+    sha256_hex(toml::to_string(cfg).expect("config serializes").as_bytes())
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `[compat] bucket_edges` of a stored manifest.
+pub fn bucket_edges(stored_manifest: &str) -> Option<String> {
+    let stored = stored_manifest.parse::<toml::Table>().ok()?;
+    stored.get("compat")?.get("bucket_edges")?.as_str().map(str::to_string)
+}
+
+/// A stored manifest after its rows were rebucketed to `new`: `bucket_edges`
+/// becomes `new`, and `rebucketed_from` keeps the edges the rows were built with.
+pub fn rebucketed(stored_manifest: &str, new: &Buckets) -> String {
+    let Ok(mut stored) = stored_manifest.parse::<toml::Table>() else { return stored_manifest.to_string() };
+    let old = match stored.get_mut("compat").and_then(toml::Value::as_table_mut) {
+        Some(compat) => compat.insert("bucket_edges".to_string(), toml::Value::String(new.to_string())),
+        None => return stored_manifest.to_string(),
+    };
+    if let Some(old) = old {
+        stored.entry("rebucketed_from").or_insert(old);
+    }
+    toml::to_string(&stored).expect("manifest serializes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_settings_have_no_differences() {
+        let cfg = Config::default();
+        let stored = render(&cfg, "https://x/2024-01.pgn.zst", "abc");
+        assert!(Compat::new(&cfg).differences(&stored).is_empty());
+        // settings outside [compat] don't count
+        let other = Config { draw_reasons: true, cohorts: true, ..Config::default() };
+        assert!(Compat::new(&other).differences(&stored).is_empty());
+    }
+
+    #[test]
+    fn differing_settings_are_listed() {
+        let stored = render(&Config { bucket_size: 100, exclude_unfinished: true, ..Config::default() }, "stdin", "abc");
+        let diffs = Compat::new(&Config::default()).differences(&stored);
+        assert_eq!(
+            diffs,
+            ["bucket_edges: every 100 (this run: every 200)", "exclude_unfinished: true (this run: false)"]
+        );
+    }
+
+    #[test]
+    fn legacy_manifests_only_compare_known_keys() {
+        let compat = Compat::new(&Config::default());
+        // unreadable, or from before [compat]: nothing to compare (rows saved
+        // before manifests have NULL ones, which `db::saved_manifests` skips)
+        for stored in ["", "not = [toml", "tool_version = \"0.1.0\"\nsource = \"stdin\"\n"] {
+            assert!(compat.differences(stored).is_empty(), "{:?}", stored);
+        }
+        // keys this version doesn't know are skipped, missing ones aren't reported
+        let old = "[compat]\nbucket_edges = \"every 100\"\nretired_setting = \"x\"\n";
+        assert_eq!(compat.differences(old), ["bucket_edges: every 100 (this run: every 200)"]);
+        let old = "[compat]\nbucket_edges = \"every 200\"\nfilter = \"\"\n";
+        assert!(compat.differences(old).is_empty());
+    }
+}
@@ -2,44 +2,400 @@ use std::hash::{Hash, Hasher};
 
 #[derive(Clone, Debug, Eq)]
 pub struct Key {
-    pub month: String,     // "YYYY-MM"
-    pub eco_group: String, // e.g., B20, C00, E60, U00
-    pub w_bucket: u16,     // lower bound of bucket (e.g., 2200)
+    pub month: String,       // "YYYY-MM"
+    pub speed: &'static str, // bullet, blitz, ... (see `pgn::speed_from_headers`)
+    pub eco_group: String,   // e.g., B20, C00, E60, U00
+    pub w_bucket: u16,       // lower bound of bucket (e.g., 2200)
     pub b_bucket: u16,
+    pub w_title: String,     // "none" | "titled" | "bot" (see `pgn::title_class`)
+    pub b_title: String,
 }
 
 impl PartialEq for Key {
     fn eq(&self, other: &Self) -> bool {
         self.month == other.month
+            && self.speed == other.speed
             && self.eco_group == other.eco_group
             && self.w_bucket == other.w_bucket
             && self.b_bucket == other.b_bucket
+            && self.w_title == other.w_title
+            && self.b_title == other.b_title
     }
 }
 impl Hash for Key {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.month.hash(state);
+        self.speed.hash(state);
         self.eco_group.hash(state);
         self.w_bucket.hash(state);
         self.b_bucket.hash(state);
+        self.w_title.hash(state);
+        self.b_title.hash(state);
     }
 }
 
+/// Why a drawn game ended in a draw (see `replay::draw_reason`).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DrawReason {
+    Agreement,
+    Repetition,
+    Stalemate,
+    InsufficientMaterial,
+    FiftyMoves,
+    TimeoutInsufficient, // flagged, but the opponent could not mate
+    Unknown,             // the moves (or the FEN) could not be replayed
+}
+
+/// Castling and early-queen facts for one side, read from SAN tokens alone (see `pgn::move_style`).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SideStyle {
+    pub castled: Option<Castle>, // first castling move, if any
+    pub castle_ply: u32,         // ply of that move (1 = White's first move)
+    pub early_queen: bool,       // a queen move before ply 8
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Castle { Kingside, Queenside }
+
+/// Counter columns, in the order returned by `Counter::values()`.
+/// Names match the `aggregates` table and the CSV header.
+pub const COUNTER_COLUMNS: &[&str] = &[
+    "games",
+    "white_wins",
+    "black_wins",
+    "draws",
+    "unfinished",
+    "draws_agreement",
+    "draws_repetition",
+    "draws_stalemate",
+    "draws_insufficient",
+    "draws_fifty_moves",
+    "draws_timeout_insufficient",
+    "draws_unknown",
+    "white_castle_kingside",
+    "white_castle_queenside",
+    "white_castle_none",
+    "white_castle_ply_sum",
+    "white_early_queen",
+    "black_castle_kingside",
+    "black_castle_queenside",
+    "black_castle_none",
+    "black_castle_ply_sum",
+    "black_early_queen",
+    "white_rating_gained",
+    "white_rating_lost",
+    "white_rating_diff_games",
+    "black_rating_gained",
+    "black_rating_lost",
+    "black_rating_diff_games",
+    "white_elo_games",
+    "white_elo_sum",
+    "white_elo_sq_sum",
+    "black_elo_games",
+    "black_elo_sum",
+    "black_elo_sq_sum",
+];
+
+/// Game and result counts: the first columns of `aggregates` (inside
+/// `Counter`) and all of `endgames`.
 #[derive(Clone, Debug, Default)]
-pub struct Counter {
+pub struct Outcomes {
     pub games: u64,
     pub white_wins: u64,
     pub black_wins: u64,
     pub draws: u64,
+    pub unfinished: u64, // "*" or any other non-final result
 }
-impl Counter {
-    pub fn add_result(&mut self, result: &str) {
-        self.games += 1;
+impl Outcomes {
+    /// Unfinished games always count in `unfinished`; with `exclude_unfinished`
+    /// they stay out of `games`, so `games = white_wins + black_wins + draws`.
+    pub fn add_result(&mut self, result: &str, exclude_unfinished: bool) {
         match result {
             "1-0" => self.white_wins += 1,
             "0-1" => self.black_wins += 1,
             "1/2-1/2" => self.draws += 1,
-            _ => {}
+            _ => {
+                self.unfinished += 1;
+                if exclude_unfinished { return; }
+            }
+        }
+        self.games += 1;
+    }
+
+    pub fn merge(&mut self, other: &Outcomes) {
+        self.games += other.games;
+        self.white_wins += other.white_wins;
+        self.black_wins += other.black_wins;
+        self.draws += other.draws;
+        self.unfinished += other.unfinished;
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Counter {
+    pub outcomes: Outcomes,
+    // draw breakdown; only filled when `draw_reasons` is enabled
+    pub draws_agreement: u64,
+    pub draws_repetition: u64,
+    pub draws_stalemate: u64,
+    pub draws_insufficient: u64,
+    pub draws_fifty_moves: u64,
+    pub draws_timeout_insufficient: u64,
+    pub draws_unknown: u64,
+    // castling / early queen; only filled when `move_style` is enabled
+    pub white_castle_kingside: u64,
+    pub white_castle_queenside: u64,
+    pub white_castle_none: u64,
+    pub white_castle_ply_sum: u64, // mean ply = sum / (kingside + queenside)
+    pub white_early_queen: u64,    // games with a queen move before ply 8
+    pub black_castle_kingside: u64,
+    pub black_castle_queenside: u64,
+    pub black_castle_none: u64,
+    pub black_castle_ply_sum: u64,
+    pub black_early_queen: u64,
+    // WhiteRatingDiff / BlackRatingDiff: points gained and lost (as positive sums)
+    pub white_rating_gained: u64,
+    pub white_rating_lost: u64,
+    pub white_rating_diff_games: u64, // games with the header present
+    pub black_rating_gained: u64,
+    pub black_rating_lost: u64,
+    pub black_rating_diff_games: u64,
+    // Elo moments per side: mean = elo_sum / elo_games, variance = elo_sq_sum / elo_games - mean²
+    pub white_elo_games: u64, // games with a numeric WhiteElo
+    pub white_elo_sum: u64,
+    pub white_elo_sq_sum: u64,
+    pub black_elo_games: u64,
+    pub black_elo_sum: u64,
+    pub black_elo_sq_sum: u64,
+}
+impl Counter {
+    pub fn add_result(&mut self, result: &str, exclude_unfinished: bool) {
+        self.outcomes.add_result(result, exclude_unfinished);
+    }
+
+    pub fn add_draw_reason(&mut self, reason: DrawReason) {
+        match reason {
+            DrawReason::Agreement => self.draws_agreement += 1,
+            DrawReason::Repetition => self.draws_repetition += 1,
+            DrawReason::Stalemate => self.draws_stalemate += 1,
+            DrawReason::InsufficientMaterial => self.draws_insufficient += 1,
+            DrawReason::FiftyMoves => self.draws_fifty_moves += 1,
+            DrawReason::TimeoutInsufficient => self.draws_timeout_insufficient += 1,
+            DrawReason::Unknown => self.draws_unknown += 1,
+        }
+    }
+
+    pub fn add_move_style(&mut self, white: &SideStyle, black: &SideStyle) {
+        let tally = |s: &SideStyle, ks: &mut u64, qs: &mut u64, none: &mut u64, ply_sum: &mut u64, eq: &mut u64| {
+            match s.castled {
+                Some(Castle::Kingside) => *ks += 1,
+                Some(Castle::Queenside) => *qs += 1,
+                None => *none += 1,
+            }
+            if s.castled.is_some() { *ply_sum += s.castle_ply as u64; }
+            if s.early_queen { *eq += 1; }
+        };
+        tally(
+            white,
+            &mut self.white_castle_kingside,
+            &mut self.white_castle_queenside,
+            &mut self.white_castle_none,
+            &mut self.white_castle_ply_sum,
+            &mut self.white_early_queen,
+        );
+        tally(
+            black,
+            &mut self.black_castle_kingside,
+            &mut self.black_castle_queenside,
+            &mut self.black_castle_none,
+            &mut self.black_castle_ply_sum,
+            &mut self.black_early_queen,
+        );
+    }
+
+    pub fn add_rating_diffs(&mut self, white: Option<i32>, black: Option<i32>) {
+        if let Some(d) = white {
+            self.white_rating_diff_games += 1;
+            if d >= 0 { self.white_rating_gained += d as u64; } else { self.white_rating_lost += d.unsigned_abs() as u64; }
+        }
+        if let Some(d) = black {
+            self.black_rating_diff_games += 1;
+            if d >= 0 { self.black_rating_gained += d as u64; } else { self.black_rating_lost += d.unsigned_abs() as u64; }
+        }
+    }
+
+    pub fn add_elos(&mut self, white: Option<u16>, black: Option<u16>) {
+        if let Some(e) = white {
+            self.white_elo_games += 1;
+            self.white_elo_sum += e as u64;
+            self.white_elo_sq_sum += (e as u64) * (e as u64);
+        }
+        if let Some(e) = black {
+            self.black_elo_games += 1;
+            self.black_elo_sum += e as u64;
+            self.black_elo_sq_sum += (e as u64) * (e as u64);
+        }
+    }
+
+    pub fn merge(&mut self, other: &Counter) {
+        self.outcomes.merge(&other.outcomes);
+        self.draws_agreement += other.draws_agreement;
+        self.draws_repetition += other.draws_repetition;
+        self.draws_stalemate += other.draws_stalemate;
+        self.draws_insufficient += other.draws_insufficient;
+        self.draws_fifty_moves += other.draws_fifty_moves;
+        self.draws_timeout_insufficient += other.draws_timeout_insufficient;
+        self.draws_unknown += other.draws_unknown;
+        self.white_castle_kingside += other.white_castle_kingside;
+        self.white_castle_queenside += other.white_castle_queenside;
+        self.white_castle_none += other.white_castle_none;
+        self.white_castle_ply_sum += other.white_castle_ply_sum;
+        self.white_early_queen += other.white_early_queen;
+        self.black_castle_kingside += other.black_castle_kingside;
+        self.black_castle_queenside += other.black_castle_queenside;
+        self.black_castle_none += other.black_castle_none;
+        self.black_castle_ply_sum += other.black_castle_ply_sum;
+        self.black_early_queen += other.black_early_queen;
+        self.white_rating_gained += other.white_rating_gained;
+        self.white_rating_lost += other.white_rating_lost;
+        self.white_rating_diff_games += other.white_rating_diff_games;
+        self.black_rating_gained += other.black_rating_gained;
+        self.black_rating_lost += other.black_rating_lost;
+        self.black_rating_diff_games += other.black_rating_diff_games;
+        self.white_elo_games += other.white_elo_games;
+        self.white_elo_sum += other.white_elo_sum;
+        self.white_elo_sq_sum += other.white_elo_sq_sum;
+        self.black_elo_games += other.black_elo_games;
+        self.black_elo_sum += other.black_elo_sum;
+        self.black_elo_sq_sum += other.black_elo_sq_sum;
+    }
+
+    /// Values in `COUNTER_COLUMNS` order.
+    pub fn values(&self) -> Vec<u64> {
+        vec![
+            self.outcomes.games,
+            self.outcomes.white_wins,
+            self.outcomes.black_wins,
+            self.outcomes.draws,
+            self.outcomes.unfinished,
+            self.draws_agreement,
+            self.draws_repetition,
+            self.draws_stalemate,
+            self.draws_insufficient,
+            self.draws_fifty_moves,
+            self.draws_timeout_insufficient,
+            self.draws_unknown,
+            self.white_castle_kingside,
+            self.white_castle_queenside,
+            self.white_castle_none,
+            self.white_castle_ply_sum,
+            self.white_early_queen,
+            self.black_castle_kingside,
+            self.black_castle_queenside,
+            self.black_castle_none,
+            self.black_castle_ply_sum,
+            self.black_early_queen,
+            self.white_rating_gained,
+            self.white_rating_lost,
+            self.white_rating_diff_games,
+            self.black_rating_gained,
+            self.black_rating_lost,
+            self.black_rating_diff_games,
+            self.white_elo_games,
+            self.white_elo_sum,
+            self.white_elo_sq_sum,
+            self.black_elo_games,
+            self.black_elo_sum,
+            self.black_elo_sq_sum,
+        ]
+    }
+}
+
+/// Cell of the `endgames` table.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EndgameKey {
+    pub month: String,
+    pub speed: &'static str, // see `pgn::speed_from_headers`
+    pub w_bucket: u16,
+    pub b_bucket: u16,
+    pub signature: String,   // e.g., "KRPvKR"
+}
+
+/// Cell of the `rating_quantiles` table.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MonthSpeed {
+    pub month: String,
+    pub speed: &'static str,
+}
+
+/// Cell of the `player_sketches` table; "*" means all eco groups / speeds.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PlayerCell {
+    pub month: String,
+    pub eco_group: String,
+    pub speed: &'static str,
+}
+
+/// Quantiles stored per (month, speed), in `rating_quantiles` column order.
+pub const RATING_QUANTILES: &[(&str, f64)] = &[("p10", 0.10), ("p25", 0.25), ("p50", 0.50), ("p75", 0.75), ("p90", 0.90)];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcomes(results: &[&str], exclude_unfinished: bool) -> Outcomes {
+        let mut o = Outcomes::default();
+        for r in results {
+            o.add_result(r, exclude_unfinished);
+        }
+        o
+    }
+
+    #[test]
+    fn unfinished_games_count_in_games_by_default() {
+        let o = outcomes(&["1-0", "0-1", "1/2-1/2", "*", "?"], false);
+        assert_eq!((o.games, o.white_wins, o.black_wins, o.draws, o.unfinished), (5, 1, 1, 1, 2));
+        assert_eq!(o.games, o.white_wins + o.black_wins + o.draws + o.unfinished);
+    }
+
+    #[test]
+    fn exclude_unfinished_keeps_them_out_of_games() {
+        let o = outcomes(&["1-0", "0-1", "1/2-1/2", "*", "?"], true);
+        assert_eq!((o.games, o.white_wins, o.black_wins, o.draws, o.unfinished), (3, 1, 1, 1, 2));
+        assert_eq!(o.games, o.white_wins + o.black_wins + o.draws);
+    }
+
+    #[test]
+    fn elo_moments_count_only_numeric_elos() {
+        let mut c = Counter::default();
+        c.add_elos(Some(1500), None);
+        c.add_elos(Some(1700), Some(2000));
+        assert_eq!((c.white_elo_games, c.white_elo_sum, c.white_elo_sq_sum), (2, 3200, 1500 * 1500 + 1700 * 1700));
+        assert_eq!((c.black_elo_games, c.black_elo_sum, c.black_elo_sq_sum), (1, 2000, 2000 * 2000));
+        // mean and variance divide by the side's elo_games, not by games
+        let mean = c.white_elo_sum as f64 / c.white_elo_games as f64;
+        assert_eq!(mean, 1600.0);
+        assert_eq!(c.white_elo_sq_sum as f64 / c.white_elo_games as f64 - mean * mean, 10_000.0);
+    }
+
+    #[test]
+    fn merge_adds_every_column() {
+        let mut a = Counter::default();
+        a.add_result("1-0", false);
+        a.add_elos(Some(1500), Some(1400));
+        a.add_rating_diffs(Some(7), Some(-7));
+        let mut b = Counter::default();
+        b.add_result("1/2-1/2", false);
+        b.add_draw_reason(DrawReason::Repetition);
+        b.add_elos(None, Some(1600));
+
+        let mut merged = a.clone();
+        merged.merge(&b);
+        let (va, vb, vm) = (a.values(), b.values(), merged.values());
+        assert_eq!(vm.len(), COUNTER_COLUMNS.len());
+        for (i, col) in COUNTER_COLUMNS.iter().enumerate() {
+            assert_eq!(vm[i], va[i] + vb[i], "{}", col);
         }
+        assert_eq!((merged.black_elo_games, merged.black_elo_sum), (2, 3000));
     }
 }
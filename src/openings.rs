@@ -0,0 +1,202 @@
+// src/openings.rs
+// Opening book (ECO → move sequence) for classifying games by their moves
+// when the ECO header is missing or not trusted. The built-in book covers the
+// main line of every group of the built-in ECO taxonomy; for full coverage point
+// `opening_book` at the lichess chess-openings TSVs (eco<TAB>name<TAB>pgn),
+// e.g. the directory holding its `a.tsv`…`e.tsv`. Lines are move sequences from
+// the standard start, so games set up from a `FEN` are never classified here.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::Context;
+
+use crate::manifest;
+use crate::pgn::movetext_sans;
+
+static BUILTIN_TSV: &str = include_str!("../openings/book.tsv");
+static BOOK: OnceLock<Book> = OnceLock::new();
+
+/// Trie over SAN moves; a node carries the ECO of the line ending there.
+#[derive(Default)]
+pub struct Book {
+    nodes: Vec<Node>,
+    sha256: String, // of the TSV, recorded in run manifests
+}
+
+#[derive(Default)]
+struct Node {
+    children: HashMap<String, usize>,
+    eco: Option<String>,
+}
+
+fn norm_san(san: &str) -> &str {
+    san.trim_end_matches(['+', '#', '!', '?'])
+}
+
+impl Book {
+    /// Parse `eco<TAB>name<TAB>pgn` lines; a header line starting with "eco" is skipped.
+    pub fn from_tsv(tsv: &str) -> anyhow::Result<Book> {
+        let mut book = Book { nodes: vec![Node::default()], sha256: manifest::sha256_hex(tsv.as_bytes()) };
+        book.add_tsv(tsv)?;
+        Ok(book)
+    }
+
+    /// One book from several TSVs (e.g. the lichess `a.tsv`…`e.tsv`); its
+    /// SHA-256 is that of the files concatenated in order.
+    pub fn from_files(files: &[PathBuf]) -> anyhow::Result<Book> {
+        let mut book = Book { nodes: vec![Node::default()], sha256: String::new() };
+        let mut all = Vec::new();
+        for file in files {
+            let tsv = fs::read_to_string(file).with_context(|| format!("reading opening book {}", file.display()))?;
+            book.add_tsv(&tsv).with_context(|| format!("opening book {}", file.display()))?;
+            all.extend_from_slice(tsv.as_bytes());
+        }
+        book.sha256 = manifest::sha256_hex(&all);
+        Ok(book)
+    }
+
+    fn add_tsv(&mut self, tsv: &str) -> anyhow::Result<()> {
+        for (i, line) in tsv.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with("eco\t") { continue; }
+            let mut cols = line.split('\t');
+            let (Some(eco), Some(_name), Some(pgn)) = (cols.next(), cols.next(), cols.next()) else {
+                anyhow::bail!("opening book line {}: expected eco, name, pgn", i + 1);
+            };
+            self.insert(eco, &movetext_sans(&[pgn.to_string()]));
+        }
+        Ok(())
+    }
+
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    fn insert(&mut self, eco: &str, sans: &[String]) {
+        let mut at = 0;
+        for san in sans {
+            let san = norm_san(san);
+            at = match self.nodes[at].children.get(san) {
+                Some(&next) => next,
+                None => {
+                    self.nodes.push(Node::default());
+                    let next = self.nodes.len() - 1;
+                    self.nodes[at].children.insert(san.to_string(), next);
+                    next
+                }
+            };
+        }
+        self.nodes[at].eco = Some(eco.to_string());
+    }
+
+    /// ECO of the longest book line the game follows.
+    pub fn classify(&self, sans: &[String]) -> Option<&str> {
+        let mut at = 0;
+        let mut best = None;
+        for san in sans {
+            match self.nodes[at].children.get(norm_san(san)) {
+                Some(&next) => at = next,
+                None => break,
+            }
+            if let Some(eco) = self.nodes[at].eco.as_deref() {
+                best = Some(eco);
+            }
+        }
+        best
+    }
+}
+
+/// The TSV files of `opening_book`: files as given, directories as their
+/// `*.tsv` files in name order.
+fn book_files(paths: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        if !path.is_dir() {
+            files.push(path.to_path_buf());
+            continue;
+        }
+        let mut tsvs: Vec<PathBuf> = fs::read_dir(path)
+            .with_context(|| format!("reading opening book directory {}", path.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        tsvs.retain(|p| p.extension().is_some_and(|ext| ext == "tsv"));
+        tsvs.sort();
+        anyhow::ensure!(!tsvs.is_empty(), "opening book directory {} has no .tsv files", path.display());
+        files.extend(tsvs);
+    }
+    Ok(files)
+}
+
+/// Load the book from the `opening_book` paths (once, before processing);
+/// without a call, or with no paths, the built-in book is used.
+pub fn init(paths: &[String]) -> anyhow::Result<()> {
+    let book = if paths.is_empty() { Book::from_tsv(BUILTIN_TSV)? } else { Book::from_files(&book_files(paths)?)? };
+    vprintln!("openings: book loaded ({} nodes)", book.nodes.len());
+    let _ = BOOK.set(book);
+    Ok(())
+}
+
+pub fn book() -> &'static Book {
+    BOOK.get_or_init(|| Book::from_tsv(BUILTIN_TSV).expect("built-in opening book is valid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSV: &str = "eco\tname\tpgn\n\
+        C20\tKing's Pawn Game\t1. e4 e5\n\
+        C40\tKing's Knight Opening\t1. e4 e5 2. Nf3\n\
+        C50\tItalian Game\t1. e4 e5 2. Nf3 Nc6 3. Bc4\n";
+
+    fn sans(movetext: &str) -> Vec<String> {
+        movetext_sans(&[movetext.to_string()])
+    }
+
+    #[test]
+    fn classify_takes_longest_prefix() {
+        let book = Book::from_tsv(TSV).unwrap();
+        assert_eq!(book.classify(&sans("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. c3")), Some("C50"));
+        assert_eq!(book.classify(&sans("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6")), Some("C40"));
+        assert_eq!(book.classify(&sans("1. e4 e5 2. d4")), Some("C20"));
+    }
+
+    #[test]
+    fn classify_misses_transpositions_and_unknown_lines() {
+        let book = Book::from_tsv(TSV).unwrap();
+        // same position as C40, reached by another move order
+        assert_eq!(book.classify(&sans("1. Nf3 e5 2. e4")), None);
+        assert_eq!(book.classify(&sans("1. d4 d5")), None);
+        // a book line's prefix carries no ECO of its own
+        assert_eq!(book.classify(&sans("1. e4")), None);
+        assert_eq!(book.classify(&[]), None);
+    }
+
+    #[test]
+    fn directory_loads_its_tsvs_in_name_order() {
+        let dir = std::env::temp_dir().join(format!("lta-openings-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (head, tail) = TSV.split_at(TSV.find("C50").unwrap());
+        fs::write(dir.join("a.tsv"), head).unwrap();
+        fs::write(dir.join("b.tsv"), format!("eco\tname\tpgn\n{}", tail)).unwrap();
+        fs::write(dir.join("notes.txt"), "not a book").unwrap();
+
+        let files = book_files(&[dir.display().to_string()]).unwrap();
+        assert_eq!(files, vec![dir.join("a.tsv"), dir.join("b.tsv")]);
+        let book = Book::from_files(&files).unwrap();
+        assert_eq!(book.classify(&sans("1. e4 e5 2. Nf3 Nc6 3. Bc4")), Some("C50"));
+        assert_eq!(book.classify(&sans("1. e4 e5 2. Nf3 d6")), Some("C40"));
+
+        // a single file hashes as its text, like the built-in book
+        let single = Book::from_files(&[dir.join("a.tsv")]).unwrap();
+        assert_eq!(single.sha256, Book::from_tsv(head).unwrap().sha256);
+
+        let empty = dir.join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(book_files(&[empty.display().to_string()]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
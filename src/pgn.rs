@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use crate::model::{Castle, SideStyle};
+
 /// Parse PGN headers (Tag -> Value).
 pub fn parse_headers(game_lines: &[String]) -> HashMap<String, String> {
     let mut map = HashMap::new();
@@ -55,6 +57,21 @@ pub fn parse_elo(s: Option<&String>) -> Option<u16> {
     s.and_then(|x| x.parse::<u16>().ok())
 }
 
+/// Title class of a `WhiteTitle` / `BlackTitle` value: "bot" for BOT, "titled"
+/// for any other title (GM, IM, FM, LM, ...), "none" when absent.
+pub fn title_class(title: Option<&String>) -> &'static str {
+    match title.map(|t| t.trim()) {
+        Some("BOT") => "bot",
+        Some(t) if !t.is_empty() && t != "-" => "titled",
+        _ => "none",
+    }
+}
+
+/// Signed rating change ("+7", "-12") from a `WhiteRatingDiff` / `BlackRatingDiff` tag.
+pub fn parse_rating_diff(s: Option<&String>) -> Option<i32> {
+    s.and_then(|x| x.parse::<i32>().ok())
+}
+
 /// Bucket ELO with an arbitrary bucket size (e.g., 200).
 pub fn elo_bucket_with_size(elo: Option<u16>, size: u16) -> u16 {
     match elo {
@@ -62,3 +79,287 @@ pub fn elo_bucket_with_size(elo: Option<u16>, size: u16) -> u16 {
         _ => 0,
     }
 }
+
+/// Bucket ELO by custom lower bounds (ascending, starting at 0): the last edge <= elo.
+pub fn elo_bucket_with_edges(elo: Option<u16>, edges: &[u16]) -> u16 {
+    match elo {
+        Some(e) => edges.iter().rev().find(|&&edge| edge <= e).copied().unwrap_or(0),
+        None => 0,
+    }
+}
+
+/// Rating bucket layout: `bucket_size` points wide, or custom `bucket_edges`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Buckets {
+    Every(u16),
+    Edges(Vec<u16>),
+}
+
+impl Buckets {
+    pub fn bucket(&self, elo: Option<u16>) -> u16 {
+        match self {
+            Buckets::Every(size) => elo_bucket_with_size(elo, *size),
+            Buckets::Edges(edges) => elo_bucket_with_edges(elo, edges),
+        }
+    }
+
+    /// Custom edges must be strictly ascending and start at 0 (the bucket of games without Elo).
+    pub fn edges(edges: Vec<u16>) -> anyhow::Result<Buckets> {
+        anyhow::ensure!(edges.first() == Some(&0), "bucket_edges must start at 0");
+        anyhow::ensure!(edges.windows(2).all(|w| w[0] < w[1]), "bucket_edges must be strictly ascending");
+        Ok(Buckets::Edges(edges))
+    }
+
+    /// Inverse of `Display` ("every 200", "edges 0,1200,1800").
+    pub fn parse(s: &str) -> Option<Buckets> {
+        if let Some(size) = s.strip_prefix("every ") {
+            return size.parse().ok().map(Buckets::Every);
+        }
+        let edges = s.strip_prefix("edges ")?.split(',').map(|e| e.parse().ok()).collect::<Option<Vec<u16>>>()?;
+        Buckets::edges(edges).ok()
+    }
+
+    /// Whether `value` is the lower bound of a bucket.
+    pub fn is_edge(&self, value: u16) -> bool {
+        match self {
+            Buckets::Every(size) => value.is_multiple_of(*size),
+            Buckets::Edges(edges) => edges.contains(&value),
+        }
+    }
+
+    /// Whether every bucket of `old` falls inside one bucket of `self`, i.e.
+    /// each edge of `self` is an edge of `old` (so counts can be summed up).
+    pub fn aligns_with(&self, old: &Buckets) -> bool {
+        match (self, old) {
+            (Buckets::Every(0), _) => true,
+            // infinitely many edges: only a divisor width has them all
+            (Buckets::Every(size), Buckets::Every(old_size)) => size.is_multiple_of(*old_size),
+            (Buckets::Every(_), Buckets::Edges(_)) => false,
+            (Buckets::Edges(edges), _) => edges.iter().all(|&e| old.is_edge(e)),
+        }
+    }
+
+    /// SQL expression mapping the bucket column `col` of an aligned layout to this one.
+    pub fn sql(&self, col: &str) -> String {
+        match self {
+            Buckets::Every(0) => "0".to_string(),
+            Buckets::Every(size) => format!("({col} - {col} % {size})"),
+            Buckets::Edges(edges) => {
+                let whens: Vec<String> =
+                    edges.iter().rev().map(|e| format!("WHEN {col} >= {e} THEN {e}")).collect();
+                format!("(CASE {} ELSE 0 END)", whens.join(" "))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Buckets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Buckets::Every(size) => write!(f, "every {}", size),
+            Buckets::Edges(edges) => {
+                let edges: Vec<String> = edges.iter().map(u16::to_string).collect();
+                write!(f, "edges {}", edges.join(","))
+            }
+        }
+    }
+}
+
+/// SAN tokens of the main line, in order. Drops comments `{...}`, variations `(...)`,
+/// NAGs (`$1`), move numbers (`12.`, `12...`) and the trailing result.
+pub fn movetext_sans(game_lines: &[String]) -> Vec<String> {
+    let mut sans = Vec::new();
+    let mut in_comment = false;
+    let mut depth = 0usize; // variation nesting
+
+    for line in game_lines {
+        let line = line.trim();
+        if !in_comment && line.starts_with('[') { continue; }
+        if !in_comment && line.starts_with('%') { continue; } // escape line
+
+        let mut token = String::new();
+        let mut flush = |token: &mut String, depth: usize| {
+            if depth == 0 && !token.is_empty() && is_san_token(token) {
+                sans.push(std::mem::take(token));
+            }
+            token.clear();
+        };
+
+        for ch in line.chars() {
+            if in_comment {
+                if ch == '}' { in_comment = false; }
+                continue;
+            }
+            match ch {
+                '{' => { flush(&mut token, depth); in_comment = true; }
+                ';' => { flush(&mut token, depth); break; } // rest-of-line comment
+                '(' => { flush(&mut token, depth); depth += 1; }
+                ')' => { flush(&mut token, depth); depth = depth.saturating_sub(1); }
+                c if c.is_whitespace() => flush(&mut token, depth),
+                '.' => {
+                    // "12." / "12..." are move numbers; "12.e4" has no space
+                    if token.chars().all(|c| c.is_ascii_digit()) { token.clear(); } else { token.push(ch); }
+                }
+                _ => token.push(ch),
+            }
+        }
+        flush(&mut token, depth);
+    }
+    sans
+}
+
+fn is_san_token(t: &str) -> bool {
+    !(t.starts_with('$')
+        || t.chars().all(|c| c.is_ascii_digit())
+        || matches!(t, "1-0" | "0-1" | "1/2-1/2" | "*"))
+}
+
+/// Every value `speed_from_headers` can return.
+pub const SPEEDS: &[&str] = &["ultrabullet", "bullet", "blitz", "rapid", "classical", "correspondence", "unknown"];
+
+/// Lichess speed category from `TimeControl` ("300+3"): estimated duration is
+/// base + 40 × increment seconds. "-" is correspondence; anything else "unknown".
+pub fn speed_from_headers(h: &HashMap<String, String>) -> &'static str {
+    let tc = match h.get("TimeControl") {
+        Some(tc) => tc.as_str(),
+        None => return "unknown",
+    };
+    if tc == "-" { return "correspondence"; }
+    let (base, inc) = tc.split_once('+').unwrap_or((tc, "0"));
+    match (base.parse::<u32>(), inc.parse::<u32>()) {
+        (Ok(base), Ok(inc)) => match base + 40 * inc {
+            0..=29 => "ultrabullet",
+            30..=179 => "bullet",
+            180..=479 => "blitz",
+            480..=1499 => "rapid",
+            _ => "classical",
+        },
+        _ => "unknown",
+    }
+}
+
+/// (white, black) move style. Ply numbering follows the side to move in the
+/// `FEN` tag when the game starts from a custom position.
+pub fn move_style(h: &HashMap<String, String>, sans: &[String]) -> (SideStyle, SideStyle) {
+    let black_first = h.get("SetUp").map(String::as_str) == Some("1")
+        && h.get("FEN").and_then(|f| f.split_whitespace().nth(1)) == Some("b");
+    let mut sides = [SideStyle::default(), SideStyle::default()];
+
+    for (i, san) in sans.iter().enumerate() {
+        let ply = i as u32 + 1 + black_first as u32;
+        let side = &mut sides[((ply + 1) % 2) as usize]; // odd ply → White
+        if san.starts_with('Q') && ply < 8 {
+            side.early_queen = true;
+        }
+        if side.castled.is_none() {
+            let castle = match san.trim_end_matches(['+', '#', '!', '?']) {
+                "O-O" | "0-0" => Some(Castle::Kingside),
+                "O-O-O" | "0-0-0" => Some(Castle::Queenside),
+                _ => None,
+            };
+            if castle.is_some() {
+                side.castled = castle;
+                side.castle_ply = ply;
+            }
+        }
+    }
+    (sides[0], sides[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sans(movetext: &str) -> Vec<String> {
+        movetext_sans(&[movetext.to_string()])
+    }
+
+    #[test]
+    fn title_classes() {
+        let class = |t: &str| title_class(Some(&t.to_string()));
+        assert_eq!(class("BOT"), "bot");
+        assert_eq!(class("GM"), "titled");
+        assert_eq!(class(" LM "), "titled");
+        assert_eq!(class("-"), "none");
+        assert_eq!(class(""), "none");
+        assert_eq!(title_class(None), "none");
+    }
+
+    #[test]
+    fn castling_side_and_ply() {
+        let game = sans("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. O-O d6 5. d3 Bg4 6. Nc3 Qd7 7. a3 O-O-O");
+        let (white, black) = move_style(&HashMap::new(), &game);
+        assert_eq!((white.castled, white.castle_ply), (Some(Castle::Kingside), 7));
+        assert_eq!((black.castled, black.castle_ply), (Some(Castle::Queenside), 14));
+        assert!(!white.early_queen && !black.early_queen); // 6... Qd7 is ply 12
+    }
+
+    #[test]
+    fn no_castling() {
+        let (white, black) = move_style(&HashMap::new(), &sans("1. d4 d5 2. c4 e6 3. Nc3 Nf6 1/2-1/2"));
+        assert_eq!((white.castled, white.castle_ply), (None, 0));
+        assert_eq!((black.castled, black.castle_ply), (None, 0));
+    }
+
+    #[test]
+    fn early_queen_is_before_ply_8() {
+        // 4. Qe2 is ply 7, 4... Qe7 ply 8
+        let (white, black) = move_style(&HashMap::new(), &sans("1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Qe2 Qe7"));
+        assert!(white.early_queen);
+        assert!(!black.early_queen);
+
+        // from a FEN with Black to move, Black's first move is ply 2
+        let h: HashMap<String, String> = [("SetUp", "1"), ("FEN", "4k3/8/8/8/8/8/8/3QK2q b - - 0 1")]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .into();
+        let (white, black) = move_style(&h, &sans("1... Qh5 2. Qd2"));
+        assert!(black.early_queen);
+        assert!(white.early_queen);
+    }
+
+    fn edges(e: &[u16]) -> Buckets {
+        Buckets::edges(e.to_vec()).unwrap()
+    }
+
+    /// Summing the old buckets into the new ones gives each game the bucket it
+    /// would have had under the new layout.
+    fn assert_rebuckets(new: &Buckets, old: &Buckets) {
+        assert!(new.aligns_with(old), "{} should align with {}", new, old);
+        for elo in (0..3200).step_by(7) {
+            let summed = new.bucket(Some(old.bucket(Some(elo))));
+            assert_eq!(summed, new.bucket(Some(elo)), "elo {} ({} -> {})", elo, old, new);
+        }
+    }
+
+    #[test]
+    fn coarser_layouts_align() {
+        assert_rebuckets(&Buckets::Every(200), &Buckets::Every(100));
+        assert_rebuckets(&Buckets::Every(400), &Buckets::Every(200));
+        assert_rebuckets(&Buckets::Every(0), &Buckets::Every(100));
+        assert_rebuckets(&edges(&[0, 1200, 1800, 2200]), &Buckets::Every(200));
+        assert_rebuckets(&edges(&[0, 1800]), &edges(&[0, 1200, 1800, 2200]));
+        assert_rebuckets(&Buckets::Every(200), &Buckets::Every(200));
+    }
+
+    #[test]
+    fn split_buckets_are_refused() {
+        // a new edge inside a stored bucket would split it
+        assert!(!Buckets::Every(100).aligns_with(&Buckets::Every(200)));
+        assert!(!Buckets::Every(300).aligns_with(&Buckets::Every(200)));
+        assert!(!edges(&[0, 1250]).aligns_with(&Buckets::Every(100)));
+        assert!(!edges(&[0, 1500]).aligns_with(&edges(&[0, 1200, 1800])));
+        // fixed widths have edges past every custom one
+        assert!(!Buckets::Every(200).aligns_with(&edges(&[0, 1200, 1800])));
+        assert!(!Buckets::Every(100).aligns_with(&Buckets::Every(0)));
+    }
+
+    #[test]
+    fn bad_edges_are_refused() {
+        assert!(Buckets::edges(vec![1000, 1500]).is_err());
+        assert!(Buckets::edges(vec![0, 1500, 1500]).is_err());
+        assert!(Buckets::edges(vec![0, 1800, 1200]).is_err());
+        assert_eq!(Buckets::parse("edges 0,1200,1800"), Some(edges(&[0, 1200, 1800])));
+        assert_eq!(Buckets::parse("every 200"), Some(Buckets::Every(200)));
+        assert_eq!(Buckets::parse("edges 1200,1800"), None);
+    }
+}
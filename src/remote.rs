@@ -1,13 +1,15 @@
-use std::io::BufReader;
+use std::collections::HashSet;
+use std::io::{self, BufReader};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 use regex::Regex;
 use tokio::task;
 
-use crate::aggregator::{aggregate_from_reader, write_csv, AggMap};
+use crate::aggregator::{aggregate_from_reader, write_csvs, Aggregation};
 use crate::config::Config;
 use crate::db;
+use crate::local::HashingReader;
 
 // ---- Types ----
 
@@ -41,7 +43,7 @@ fn parse_list_to_oldest(list_txt: &str) -> Vec<PlanItem> {
 fn norm_month(s: &str) -> Option<String> {
     // Accept "YYYY-MM", "YYYY-M", "YYYY/MM", "YYYY.MM"
     let s = s.trim();
-    let parts: Vec<&str> = s.split(|c| c == '-' || c == '/' || c == '.').collect();
+    let parts: Vec<&str> = s.split(['-', '/', '.']).collect();
     if parts.len() < 2 { return None; }
     let y = parts[0];
     let m = parts[1];
@@ -70,12 +72,13 @@ async fn fetch_list(list_url: &str) -> anyhow::Result<String> {
 
 // ---- Plans ----
 
-/// Build ingest plan using DB (skips already-success months).
+/// Build ingest plan using DB (skips already-success months, except those in `redo`).
 pub async fn build_plan(
     dbh: &crate::db::Db,
     list_url: &str,
     since: Option<&str>,
     until: Option<&str>,
+    redo: &HashSet<String>,
 ) -> anyhow::Result<Vec<PlanItem>> {
     let text = fetch_list(list_url).await?;
     let mut items = parse_list_to_oldest(&text);
@@ -105,7 +108,7 @@ pub async fn build_plan(
     let t1 = Instant::now();
     let done = db::already_ingested_months(dbh).await?;
     let before = items.len();
-    items.retain(|it| !done.contains(&it.month));
+    items.retain(|it| !done.contains(&it.month) || redo.contains(&it.month));
     vprintln!(
         "remote: filtered already-ingested -> {} items (was {}), query took {:.3}s",
         items.len(), before, t1.elapsed().as_secs_f64()
@@ -150,17 +153,17 @@ pub async fn plan_no_db(
 // ---- Streaming + aggregation ----
 
 /// Stream one monthly .zst over HTTP, aggregate, optionally write CSV.
-/// Returns (aggregate map, total games, elapsed_ms).
+/// Returns (aggregation, total games, elapsed_ms, SHA-256 of the .zst).
 pub async fn stream_and_aggregate_async(
     url: &str,
     out_csv: Option<&Path>,
     cfg: &Config,
-) -> anyhow::Result<(AggMap, usize, u128)> {
+) -> anyhow::Result<(Aggregation, usize, u128, String)> {
     let url_owned = url.to_string();
     let out_opt: Option<PathBuf> = out_csv.map(|p| p.to_path_buf());
     let cfg_cloned = cfg.clone();
 
-    let (map, games, elapsed_ms) = task::spawn_blocking(move || -> anyhow::Result<(AggMap, usize, u128)> {
+    let (map, games, elapsed_ms, sha256) = task::spawn_blocking(move || -> anyhow::Result<(Aggregation, usize, u128, String)> {
         let start = Instant::now();
 
         vprintln!("remote: HTTP GET {}", url_owned);
@@ -169,25 +172,33 @@ pub async fn stream_and_aggregate_async(
         vprintln!("remote: HTTP connected in {:.3}s", t_net.elapsed().as_secs_f64());
 
         let t_dec = Instant::now();
-        let decoder = zstd::stream::Decoder::new(resp)?;
+        let decoder = zstd::stream::Decoder::with_buffer(HashingReader::new(BufReader::new(resp)))?;
         vprintln!("remote: zstd decoder ready in {:.3}s", t_dec.elapsed().as_secs_f64());
 
-        let reader = BufReader::new(decoder);
+        let mut reader = BufReader::new(decoder);
         vprintln!("remote: aggregation start");
-        let (map, total_games) = aggregate_from_reader(reader, &cfg_cloned)?;
+        let (map, total_games) = aggregate_from_reader(&mut reader, &cfg_cloned)?;
         vprintln!("remote: aggregation done; games={}", total_games);
 
+        // the hash covers the whole file: read what the decoder left (nothing
+        // for a single-frame file) unless a preview limit stopped early
+        let hashing = reader.get_mut().get_mut();
+        if !map.truncated {
+            io::copy(hashing, &mut io::sink())?;
+        }
+        let sha256 = hashing.hex_digest();
+
         if let Some(csv_path) = out_opt.as_ref() {
             let t_csv = Instant::now();
             vprintln!("remote: writing CSV to {}", csv_path.display());
-            write_csv(&map, csv_path)?;
+            write_csvs(&map, csv_path, &cfg_cloned)?;
             vprintln!("remote: CSV written in {:.3}s", t_csv.elapsed().as_secs_f64());
         }
 
         let dur = start.elapsed().as_millis();
-        Ok((map, total_games, dur))
+        Ok((map, total_games, dur, sha256))
     })
     .await??;
 
-    Ok((map, games, elapsed_ms))
+    Ok((map, games, elapsed_ms, sha256))
 }
\ No newline at end of file
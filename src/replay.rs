@@ -0,0 +1,220 @@
+// src/replay.rs
+// Optional move replay (shakmaty) for analyses that need the actual position,
+// not just headers. Replay is much slower than header parsing, so every caller
+// is behind a config switch.
+
+use std::collections::HashMap;
+
+use shakmaty::fen::Fen;
+use shakmaty::san::San;
+use shakmaty::zobrist::Zobrist64;
+use shakmaty::{ByRole, CastlingMode, Chess, Color, EnPassantMode, Position};
+
+use crate::model::DrawReason;
+use crate::pgn::movetext_sans;
+
+/// Starting position: the `FEN` tag when `SetUp` is "1", else the standard one.
+fn start_position(h: &HashMap<String, String>) -> Option<Chess> {
+    match (h.get("SetUp").map(String::as_str), h.get("FEN")) {
+        (Some("1"), Some(fen)) => {
+            let fen: Fen = fen.parse().ok()?;
+            fen.into_position(CastlingMode::Standard).ok()
+        }
+        _ => Some(Chess::default()),
+    }
+}
+
+/// Replay the main line, calling `visit` with every position (start included)
+/// until it returns false. Returns the last visited position, or None if the
+/// start or any move before that is illegal.
+pub fn replay_with<F: FnMut(&Chess) -> bool>(
+    h: &HashMap<String, String>,
+    game_lines: &[String],
+    mut visit: F,
+) -> Option<Chess> {
+    let mut pos = start_position(h)?;
+    if !visit(&pos) { return Some(pos); }
+    for tok in movetext_sans(game_lines) {
+        let san_txt = tok.trim_end_matches(['+', '#', '!', '?']);
+        let san = San::from_ascii(san_txt.as_bytes()).ok()?;
+        let m = san.to_move(&pos).ok()?;
+        pos.play_unchecked(m);
+        if !visit(&pos) { break; }
+    }
+    Some(pos)
+}
+
+/// How a drawn game ended, from its last position and how often each
+/// position (Zobrist hash) occurred.
+fn classify_draw(last: &Chess, seen: &HashMap<u64, u32>) -> DrawReason {
+    let z: Zobrist64 = last.zobrist_hash(EnPassantMode::Legal);
+    if last.is_stalemate() {
+        DrawReason::Stalemate
+    } else if last.is_insufficient_material() {
+        DrawReason::InsufficientMaterial
+    } else if seen.get(&z.0).copied().unwrap_or(0) >= 3 {
+        DrawReason::Repetition
+    } else if last.halfmoves() >= 100 {
+        DrawReason::FiftyMoves
+    } else {
+        DrawReason::Agreement
+    }
+}
+
+/// Non-pawn material of both sides (N/B = 3, R = 5, Q = 9).
+fn piece_material(pos: &Chess) -> u16 {
+    let m = pos.board().material();
+    [m.white, m.black]
+        .iter()
+        .map(|r| 3 * (r.knight + r.bishop) as u16 + 5 * r.rook as u16 + 9 * r.queen as u16)
+        .sum()
+}
+
+/// "KRPvKR": white pieces, "v", black pieces, each strongest first.
+fn material_signature(pos: &Chess) -> String {
+    let side = |r: ByRole<u8>| {
+        let mut s = String::from("K");
+        for (ch, n) in [('Q', r.queen), ('R', r.rook), ('B', r.bishop), ('N', r.knight), ('P', r.pawn)] {
+            for _ in 0..n { s.push(ch); }
+        }
+        s
+    };
+    let board = pos.board();
+    format!("{}v{}", side(board.material_side(Color::White)), side(board.material_side(Color::Black)))
+}
+
+/// What one replay of a game yields; a field is None unless asked for.
+#[derive(Debug, Default, PartialEq)]
+pub struct Replayed {
+    /// Why a drawn game was drawn; `Unknown` when the moves can't be replayed,
+    /// so the reasons always add up to `draws`.
+    pub draw_reason: Option<DrawReason>,
+    /// Signature of the first position whose non-pawn material is at most the
+    /// limit; None if the game never gets there or can't be replayed up to it.
+    pub endgame: Option<String>,
+}
+
+/// Replay a game once for every analysis asked for: the draw reason (`draw`,
+/// for a "1/2-1/2" game) and the endgame signature (`endgame_max_material`).
+/// Nothing is replayed when neither needs the moves.
+pub fn analyze(
+    h: &HashMap<String, String>,
+    game_lines: &[String],
+    draw: bool,
+    endgame_max_material: Option<u16>,
+) -> Replayed {
+    // Lichess records a flag against a lone king as a draw with this termination.
+    let timeout = draw && h.get("Termination").map(String::as_str) == Some("Time forfeit");
+    let track_draw = draw && !timeout;
+    let mut out = Replayed {
+        draw_reason: timeout.then_some(DrawReason::TimeoutInsufficient),
+        endgame: None,
+    };
+    if !track_draw && endgame_max_material.is_none() { return out; }
+
+    let mut seen: HashMap<u64, u32> = HashMap::new();
+    let last = replay_with(h, game_lines, |pos| {
+        if let Some(max) = endgame_max_material.filter(|_| out.endgame.is_none()) {
+            if piece_material(pos) <= max { out.endgame = Some(material_signature(pos)); }
+        }
+        if track_draw {
+            let z: Zobrist64 = pos.zobrist_hash(EnPassantMode::Legal);
+            *seen.entry(z.0).or_default() += 1;
+        }
+        // a draw needs the last position; an endgame only the first match
+        track_draw || (endgame_max_material.is_some() && out.endgame.is_none())
+    });
+    if track_draw {
+        out.draw_reason = Some(last.map_or(DrawReason::Unknown, |last| classify_draw(&last, &seen)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Headers (`FEN` sets up the start position) and one movetext line.
+    fn game(fen: Option<&str>, movetext: &str) -> (HashMap<String, String>, Vec<String>) {
+        let mut h = HashMap::new();
+        if let Some(fen) = fen {
+            h.insert("SetUp".to_string(), "1".to_string());
+            h.insert("FEN".to_string(), fen.to_string());
+        }
+        (h, vec![movetext.to_string()])
+    }
+
+    fn reason(fen: Option<&str>, movetext: &str) -> DrawReason {
+        let (h, lines) = game(fen, movetext);
+        draw_reason(&h, &lines)
+    }
+
+    fn draw_reason(h: &HashMap<String, String>, lines: &[String]) -> DrawReason {
+        analyze(h, lines, true, None).draw_reason.unwrap()
+    }
+
+    fn endgame_signature(h: &HashMap<String, String>, lines: &[String], max_material: u16) -> Option<String> {
+        analyze(h, lines, false, Some(max_material)).endgame
+    }
+
+    #[test]
+    fn stalemate() {
+        assert_eq!(reason(Some("7k/8/6K1/8/8/8/8/5Q2 w - - 0 1"), "1. Qf7 1/2-1/2"), DrawReason::Stalemate);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        assert_eq!(
+            reason(Some("8/8/8/8/8/3k4/8/Kn6 w - - 0 1"), "1. Kxb1 1/2-1/2"),
+            DrawReason::InsufficientMaterial
+        );
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        assert_eq!(
+            reason(None, "1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3 Nf6 4. Ng1 Ng8 1/2-1/2"),
+            DrawReason::Repetition
+        );
+        // twice is not enough
+        assert_eq!(reason(None, "1. Nf3 Nf6 2. Ng1 Ng8 1/2-1/2"), DrawReason::Agreement);
+    }
+
+    #[test]
+    fn fifty_moves() {
+        assert_eq!(reason(Some("8/8/8/8/8/3k4/8/K6R w - - 99 80"), "80. Rh2 1/2-1/2"), DrawReason::FiftyMoves);
+        assert_eq!(reason(Some("8/8/8/8/8/3k4/8/K6R w - - 98 80"), "80. Rh2 1/2-1/2"), DrawReason::Agreement);
+    }
+
+    #[test]
+    fn timeout_and_unreplayable() {
+        let (mut h, lines) = game(None, "1. e4 e5 1/2-1/2");
+        h.insert("Termination".to_string(), "Time forfeit".to_string());
+        assert_eq!(draw_reason(&h, &lines), DrawReason::TimeoutInsufficient);
+        assert_eq!(reason(None, "1. e5 1/2-1/2"), DrawReason::Unknown);
+        assert_eq!(reason(Some("not a fen"), "1. e4 1/2-1/2"), DrawReason::Unknown);
+    }
+
+    #[test]
+    fn endgame_signature_at_threshold() {
+        let (h, lines) = game(Some("4k3/8/8/8/8/8/3q4/R3K2R w - - 0 1"), "1. Kxd2 1/2-1/2");
+        assert_eq!(endgame_signature(&h, &lines, 13), Some("KRRvK".to_string()));
+        assert_eq!(endgame_signature(&h, &lines, 9), None);
+    }
+
+    #[test]
+    fn one_replay_gives_both() {
+        // reaches KRRvK at move 1, then is agreed drawn
+        let (h, lines) = game(Some("1k6/8/8/8/8/8/3q4/R3K2R w - - 0 1"), "1. Kxd2 Kc7 2. Rh2 1/2-1/2");
+        let both = analyze(&h, &lines, true, Some(13));
+        assert_eq!(both.endgame.as_deref(), Some("KRRvK"));
+        assert_eq!(both.draw_reason, Some(DrawReason::Agreement));
+        assert_eq!(analyze(&h, &lines, false, None), Replayed::default());
+
+        // an illegal move after the endgame keeps the signature
+        let (h, lines) = game(Some("1k6/8/8/8/8/8/3q4/R3K2R w - - 0 1"), "1. Kxd2 Kc7 2. Qd4 1/2-1/2");
+        let both = analyze(&h, &lines, true, Some(13));
+        assert_eq!(both.endgame.as_deref(), Some("KRRvK"));
+        assert_eq!(both.draw_reason, Some(DrawReason::Unknown));
+    }
+}
@@ -0,0 +1,459 @@
+// src/sketch.rs
+// Mergeable summaries built next to the aggregates inside the Rayon fold/reduce.
+
+use std::collections::HashMap;
+
+/// Rating distribution as a sparse histogram with 1-point resolution. Ratings
+/// are small integers, so this is exact, bounded by the rating range (a few
+/// thousand bins at most) and merges by adding counts.
+#[derive(Clone, Debug, Default)]
+pub struct EloHistogram {
+    bins: HashMap<u16, u64>,
+    count: u64,
+}
+
+impl EloHistogram {
+    pub fn add(&mut self, elo: u16) {
+        self.add_count(elo, 1);
+    }
+
+    pub fn add_count(&mut self, elo: u16, n: u64) {
+        *self.bins.entry(elo).or_default() += n;
+        self.count += n;
+    }
+
+    /// (elo, count) of every non-empty bin, in no particular order.
+    pub fn bins(&self) -> impl Iterator<Item = (u16, u64)> + '_ {
+        self.bins.iter().map(|(&elo, &n)| (elo, n))
+    }
+
+    pub fn merge(&mut self, other: &EloHistogram) {
+        for (&elo, &n) in &other.bins {
+            *self.bins.entry(elo).or_default() += n;
+        }
+        self.count += other.count;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Nearest-rank quantiles for each `q` in [0, 1] (same order as `qs`).
+    pub fn quantiles(&self, qs: &[f64]) -> Vec<u16> {
+        let mut sorted: Vec<(u16, u64)> = self.bins.iter().map(|(&e, &n)| (e, n)).collect();
+        sorted.sort_unstable_by_key(|&(e, _)| e);
+        qs.iter()
+            .map(|&q| {
+                let rank = ((q * self.count as f64).ceil() as u64).max(1);
+                let mut seen = 0;
+                for &(elo, n) in &sorted {
+                    seen += n;
+                    if seen >= rank { return elo; }
+                }
+                sorted.last().map(|&(e, _)| e).unwrap_or(0)
+            })
+            .collect()
+    }
+}
+
+/// 64-bit hash that is stable across builds and platforms (FNV-1a, then the
+/// MurmurHash3 finalizer for avalanche). Persisted sketches depend on it.
+pub fn stable_hash64(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+/// HyperLogLog distinct counter with 2^p one-byte registers (relative error
+/// ≈ 1.04 / sqrt(2^p)). Sketches with the same precision merge by register max.
+#[derive(Clone, Debug)]
+pub struct Hll {
+    p: u8,
+    regs: Vec<u8>,
+}
+
+impl Hll {
+    pub const MIN_PRECISION: u8 = 4;
+    pub const MAX_PRECISION: u8 = 14; // hex-encoded registers must fit a MySQL TEXT
+
+    pub fn new(p: u8) -> Self {
+        let p = p.clamp(Self::MIN_PRECISION, Self::MAX_PRECISION);
+        Self { p, regs: vec![0; 1 << p] }
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.p
+    }
+
+    pub fn add_hash(&mut self, h: u64) {
+        let idx = (h >> (64 - self.p)) as usize;
+        let rest = h << self.p;
+        let rank = (rest.leading_zeros() as u8 + 1).min(64 - self.p + 1);
+        if rank > self.regs[idx] {
+            self.regs[idx] = rank;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Hll) {
+        debug_assert_eq!(self.p, other.p);
+        for (a, &b) in self.regs.iter_mut().zip(&other.regs) {
+            if b > *a { *a = b; }
+        }
+    }
+
+    pub fn estimate(&self) -> f64 {
+        let m = self.regs.len() as f64;
+        let alpha = match self.regs.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.regs.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.regs.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln() // linear counting for small cardinalities
+        } else {
+            raw
+        }
+    }
+
+    pub fn to_hex(&self) -> String {
+        let mut s = String::with_capacity(self.regs.len() * 2);
+        for r in &self.regs {
+            s.push_str(&format!("{:02x}", r));
+        }
+        s
+    }
+
+    pub fn from_hex(p: u8, hex: &str) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            (Self::MIN_PRECISION..=Self::MAX_PRECISION).contains(&p) && hex.len() == 2 << p,
+            "bad HLL sketch (precision {}, {} hex chars)", p, hex.len()
+        );
+        let regs = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?;
+        Ok(Self { p, regs })
+    }
+}
+
+/// Space-Saving top-k counter with batched eviction: it tracks up to
+/// 2 × `capacity` items and, when full, keeps the `capacity` largest. Counts
+/// are upper bounds and `count - error` lower bounds. Summaries merge.
+#[derive(Clone, Debug)]
+pub struct TopK {
+    capacity: usize,
+    items: HashMap<String, (u64, u64)>, // item -> (count, error)
+    floor: u64,                         // largest count evicted so far
+}
+
+impl TopK {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), items: HashMap::new(), floor: 0 }
+    }
+
+    /// A summary stored as its (item, count, error) rows. Once it holds
+    /// `capacity` items, an untracked item can have had up to the smallest
+    /// stored count, which becomes the floor.
+    pub fn from_summary(capacity: usize, items: impl IntoIterator<Item = (String, u64, u64)>) -> Self {
+        let mut top = Self::new(capacity);
+        top.items = items.into_iter().map(|(item, count, err)| (item, (count, err))).collect();
+        if top.items.len() >= top.capacity {
+            top.floor = top.items.values().map(|&(count, _)| count).min().unwrap_or(0);
+        }
+        top.prune_if_full();
+        top
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn add(&mut self, item: &str) {
+        match self.items.get_mut(item) {
+            Some((count, _)) => *count += 1,
+            None => {
+                // an untracked item may have been evicted with up to `floor` occurrences
+                self.items.insert(item.to_string(), (self.floor + 1, self.floor));
+                self.prune_if_full();
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &TopK) {
+        for (item, (count, err)) in self.items.iter_mut() {
+            if !other.items.contains_key(item) {
+                *count += other.floor;
+                *err += other.floor;
+            }
+        }
+        for (item, &(count, err)) in &other.items {
+            let e = self.items.entry(item.clone()).or_insert((self.floor, self.floor));
+            e.0 += count;
+            e.1 += err;
+        }
+        self.floor += other.floor;
+        self.prune_if_full();
+    }
+
+    /// Take out a summary merged in earlier (a saved input being replaced).
+    /// Its lower bounds come off the counts, so counts stay upper bounds, and
+    /// the errors grow by what `other` can't pin down.
+    pub fn subtract(&mut self, other: &TopK) {
+        self.items.retain(|item, (count, err)| {
+            let (gone, unsure) = match other.items.get(item) {
+                Some(&(c, e)) => (c - e, e),
+                None => (0, other.floor),
+            };
+            *count = count.saturating_sub(gone);
+            *err = (*err + unsure).min(*count);
+            *count > 0
+        });
+    }
+
+    fn prune_if_full(&mut self) {
+        if self.items.len() < 2 * self.capacity { return; }
+        let mut all: Vec<(String, (u64, u64))> = self.items.drain().collect();
+        all.sort_unstable_by_key(|(_, (count, _))| std::cmp::Reverse(*count));
+        for (_, (count, _)) in all.drain(self.capacity..) {
+            self.floor = self.floor.max(count);
+        }
+        self.items = all.into_iter().collect();
+    }
+
+    /// The `k` largest items as (item, count, error), largest first.
+    pub fn top(&self, k: usize) -> Vec<(&str, u64, u64)> {
+        let mut all: Vec<(&str, u64, u64)> =
+            self.items.iter().map(|(i, &(c, e))| (i.as_str(), c, e)).collect();
+        all.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        all.truncate(k);
+        all
+    }
+}
+
+/// Bloom filter sized for `capacity` items at false-positive rate `fp_rate`
+/// (k hash functions derived from one 64-bit hash by double hashing).
+#[derive(Clone, Debug)]
+pub struct Bloom {
+    k: u32,
+    bits: Vec<u64>,
+}
+
+impl Bloom {
+    pub fn new(capacity: u64, fp_rate: f64) -> Self {
+        let n = capacity.max(1) as f64;
+        let p = fp_rate.clamp(1e-9, 0.5);
+        let m = (-n * p.ln() / (std::f64::consts::LN_2 * std::f64::consts::LN_2)).ceil().max(64.0);
+        let k = ((m / n) * std::f64::consts::LN_2).round().clamp(1.0, 30.0) as u32;
+        Self { k, bits: vec![0; (m as usize).div_ceil(64)] }
+    }
+
+    pub fn from_parts(k: u32, bits: Vec<u64>) -> Self {
+        Self { k, bits }
+    }
+
+    pub fn k(&self) -> u32 {
+        self.k
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.bits
+    }
+
+    /// Set the item's bits; true if they were all set already (seen, or a false positive).
+    pub fn check_and_insert(&mut self, h: u64) -> bool {
+        let m = self.bits.len() as u64 * 64;
+        let h2 = stable_hash64(&h.to_le_bytes()) | 1;
+        let mut present = true;
+        for i in 0..self.k as u64 {
+            let bit = h.wrapping_add(i.wrapping_mul(h2)) % m;
+            let (w, mask) = ((bit / 64) as usize, 1u64 << (bit % 64));
+            if self.bits[w] & mask == 0 {
+                present = false;
+                self.bits[w] |= mask;
+            }
+        }
+        present
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantiles_of_empty_histogram_are_zero() {
+        assert_eq!(EloHistogram::default().quantiles(&[0.1, 0.5, 0.9]), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn quantiles_of_single_value() {
+        let mut h = EloHistogram::default();
+        h.add(1500);
+        assert_eq!(h.quantiles(&[0.0, 0.1, 0.5, 0.9, 1.0]), vec![1500; 5]);
+    }
+
+    #[test]
+    fn quantiles_use_nearest_rank() {
+        // 1000, 1010, ..., 1990: 100 ratings, the k-th smallest is 1000 + 10 * (k - 1)
+        let mut h = EloHistogram::default();
+        for i in 0..100 {
+            h.add(1000 + 10 * i);
+        }
+        assert_eq!(h.quantiles(&[0.1, 0.5, 0.9]), vec![1090, 1490, 1890]);
+
+        // merging by counts gives the same as adding one by one
+        let mut skewed = EloHistogram::default();
+        skewed.add_count(1200, 8);
+        let mut other = EloHistogram::default();
+        other.add_count(2000, 2);
+        skewed.merge(&other);
+        assert_eq!(skewed.count(), 10);
+        assert_eq!(skewed.quantiles(&[0.1, 0.5, 0.8, 0.9]), vec![1200, 1200, 1200, 2000]);
+    }
+
+    fn hll_of(p: u8, ids: std::ops::Range<u64>) -> Hll {
+        let mut hll = Hll::new(p);
+        for i in ids {
+            hll.add_hash(stable_hash64(&i.to_le_bytes()));
+        }
+        hll
+    }
+
+    fn assert_close(estimate: f64, actual: f64, rel: f64) {
+        assert!((estimate - actual).abs() <= rel * actual, "estimate {estimate:.0}, actual {actual}");
+    }
+
+    #[test]
+    fn hll_estimate_is_within_error() {
+        // three standard errors of 1.04 / sqrt(2^12) ≈ 1.6%
+        assert_close(hll_of(12, 0..100_000).estimate(), 100_000.0, 0.05);
+        // small cardinalities go through linear counting
+        assert_close(hll_of(12, 0..200).estimate(), 200.0, 0.05);
+        assert_eq!(hll_of(12, 0..0).estimate(), 0.0);
+    }
+
+    #[test]
+    fn hll_union_equals_sketch_of_union() {
+        let mut a = hll_of(10, 0..60_000);
+        let b = hll_of(10, 40_000..100_000);
+        a.merge(&b);
+        assert_eq!(a.to_hex(), hll_of(10, 0..100_000).to_hex());
+        // duplicates across the two sketches are counted once
+        assert_close(a.estimate(), 100_000.0, 0.1);
+
+        let round_trip = Hll::from_hex(10, &a.to_hex()).unwrap();
+        assert_eq!(round_trip.to_hex(), a.to_hex());
+        assert!(Hll::from_hex(11, &a.to_hex()).is_err());
+    }
+
+    /// Heavy items "h0".."h4" (500, 400, ... occurrences) among 3000 items seen once or twice.
+    fn stream() -> Vec<String> {
+        let mut items = Vec::new();
+        for i in 0..3000u64 {
+            items.push(format!("n{i}"));
+            if i % 3 == 0 {
+                items.push(format!("n{i}"));
+            }
+            let h = i / 6;
+            if i % 6 == 0 && h < 500 {
+                for j in 0..5u64 {
+                    if h < 500 - 100 * j {
+                        items.push(format!("h{j}"));
+                    }
+                }
+            }
+        }
+        items
+    }
+
+    fn check_bounds(top: &TopK, truth: &HashMap<String, u64>) {
+        for (item, count, err) in top.top(usize::MAX) {
+            let actual = truth[item];
+            assert!(count - err <= actual && actual <= count, "{item}: {actual} outside [{}, {count}]", count - err);
+        }
+    }
+
+    #[test]
+    fn topk_counts_bound_true_frequencies() {
+        let items = stream();
+        let mut truth: HashMap<String, u64> = HashMap::new();
+        for item in &items {
+            *truth.entry(item.clone()).or_default() += 1;
+        }
+
+        let mut whole = TopK::new(10);
+        items.iter().for_each(|i| whole.add(i));
+        check_bounds(&whole, &truth);
+
+        let (left, right) = items.split_at(items.len() / 3);
+        let (mut a, mut b) = (TopK::new(10), TopK::new(10));
+        left.iter().for_each(|i| a.add(i));
+        right.iter().for_each(|i| b.add(i));
+        a.merge(&b);
+        check_bounds(&a, &truth);
+
+        let ranked: Vec<&str> = a.top(5).into_iter().map(|(item, _, _)| item).collect();
+        assert_eq!(ranked, ["h0", "h1", "h2", "h3", "h4"]);
+    }
+
+    #[test]
+    fn topk_stored_summaries_keep_bounds() {
+        let items = stream();
+        let mut truth: HashMap<String, u64> = HashMap::new();
+        for item in &items {
+            *truth.entry(item.clone()).or_default() += 1;
+        }
+
+        // as a save does: load the stored rows, merge this pass, store `capacity` rows
+        let mut stored: Vec<(String, u64, u64)> = Vec::new();
+        for part in items.chunks(items.len() / 4 + 1) {
+            let mut pass = TopK::new(10);
+            part.iter().for_each(|i| pass.add(i));
+            let mut summary = TopK::from_summary(10, stored);
+            summary.merge(&pass);
+            stored = summary.top(10).into_iter().map(|(i, c, e)| (i.to_string(), c, e)).collect();
+        }
+        assert_eq!(stored.len(), 10);
+        check_bounds(&TopK::from_summary(10, stored.clone()), &truth);
+        let ranked: Vec<&str> = stored.iter().take(5).map(|(item, _, _)| item.as_str()).collect();
+        assert_eq!(ranked, ["h0", "h1", "h2", "h3", "h4"]);
+    }
+
+    #[test]
+    fn topk_subtract_keeps_bounds_of_the_rest() {
+        let items = stream();
+        let (kept, removed) = items.split_at(items.len() / 2);
+        let mut truth: HashMap<String, u64> = HashMap::new();
+        for item in kept {
+            *truth.entry(item.clone()).or_default() += 1;
+        }
+        for item in removed {
+            truth.entry(item.clone()).or_default();
+        }
+
+        let summary = |part: &[String]| {
+            let mut top = TopK::new(50);
+            part.iter().for_each(|i| top.add(i));
+            TopK::from_summary(50, top.top(50).into_iter().map(|(i, c, e)| (i.to_string(), c, e)))
+        };
+        let mut both = summary(kept);
+        both.merge(&summary(removed));
+        both.subtract(&summary(removed));
+        check_bounds(&both, &truth);
+        // h0..h2 played 223 times in the kept half, h3 200 times
+        let mut ranked: Vec<&str> = both.top(4).into_iter().map(|(item, _, _)| item).collect();
+        ranked.sort_unstable();
+        assert_eq!(ranked, ["h0", "h1", "h2", "h3"]);
+    }
+}